@@ -3,7 +3,8 @@ use deluxe::ExtractAttributes;
 use proc_macro::{self, TokenStream};
 use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, Ident, Type};
 
 use crate::synerror;
 
@@ -33,6 +34,170 @@ struct EndpointRowAttributes {
     raw: bool,
 }
 
+#[derive(ExtractAttributes, Default)]
+#[deluxe(attributes(api_schema), default)]
+struct SchemaAttributes {
+    input: bool,
+}
+
+#[derive(ExtractAttributes, Default)]
+#[deluxe(attributes(api_schema), default)]
+struct SchemaFieldAttributes {
+    schema: Option<Expr>,
+    value_type: Option<Type>,
+}
+
+/// A `#[serde(rename_all = "...")]` rule, applied the same way serde applies it.
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(rule: &syn::LitStr) -> syn::Result<Self> {
+        Ok(match rule.value().as_str() {
+            "lowercase" => Self::Lower,
+            "UPPERCASE" => Self::Upper,
+            "PascalCase" => Self::Pascal,
+            "camelCase" => Self::Camel,
+            "snake_case" => Self::Snake,
+            "SCREAMING_SNAKE_CASE" => Self::ScreamingSnake,
+            "kebab-case" => Self::Kebab,
+            "SCREAMING-KEBAB-CASE" => Self::ScreamingKebab,
+            _ => return Err(syn::Error::new(rule.span(), "unknown `rename_all` rule")),
+        })
+    }
+
+    /// Rename a field, whose name is expected to be in snake case.
+    fn apply_to_field(self, field: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field.to_owned(),
+            Self::Upper | Self::ScreamingSnake => field.to_ascii_uppercase(),
+            Self::Pascal => field
+                .split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                        None => String::new(),
+                    }
+                })
+                .collect(),
+            Self::Camel => {
+                let pascal = Self::Pascal.apply_to_field(field);
+                pascal[..1].to_ascii_lowercase() + &pascal[1..]
+            }
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+
+    /// Rename an enum variant, whose name is expected to be in Pascal case.
+    fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            Self::Pascal => variant.to_owned(),
+            Self::Lower => variant.to_ascii_lowercase(),
+            Self::Upper => variant.to_ascii_uppercase(),
+            Self::Camel => variant[..1].to_ascii_lowercase() + &variant[1..],
+            Self::Snake => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                snake
+            }
+            Self::ScreamingSnake => Self::Snake.apply_to_variant(variant).to_ascii_uppercase(),
+            Self::Kebab => Self::Snake.apply_to_variant(variant).replace('_', "-"),
+            Self::ScreamingKebab => Self::ScreamingSnake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+}
+
+/// The `#[serde(...)]` options of a struct or enum which change the shape of its JSON.
+#[derive(Default)]
+struct SerdeContainerOptions {
+    rename_all: Option<RenameRule>,
+}
+
+impl SerdeContainerOptions {
+    fn parse(attributes: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attribute in attributes.iter().filter(|a| a.path().is_ident("serde")) {
+            attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename_all") {
+                    let rule = meta.value()?.parse::<syn::LitStr>()?;
+                    options.rename_all = Some(RenameRule::parse(&rule)?);
+                } else if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<Expr>()?;
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// The `#[serde(...)]` options of a field or variant which change the shape of its JSON.
+#[derive(Default)]
+struct SerdeFieldOptions {
+    rename: Option<String>,
+    flatten: bool,
+    default: bool,
+    skip: bool,
+}
+
+impl SerdeFieldOptions {
+    fn parse(attributes: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attribute in attributes.iter().filter(|a| a.path().is_ident("serde")) {
+            attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    options.rename = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+                } else if meta.path.is_ident("flatten") {
+                    options.flatten = true;
+                } else if meta.path.is_ident("default") {
+                    options.default = true;
+                } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing") {
+                    options.skip = true;
+                }
+
+                // * Any other options (and their values) do not change the schema.
+                if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<Expr>()?;
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Check whether a type is an `Option`, which input fields can be left out of.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
 enum ColumnFormatPreset {
     Id,
     String { trimmable: bool },
@@ -96,7 +261,7 @@ pub fn derive_process_endpoint(input: TokenStream) -> TokenStream {
         )
     };
 
-    let fields: Vec<(String, Ident, ColumnFormatAttributes)> = {
+    let fields: Vec<(String, Ident, ColumnFormatAttributes, Type)> = {
         let Fields::Named(_) = &data_struct.fields else {
             synerror!(
                 type_name,
@@ -131,13 +296,15 @@ pub fn derive_process_endpoint(input: TokenStream) -> TokenStream {
                 });
             }
 
-            fields.push((field_name, field_ident, format_attributes));
+            fields.push((field_name, field_ident, format_attributes, field.ty));
         }
 
         fields
     };
 
     let columns: Vec<Ident> = fields.iter().map(|f| f.1.clone()).collect();
+    let column_names: Vec<String> = fields.iter().map(|f| f.0.clone()).collect();
+    let column_types: Vec<Type> = fields.iter().map(|f| f.3.clone()).collect();
     let column_formats: Vec<TokenStream2> = fields
         .iter()
         .map(|f| {
@@ -191,7 +358,7 @@ pub fn derive_process_endpoint(input: TokenStream) -> TokenStream {
             
             let (display_type, trimmable_or_tag_options) = match (column_format.trimmable, column_format.tag_options) {
                 (Some(trimmable), None) => (quote!(crate::api::endpoints::FrontendColumnDisplay::Text), quote!(trimmable: #trimmable)),
-                (None, Some(tag_options)) => (quote!(crate::api::endpoints::FrontendColumnDisplay::Tag), quote!(options: #tag_options)),
+                (None, Some(tag_options)) => (quote!(crate::api::endpoints::FrontendColumnDisplay::Tag), quote!(options: std::borrow::Cow::Borrowed(#tag_options))),
                 _ => synerror!(
                     column_ident,
                     "either `trimmable` or `tag_options` must be specified in `#[col_format(...)]` attribute"
@@ -248,6 +415,49 @@ pub fn derive_process_endpoint(input: TokenStream) -> TokenStream {
                 }
             }
         }
+
+        impl EndpointMetadata {
+            /// Get the metadata with its display names and tag labels translated.
+            fn localized(localization: &crate::api::localization::Localization) -> Self {
+                let metadata = Self::new();
+                Self {
+                    #(
+                        #columns: metadata.#columns.localized(localization),
+                    )*
+                }
+            }
+
+            fn describe_columns(&self) -> serde_json::Value {
+                serde_json::to_value(vec![
+                    #(
+                        crate::api::endpoints::ColumnDescription::new(#column_names, &self.#columns),
+                    )*
+                ])
+                .unwrap()
+            }
+        }
+
+        impl crate::api::openapi::ApiSchema for EndpointMetadata {
+            fn schema() -> serde_json::Value {
+                crate::api::openapi::metadata_schema(
+                    &[#(#column_names),*],
+                    serde_json::to_value(Self::new()).unwrap(),
+                )
+            }
+        }
+
+        impl crate::api::openapi::ApiSchema for #type_name {
+            fn schema() -> serde_json::Value {
+                crate::api::openapi::object_schema(
+                    vec![
+                        #(
+                            (#column_names, <#column_types as crate::api::openapi::ApiSchema>::schema()),
+                        )*
+                    ],
+                    &[#(#column_names),*],
+                )
+            }
+        }
     }
     .into()
 }
@@ -277,19 +487,66 @@ pub fn derive_from_relation(input: TokenStream) -> TokenStream {
 
     let optional_metadata_assignment = match raw {
         true => None,
-        false => Some(quote!(metadata: EndpointMetadata::new(),)),
+        false => Some(quote!(metadata: EndpointMetadata::localized(localization),)),
+    };
+
+    let optional_metadata_schema = match raw {
+        true => None,
+        false => Some(quote! {
+            ("metadata", <EndpointMetadata as crate::api::openapi::ApiSchema>::schema()),
+        }),
+    };
+    // * Raw resources have no metadata to describe.
+    let optional_describe_resource = match raw {
+        true => None,
+        false => Some(quote! {
+            impl crate::api::DescribeResource for #type_name {
+                fn describe_columns(
+                    localization: &crate::api::localization::Localization,
+                ) -> serde_json::Value {
+                    EndpointMetadata::localized(localization).describe_columns()
+                }
+            }
+        }),
     };
 
     quote! {
+        #optional_describe_resource
+
+        impl crate::api::openapi::ApiSchema for #type_name {
+            fn schema() -> serde_json::Value {
+                crate::api::openapi::object_schema(
+                    vec![
+                        #optional_metadata_schema
+                        (
+                            "records",
+                            crate::api::openapi::array_schema(
+                                <#row_type_name as crate::api::openapi::ApiSchema>::schema(),
+                            ),
+                        ),
+                    ],
+                    &["records"],
+                )
+            }
+        }
+
         impl crate::api::FromRelation for #type_name {
             type Relation = #relation_type_name;
-            fn from_relation(relation: Self::Relation) -> Self {
+            fn from_relation(
+                relation: Self::Relation,
+                localization: &crate::api::localization::Localization,
+            ) -> Self {
                 Self {
                     #optional_metadata_assignment
                     records: relation
                         .take_records()
                         .into_iter()
-                        .map(<#row_type_name as crate::api::FromRecord>::from_record)
+                        .map(|record| {
+                            <#row_type_name as crate::api::FromRecord>::from_record(
+                                record,
+                                localization,
+                            )
+                        })
                         .collect(),
                 }
             }
@@ -321,7 +578,7 @@ pub fn derive_from_record(input: TokenStream) -> TokenStream {
         )
     };
 
-    // let fields: Vec<(String, Ident)> = {
+    let column_types: Vec<Type> = data_struct.fields.iter().map(|f| f.ty.clone()).collect();
     let columns: Vec<Ident> = {
         let Fields::Named(_) = &data_struct.fields else {
             synerror!(
@@ -339,11 +596,30 @@ pub fn derive_from_record(input: TokenStream) -> TokenStream {
         fields
     };
 
+    let column_names: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+
+    // * Processed records get their schema from the `ProcessEndpoint` derive instead.
     if raw {
         quote! {
+            impl crate::api::openapi::ApiSchema for #type_name {
+                fn schema() -> serde_json::Value {
+                    crate::api::openapi::object_schema(
+                        vec![
+                            #(
+                                (#column_names, <#column_types as crate::api::openapi::ApiSchema>::schema()),
+                            )*
+                        ],
+                        &[#(#column_names),*],
+                    )
+                }
+            }
+
             impl crate::api::FromRecord for #type_name {
                 type Record = #record_type_name;
-                fn from_record(record: Self::Record) -> Self {
+                fn from_record(
+                    record: Self::Record,
+                    _localization: &crate::api::localization::Localization,
+                ) -> Self {
                     #type_name {
                         #(
                             #columns: record.#columns,
@@ -356,7 +632,10 @@ pub fn derive_from_record(input: TokenStream) -> TokenStream {
         quote! {
             impl crate::api::FromRecord for #type_name {
                 type Record = #record_type_name;
-                fn from_record(record: Self::Record) -> Self {
+                fn from_record(
+                    record: Self::Record,
+                    localization: &crate::api::localization::Localization,
+                ) -> Self {
                     let formatting = EndpointFormatting::new();
                     let #record_type_name {
                         #(
@@ -366,7 +645,7 @@ pub fn derive_from_record(input: TokenStream) -> TokenStream {
 
                     #type_name {
                         #(
-                            #columns: ViewCell::new(#columns, &formatting.#columns),
+                            #columns: ViewCell::new(#columns, &formatting.#columns, localization),
                         )*
                     }
                 }
@@ -451,8 +730,11 @@ pub fn derive_id_parameter(input: TokenStream) -> TokenStream {
     let first_field = fields.named.into_iter().next();
     if let Some(first_field) = first_field {
         let first_field_name = first_field.ident.unwrap();
+        let parameter_name = first_field_name.to_string();
         quote! {
             impl crate::api::IdParameter for #type_name {
+                const NAME: &'static str = #parameter_name;
+
                 fn new(#first_field_name: usize) -> Self {
                     Self { #first_field_name }
                 }
@@ -470,3 +752,135 @@ pub fn derive_id_parameter(input: TokenStream) -> TokenStream {
         )
     }
 }
+
+pub fn derive_api_schema(input: TokenStream) -> TokenStream {
+    let mut input: DeriveInput = parse_macro_input!(input);
+    let type_name = input.ident.clone();
+
+    let Ok(SchemaAttributes { input: is_input }) = deluxe::extract_attributes(&mut input) else {
+        synerror!(
+            type_name,
+            "invalid `#[api_schema(...)]` attribute, expected `#[api_schema(input)]`"
+        )
+    };
+
+    let container_options = match SerdeContainerOptions::parse(&input.attrs) {
+        Ok(options) => options,
+        Err(e) => return e.into_compile_error().into(),
+    };
+
+    let data_struct = match input.data {
+        Data::Struct(data_struct) => data_struct,
+        Data::Enum(data_enum) => {
+            let mut variant_names = Vec::new();
+            for variant in data_enum.variants {
+                let variant_ident = variant.ident;
+                let Fields::Unit = variant.fields else {
+                    synerror!(
+                        variant_ident,
+                        "cannot derive `ApiSchema` for enums with non-unit variants"
+                    )
+                };
+                let serde_options = match SerdeFieldOptions::parse(&variant.attrs) {
+                    Ok(options) => options,
+                    Err(e) => return e.into_compile_error().into(),
+                };
+                if serde_options.skip {
+                    continue;
+                }
+
+                let variant_name = variant_ident.unraw().to_string();
+                variant_names.push(serde_options.rename.unwrap_or_else(|| {
+                    match container_options.rename_all {
+                        Some(rule) => rule.apply_to_variant(&variant_name),
+                        None => variant_name,
+                    }
+                }));
+            }
+
+            return quote! {
+                impl crate::api::openapi::ApiSchema for #type_name {
+                    fn schema() -> serde_json::Value {
+                        crate::api::openapi::string_enum_schema(&[#(#variant_names),*])
+                    }
+                }
+            }
+            .into();
+        }
+        Data::Union(_) => synerror!(type_name, "cannot derive `ApiSchema` for unions"),
+    };
+    let Fields::Named(_) = &data_struct.fields else {
+        synerror!(
+            type_name,
+            "cannot derive `ApiSchema` for unit or tuple structs"
+        )
+    };
+
+    let mut schema_fields = Vec::new();
+    for mut field in data_struct.fields.into_iter() {
+        let field_ident = field.ident.clone().unwrap();
+        let Ok(SchemaFieldAttributes { schema, value_type }) =
+            deluxe::extract_attributes(&mut field)
+        else {
+            synerror!(
+                field_ident,
+                "invalid `#[api_schema(...)]` attribute, expected `schema = ...` or `value_type = ...`"
+            )
+        };
+        let serde_options = match SerdeFieldOptions::parse(&field.attrs) {
+            Ok(options) => options,
+            Err(e) => return e.into_compile_error().into(),
+        };
+        if serde_options.skip {
+            continue;
+        }
+
+        let field_type = field.ty;
+        let schema = match (schema, value_type) {
+            (Some(schema), None) => quote!(#schema),
+            (None, Some(value_type)) => {
+                quote!(<#value_type as crate::api::openapi::ApiSchema>::schema())
+            }
+            (None, None) => quote!(<#field_type as crate::api::openapi::ApiSchema>::schema()),
+            (Some(_), Some(_)) => synerror!(
+                field_ident,
+                "only one of `schema` and `value_type` can be given in `#[api_schema(...)]`"
+            ),
+        };
+
+        if serde_options.flatten {
+            schema_fields.push(quote!(crate::api::openapi::SchemaField::Flattened(#schema)));
+            continue;
+        }
+
+        let field_name = field_ident.unraw().to_string();
+        let name = serde_options
+            .rename
+            .unwrap_or_else(|| match container_options.rename_all {
+                Some(rule) => rule.apply_to_field(&field_name),
+                None => field_name,
+            });
+        // * Outputs always include every field, while inputs can leave out any field that
+        // * deserializes to a default value when it is missing.
+        let required = !is_input || !(serde_options.default || is_option(&field_type));
+        schema_fields.push(quote! {
+            crate::api::openapi::SchemaField::Property {
+                name: #name,
+                schema: #schema,
+                required: #required,
+            }
+        });
+    }
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics crate::api::openapi::ApiSchema for #type_name #type_generics #where_clause {
+            fn schema() -> serde_json::Value {
+                crate::api::openapi::struct_schema(vec![
+                    #(#schema_fields,)*
+                ])
+            }
+        }
+    }
+    .into()
+}
@@ -11,6 +11,8 @@ struct RelationAttributes {
     schema_name: Option<String>,
     relation_name: String,
     primary_key: String,
+    serial_primary_key: Option<bool>,
+    location_filter: Option<String>,
 }
 
 #[derive(ExtractAttributes)]
@@ -30,6 +32,8 @@ pub fn derive_relation(input: TokenStream) -> TokenStream {
         schema_name,
         relation_name,
         primary_key,
+        serial_primary_key,
+        location_filter,
     }) = deluxe::extract_attributes(&mut input)
     else {
         synerror!(
@@ -44,10 +48,24 @@ pub fn derive_relation(input: TokenStream) -> TokenStream {
         }
     });
 
+    let optional_serial_primary_key_definition = serial_primary_key.map(|serial_primary_key| {
+        quote! {
+            const SERIAL_PRIMARY_KEY: bool = #serial_primary_key;
+        }
+    });
+
+    let optional_location_filter_definition = location_filter.map(|location_filter| {
+        quote! {
+            const LOCATION_FILTER: Option<&str> = Some(#location_filter);
+        }
+    });
+
     quote! {
         impl crate::database::Relation for #type_name {
             type Record = #record_type_name;
             #optional_schema_definition
+            #optional_serial_primary_key_definition
+            #optional_location_filter_definition
             const RELATION_NAME: &str = #relation_name;
             const PRIMARY_KEY: &str = #primary_key;
 
@@ -152,35 +170,68 @@ pub fn derive_single_insert(input: TokenStream) -> TokenStream {
 
     let mut column_names = Vec::new();
     let mut binding_statements = Vec::new();
+    let mut copy_statements = Vec::new();
+    let mut defaultable_idents = Vec::new();
     for (column_name, column_ident, defaultable) in fields {
         let binding_or_default = match defaultable {
             true => {
                 quote! {
-                    match record.#column_ident {
+                    match &record.#column_ident {
                         Some(column_value) => { builder.push_bind(column_value); },
                         None => { builder.push("DEFAULT"); },
                     }
                 }
             }
-            false => quote!(builder.push_bind(record.#column_ident);),
+            false => quote!(builder.push_bind(&record.#column_ident);),
+        };
+        // * `COPY` has no equivalent of `DEFAULT`, so records with defaulted columns are never
+        // * written as `COPY` rows (see `has_defaulted_columns`).
+        let copy_value = match defaultable {
+            true => quote! {
+                crate::database::copy::CopyText::write_copy_text(
+                    self.#column_ident.as_ref().expect("defaulted columns cannot be copied"),
+                    buffer,
+                );
+            },
+            false => quote! {
+                crate::database::copy::CopyText::write_copy_text(&self.#column_ident, buffer);
+            },
         };
 
+        if defaultable {
+            defaultable_idents.push(column_ident);
+        }
         column_names.push(column_name);
         binding_statements.push(binding_or_default);
+        copy_statements.push(copy_value);
     }
 
     quote! {
         impl crate::database::SingleInsert for #type_name {
             const COLUMN_NAMES: &[&str] = &[#(#column_names),*];
 
-            fn push_column_bindings(
-                mut builder: crate::database::Separated<crate::database::Postgres, &str>,
-                record: Self,
+            fn push_column_bindings<'args>(
+                mut builder: crate::database::Separated<'_, 'args, crate::database::Postgres, &'static str>,
+                record: &'args Self,
             ) {
                 #(
                     #binding_statements
                 )*
             }
+
+            fn has_defaulted_columns(&self) -> bool {
+                false #(|| self.#defaultable_idents.is_none())*
+            }
+
+            fn write_copy_row(&self, buffer: &mut String) {
+                #(
+                    #copy_statements
+                    buffer.push('\t');
+                )*
+                // * Replace the trailing column separator with the row terminator.
+                buffer.pop();
+                buffer.push('\n');
+            }
         }
     }
     .into()
@@ -202,6 +253,22 @@ pub fn derive_bulk_insert(input: TokenStream) -> TokenStream {
     .into()
 }
 
+pub fn derive_backup_table(input: TokenStream) -> TokenStream {
+    let DeriveInput {
+        ident: type_name,
+        data,
+        ..
+    } = parse_macro_input!(input);
+    let Data::Struct(_) = data else {
+        synerror!(type_name, "cannot derive `BackupTable` for non-struct types")
+    };
+
+    quote! {
+        impl crate::database::backup::BackupTable for #type_name {}
+    }
+    .into()
+}
+
 pub fn derive_identifiable_record(input: TokenStream) -> TokenStream {
     let DeriveInput {
         ident: type_name,
@@ -37,6 +37,11 @@ pub fn derive_serve_record_json(input: TokenStream) -> TokenStream {
     derives::api::derive_serve_record_json(input)
 }
 
+#[proc_macro_derive(ApiSchema, attributes(api_schema))]
+pub fn derive_api_schema(input: TokenStream) -> TokenStream {
+    derives::api::derive_api_schema(input)
+}
+
 #[proc_macro_derive(IdParameter)]
 pub fn derive_id_parameter(input: TokenStream) -> TokenStream {
     derives::api::derive_id_parameter(input)
@@ -67,6 +72,11 @@ pub fn derive_bulk_insert(input: TokenStream) -> TokenStream {
     derives::database::derive_bulk_insert(input)
 }
 
+#[proc_macro_derive(BackupTable)]
+pub fn derive_backup_table(input: TokenStream) -> TokenStream {
+    derives::database::derive_backup_table(input)
+}
+
 #[proc_macro_derive(IdentifiableRecord)]
 pub fn derive_identifiable_record(input: TokenStream) -> TokenStream {
     derives::database::derive_identifiable_record(input)
@@ -0,0 +1,517 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use axum::response::IntoResponse;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::{find_location, require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::{AppointmentStatus, UserRole};
+use crate::database::tables::appointments::{
+    AppointmentCalendarEntry, AppointmentDetails, AppointmentsTableRecord, BookingError,
+};
+use crate::database::tables::business_hours::BusinessHoursTableRecord;
+use crate::database::tables::service_types::ServiceTypesTableRecord;
+use crate::database::tables::users::UsersTableRecord;
+use crate::ServerState;
+
+/// How far apart the start times offered for appointments are.
+const SLOT_INTERVAL_MINUTES: i64 = 15;
+/// How many days of past appointments are kept in the calendar feed.
+const CALENDAR_HISTORY_DAYS: i64 = 30;
+/// How many days of appointments are listed when no range is given.
+const DEFAULT_LIST_DAYS: i64 = 7;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct AppointmentsParameters {
+    /// The start of the range to list, which defaults to now.
+    from: Option<DateTime<Utc>>,
+    /// The end of the range to list, which defaults to a week after its start.
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct CalendarTokenParameter {
+    token: String,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct AppointmentIdParameter {
+    id: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct CheckInParameters {
+    id: i32,
+    /// The store the customer is checked in at, which defaults to the first one.
+    location: Option<i32>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct AvailabilityParameters {
+    date: NaiveDate,
+    /// A comma-separated list of the service types to book.
+    #[api_schema(schema = json!({
+            "type": "string",
+            "description": "A comma-separated list of service type IDs",
+            "example": "1,2",
+        }))]
+    service_types: String,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ServiceDurationParameters {
+    id: i32,
+    minutes: i32,
+}
+
+/// A request to book an appointment for a customer.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct BookingRequest {
+    customer: i32,
+    device_model: Option<i32>,
+    service_types: Vec<i32>,
+    starts_at: DateTime<Utc>,
+    notes: Option<String>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct AppointmentStatusRequest {
+    status: AppointmentStatus,
+}
+
+/// The link of a user's appointment calendar feed.
+#[derive(Serialize, ApiSchema)]
+pub struct CalendarLink {
+    token: String,
+    /// The path of the feed, relative to the API's address.
+    feed: String,
+}
+
+/// The times an appointment for a set of services could start on a day.
+#[derive(Serialize, ApiSchema)]
+pub struct Availability {
+    date: NaiveDate,
+    duration_minutes: i32,
+    slots: Vec<DateTime<Utc>>,
+}
+
+/// List the appointments overlapping a span of time.
+pub async fn list_appointments(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<AppointmentsParameters>,
+) -> Json<Vec<AppointmentDetails>> {
+    let from = parameters.from.unwrap_or_else(Utc::now);
+    let to = parameters
+        .to
+        .unwrap_or_else(|| from + Duration::days(DEFAULT_LIST_DAYS));
+    Json(AppointmentDetails::query_between(&state.database, from, to).await)
+}
+
+/// Book an appointment, which lasts as long as its service types take altogether.
+///
+/// The appointment must fit within the shop's business hours on a single day and must not overlap
+/// any other appointment. Only admins and front desk staff can do this.
+pub async fn book_appointment(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<BookingRequest>,
+) -> ApiResult<AppointmentDetails> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot book or check in appointments",
+    )?;
+    let duration = booking_duration(&state, &request.service_types).await?;
+    let starts_at = request.starts_at;
+    let ends_at = starts_at + Duration::minutes(duration.into());
+
+    if starts_at < Utc::now() {
+        return Err(invalid("appointments cannot be booked in the past"));
+    }
+
+    let timezone = state.localization.timezone();
+    let date = starts_at.with_timezone(&timezone).date_naive();
+    let week = BusinessHoursTableRecord::query_week(&state.database).await;
+    match opening_hours(date, &week, timezone) {
+        Some((opens_at, closes_at)) if opens_at <= starts_at && ends_at <= closes_at => {}
+        Some(_) => {
+            return Err(invalid(
+                "the appointment does not fit within business hours",
+            ))
+        }
+        None => return Err(invalid("the shop is closed on that day")),
+    }
+
+    AppointmentDetails::book(
+        &state.database,
+        request.customer,
+        request.device_model,
+        &request.service_types,
+        starts_at,
+        ends_at,
+        request.notes.as_deref(),
+    )
+    .await
+    .map(Json)
+    .map_err(|error| match error {
+        BookingError::CustomerNotFound => (
+            StatusCode::NOT_FOUND,
+            format!("no customer with ID {}", request.customer),
+        ),
+        BookingError::SlotTaken => (
+            StatusCode::CONFLICT,
+            "the appointment overlaps another appointment".to_owned(),
+        ),
+    })
+}
+
+/// Cancel a booked appointment or mark the customer as not having shown up. Only admins and front
+/// desk staff can do this.
+pub async fn set_status(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<AppointmentIdParameter>,
+    Json(request): Json<AppointmentStatusRequest>,
+) -> ApiResult<AppointmentDetails> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot book or check in appointments",
+    )?;
+    if !matches!(
+        request.status,
+        AppointmentStatus::Cancelled | AppointmentStatus::NoShow
+    ) {
+        return Err(invalid(
+            "appointments can only be cancelled or marked as no-shows",
+        ));
+    }
+
+    match AppointmentDetails::set_status(&state.database, parameters.id, request.status).await {
+        Some(appointment) => Ok(Json(appointment)),
+        None => Err(not_booked(&state, parameters.id).await),
+    }
+}
+
+/// Check in the customer of a booked appointment, creating a ticket for their repair. Only admins
+/// and front desk staff can do this.
+pub async fn check_in(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<CheckInParameters>,
+) -> ApiResult<AppointmentDetails> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot book or check in appointments",
+    )?;
+    let location = find_location(&state, parameters.location).await?.id;
+    match AppointmentDetails::check_in(&state.database, parameters.id, location).await {
+        Some(appointment) => Ok(Json(appointment)),
+        None => Err(not_booked(&state, parameters.id).await),
+    }
+}
+
+/// Get the times an appointment for a set of service types could start on a day.
+pub async fn availability(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<AvailabilityParameters>,
+) -> ApiResult<Availability> {
+    let service_types = parameters
+        .service_types
+        .split(',')
+        .map(|id| {
+            id.trim()
+                .parse()
+                .map_err(|_| invalid(&format!("`{id}` is not a service type ID")))
+        })
+        .collect::<Result<Vec<i32>, _>>()?;
+    let duration = booking_duration(&state, &service_types).await?;
+    let length = Duration::minutes(duration.into());
+
+    let timezone = state.localization.timezone();
+    let week = BusinessHoursTableRecord::query_week(&state.database).await;
+    let mut slots = Vec::new();
+    if let Some((opens_at, closes_at)) = opening_hours(parameters.date, &week, timezone) {
+        let booked =
+            AppointmentsTableRecord::query_active_between(&state.database, opens_at, closes_at)
+                .await;
+        let now = Utc::now();
+
+        let mut start = opens_at;
+        while start + length <= closes_at {
+            let end = start + length;
+            let free = !booked
+                .iter()
+                .any(|appointment| appointment.starts_at < end && appointment.ends_at > start);
+            if start >= now && free {
+                slots.push(start);
+            }
+            start += Duration::minutes(SLOT_INTERVAL_MINUTES);
+        }
+    }
+
+    Ok(Json(Availability {
+        date: parameters.date,
+        duration_minutes: duration,
+        slots,
+    }))
+}
+
+/// Get the link of the signed-in user's appointment calendar feed, for them to subscribe to in a
+/// calendar app.
+pub async fn calendar_link(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+) -> Json<CalendarLink> {
+    let token = state.portal_signer.calendar_token(user.id);
+    Json(CalendarLink {
+        feed: format!("/appointments/calendar.ics?token={token}"),
+        token,
+    })
+}
+
+/// Serve upcoming and recent appointments as an iCalendar feed, which calendar apps can subscribe
+/// to.
+///
+/// Calendar apps cannot sign in, so the feed is authorized by the token of a link from
+/// [`calendar_link`] instead, which stops working once its user is deleted.
+pub async fn calendar(
+    State(state): State<Arc<ServerState>>,
+    Query(parameters): Query<CalendarTokenParameter>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = match state.portal_signer.verify_calendar(&parameters.token) {
+        Some(id) => UsersTableRecord::query_by_id(&state.database, id).await,
+        None => None,
+    };
+    if user.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "this calendar link is not valid".to_owned(),
+        ));
+    }
+
+    let since = Utc::now() - Duration::days(CALENDAR_HISTORY_DAYS);
+    let entries = AppointmentCalendarEntry::query_since(&state.database, since).await;
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//Fixwise//Appointments//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+        "METHOD:PUBLISH".to_owned(),
+    ];
+    for entry in entries {
+        let mut summary = entry.customer.clone();
+        if !entry.services.is_empty() {
+            summary.push_str(": ");
+            summary.push_str(&entry.services.join(", "));
+        }
+
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:appointment-{}@fixwise", entry.id));
+        lines.push(format!("DTSTAMP:{}", calendar_time(entry.created_at)));
+        lines.push(format!("DTSTART:{}", calendar_time(entry.starts_at)));
+        lines.push(format!("DTEND:{}", calendar_time(entry.ends_at)));
+        lines.push(format!("SUMMARY:{}", calendar_text(&summary)));
+        let description = [entry.device_model, entry.notes]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", calendar_text(&description)));
+        }
+        if entry.status == AppointmentStatus::CheckedIn {
+            lines.push("CATEGORIES:Checked in".to_owned());
+        }
+        lines.push("STATUS:CONFIRMED".to_owned());
+        lines.push("END:VEVENT".to_owned());
+    }
+    lines.push("END:VCALENDAR".to_owned());
+
+    let body: String = lines
+        .iter()
+        .map(|line| fold_calendar_line(line) + "\r\n")
+        .collect();
+    Ok(([(CONTENT_TYPE, "text/calendar; charset=utf-8")], body))
+}
+
+/// Get the shop's opening hours for each day of the week.
+pub async fn business_hours(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+) -> Json<Vec<BusinessHoursTableRecord>> {
+    Json(BusinessHoursTableRecord::query_week(&state.database).await)
+}
+
+/// Replace the shop's opening hours for the whole week. Only admins can do this.
+pub async fn set_business_hours(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(week): Json<Vec<BusinessHoursTableRecord>>,
+) -> ApiResult<Vec<BusinessHoursTableRecord>> {
+    require_role(
+        &user,
+        &[UserRole::Admin],
+        "only admins can change business hours and service durations",
+    )?;
+
+    let mut weekdays = HashSet::new();
+    for day in &week {
+        if !(1..=7).contains(&day.weekday) {
+            return Err(invalid("weekdays must be from 1 (Monday) to 7 (Sunday)"));
+        }
+        if !weekdays.insert(day.weekday) {
+            return Err(invalid(&format!("weekday {} is given twice", day.weekday)));
+        }
+        if day.closes_at <= day.opens_at {
+            return Err(invalid(&format!(
+                "the shop must close after it opens on weekday {}",
+                day.weekday
+            )));
+        }
+    }
+
+    BusinessHoursTableRecord::replace_week(&state.database, &week).await;
+    Ok(Json(
+        BusinessHoursTableRecord::query_week(&state.database).await,
+    ))
+}
+
+/// Change how long appointments for a type of service take. Only admins can do this.
+pub async fn set_service_duration(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<ServiceDurationParameters>,
+) -> ApiResult<ServiceTypesTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin],
+        "only admins can change business hours and service durations",
+    )?;
+    if parameters.minutes <= 0 {
+        return Err(invalid("the duration must be at least a minute"));
+    }
+
+    ServiceTypesTableRecord::set_duration(&state.database, parameters.id, parameters.minutes)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no service type with ID {}", parameters.id),
+            )
+        })
+}
+
+/// Get the total duration of a set of service types in minutes, checking that they all exist.
+async fn booking_duration(
+    state: &ServerState,
+    service_types: &[i32],
+) -> Result<i32, (StatusCode, String)> {
+    if service_types.is_empty() {
+        return Err(invalid("at least one service type must be requested"));
+    }
+    if service_types.iter().collect::<HashSet<_>>().len() != service_types.len() {
+        return Err(invalid("a service type is requested twice"));
+    }
+
+    let found = ServiceTypesTableRecord::query_by_ids(&state.database, service_types).await;
+    if let Some(missing) = service_types
+        .iter()
+        .find(|id| !found.iter().any(|service_type| service_type.id == **id))
+    {
+        return Err(invalid(&format!("there is no service type {missing}")));
+    }
+
+    Ok(found
+        .iter()
+        .map(|service_type| service_type.duration_minutes.unwrap())
+        .sum())
+}
+
+/// Get when the shop opens and closes on a local date, or [`None`] if it is closed that day.
+fn opening_hours(
+    date: NaiveDate,
+    week: &[BusinessHoursTableRecord],
+    timezone: Tz,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let weekday = date.weekday().number_from_monday() as i32;
+    let day = week.iter().find(|day| day.weekday == weekday)?;
+    let to_utc = |time: NaiveTime| {
+        timezone
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    };
+
+    Some((to_utc(day.opens_at)?, to_utc(day.closes_at)?))
+}
+
+async fn not_booked(state: &ServerState, id: i32) -> (StatusCode, String) {
+    match AppointmentDetails::query_by_id(&state.database, id).await {
+        Some(appointment) => (
+            StatusCode::CONFLICT,
+            format!(
+                "the appointment is already {}",
+                serde_json::to_value(appointment.appointment.status.unwrap())
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+            ),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("no appointment with ID {id}"),
+        ),
+    }
+}
+
+fn invalid(message: &str) -> (StatusCode, String) {
+    (StatusCode::UNPROCESSABLE_ENTITY, message.to_owned())
+}
+
+fn calendar_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape text for an iCalendar property value.
+fn calendar_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold an iCalendar content line so that no line is longer than 75 bytes, as the format requires.
+fn fold_calendar_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(character);
+        length += character.len_utf8();
+    }
+    folded
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use http::StatusCode;
+use itertools::Itertools;
+use proc_macros::ApiSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ApiResult, CurrentUser};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::ticket_assignments::{QueueItem, TicketAssignmentsTableRecord};
+use crate::database::tables::users::UsersTableRecord;
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct TicketParameter {
+    pub ticket: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct QueueParameters {
+    /// Only show the queue of this technician, rather than of every technician.
+    technician: Option<i32>,
+}
+
+/// A request to assign a ticket, or one of its devices, to a technician.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct AssignmentRequest {
+    ticket: i32,
+    /// The device of the ticket to assign, or [`None`] to assign the whole ticket.
+    device: Option<i32>,
+    /// The technician to assign, or [`None`] to unassign the work.
+    assignee: Option<i32>,
+}
+
+/// The open work assigned to a single technician.
+#[derive(Serialize, ApiSchema)]
+pub struct TechnicianQueue {
+    technician: i32,
+    display_name: String,
+    work: Vec<QueueItem>,
+}
+
+/// Assign a ticket or one of its devices to a technician, replacing its current assignee.
+///
+/// Admins and front desk staff can assign work to any technician, while technicians can only take
+/// work on themselves.
+pub async fn assign(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<AssignmentRequest>,
+) -> ApiResult<TicketAssignmentsTableRecord> {
+    if user.role == UserRole::Technician && request.assignee != Some(user.id) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "technicians can only assign work to themselves".to_owned(),
+        ));
+    }
+
+    if let Some(assignee) = request.assignee {
+        find_technician(&state, assignee)
+            .await
+            .map_err(|(_, message)| (StatusCode::UNPROCESSABLE_ENTITY, message))?;
+    }
+
+    TicketAssignmentsTableRecord::assign(
+        &state.database,
+        request.ticket,
+        request.device,
+        request.assignee,
+        user.id,
+    )
+    .await
+    .map(Json)
+    .ok_or_else(|| {
+        let message = match request.device {
+            Some(device) => format!("ticket {} has no device {device}", request.ticket),
+            None => format!("no ticket with ID {}", request.ticket),
+        };
+        (StatusCode::NOT_FOUND, message)
+    })
+}
+
+/// Get the assignment history of a ticket and its devices, oldest first.
+pub async fn list_assignments(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<TicketParameter>,
+) -> Json<Vec<TicketAssignmentsTableRecord>> {
+    Json(TicketAssignmentsTableRecord::query_for_ticket(&state.database, parameters.ticket).await)
+}
+
+/// Get the open work of every technician (including those with none), or of a single technician.
+pub async fn serve_queue(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<QueueParameters>,
+) -> ApiResult<Vec<TechnicianQueue>> {
+    let technicians = match parameters.technician {
+        Some(technician) => vec![find_technician(&state, technician).await?],
+        None => UsersTableRecord::query_by_role(&state.database, UserRole::Technician).await,
+    };
+    let mut work = QueueItem::query_open(&state.database, parameters.technician)
+        .await
+        .into_iter()
+        .into_group_map_by(|item| item.technician);
+
+    Ok(Json(
+        technicians
+            .into_iter()
+            .map(|technician| TechnicianQueue {
+                technician: technician.id,
+                work: work.remove(&technician.id).unwrap_or_default(),
+                display_name: technician.display_name,
+            })
+            .collect(),
+    ))
+}
+
+async fn find_technician(
+    state: &ServerState,
+    user_id: i32,
+) -> Result<UsersTableRecord, (StatusCode, String)> {
+    match UsersTableRecord::query_by_id(&state.database, user_id).await {
+        Some(user) if user.role == UserRole::Technician => Ok(user),
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            format!("user {user_id} is not a technician"),
+        )),
+    }
+}
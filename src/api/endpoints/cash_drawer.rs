@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::api::{find_location, require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::{CashMovementType, UserRole};
+use crate::database::tables::register_sessions::{
+    CashMovementsTableRecord, RegisterSessionsTableRecord, ZReport,
+};
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct DrawerParameter {
+    /// The store whose drawer is handled, which defaults to the first one.
+    location: Option<i32>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct SessionsParameter {
+    location: Option<i32>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct SessionParameter {
+    /// The session to report on, which defaults to the open one of the store.
+    id: Option<i32>,
+    location: Option<i32>,
+}
+
+/// A request to open the drawer at the start of a shift.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct OpenSessionRequest {
+    /// The cash the drawer starts with, for making change.
+    opening_float: Decimal,
+    notes: Option<String>,
+}
+
+/// A request to close the drawer at the end of a shift.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct CloseSessionRequest {
+    /// The cash counted in the drawer, including the float.
+    counted_amount: Decimal,
+    notes: Option<String>,
+}
+
+/// A request to record cash put into or taken out of the drawer other than for an invoice.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct CashMovementRequest {
+    r#type: CashMovementType,
+    amount: Decimal,
+    reason: String,
+}
+
+/// List every session of the cash drawers, newest first, optionally only those of a store.
+pub async fn list_sessions(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<SessionsParameter>,
+) -> Json<Vec<RegisterSessionsTableRecord>> {
+    Json(RegisterSessionsTableRecord::query_all(&state.database, parameters.location).await)
+}
+
+/// Open the cash drawer with a float.
+pub async fn open_session(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<DrawerParameter>,
+    Json(request): Json<OpenSessionRequest>,
+) -> ApiResult<RegisterSessionsTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot handle the cash drawer",
+    )?;
+    let location = find_location(&state, parameters.location).await?.id;
+    if request.opening_float.is_sign_negative() {
+        return Err(invalid("the opening float cannot be negative"));
+    }
+    if let Some(open) = RegisterSessionsTableRecord::query_open(&state.database, location).await {
+        return Err(already_open(Some(open.id)));
+    }
+
+    RegisterSessionsTableRecord::open(
+        &state.database,
+        location,
+        user.id,
+        request.opening_float,
+        request.notes.as_deref(),
+    )
+    .await
+    .map(Json)
+    .ok_or_else(|| already_open(None))
+}
+
+/// Close the cash drawer with the cash counted in it, responding with the Z-report of the session.
+pub async fn close_session(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<DrawerParameter>,
+    Json(request): Json<CloseSessionRequest>,
+) -> ApiResult<ZReport> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot handle the cash drawer",
+    )?;
+    let location = find_location(&state, parameters.location).await?.id;
+    if request.counted_amount.is_sign_negative() {
+        return Err(invalid("the counted amount cannot be negative"));
+    }
+
+    let session = RegisterSessionsTableRecord::close(
+        &state.database,
+        location,
+        user.id,
+        request.counted_amount,
+        request.notes.as_deref(),
+    )
+    .await
+    .ok_or_else(not_open)?;
+    Ok(Json(ZReport::query(&state.database, session).await))
+}
+
+/// Record cash paid into or out of the open drawer.
+pub async fn record_movement(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<DrawerParameter>,
+    Json(request): Json<CashMovementRequest>,
+) -> ApiResult<CashMovementsTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot handle the cash drawer",
+    )?;
+    let location = find_location(&state, parameters.location).await?.id;
+    if request.amount <= Decimal::ZERO {
+        return Err(invalid("the amount must be more than zero"));
+    }
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Err(invalid("cash paid in or out needs a reason"));
+    }
+
+    CashMovementsTableRecord::record(
+        &state.database,
+        location,
+        request.r#type,
+        request.amount,
+        reason,
+        user.id,
+    )
+    .await
+    .map(Json)
+    .ok_or_else(not_open)
+}
+
+/// Get the Z-report of a session, or the takings so far of the open session of a store.
+pub async fn session_report(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<SessionParameter>,
+) -> ApiResult<ZReport> {
+    let session = match parameters.id {
+        Some(id) => RegisterSessionsTableRecord::query_by_id(&state.database, id)
+            .await
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no session with ID {id}")))?,
+        None => {
+            let location = find_location(&state, parameters.location).await?.id;
+            RegisterSessionsTableRecord::query_open(&state.database, location)
+                .await
+                .ok_or_else(not_open)?
+        }
+    };
+    Ok(Json(ZReport::query(&state.database, session).await))
+}
+
+fn already_open(session: Option<i32>) -> (StatusCode, String) {
+    (
+        StatusCode::CONFLICT,
+        match session {
+            Some(session) => format!("the drawer is already open in session {session}"),
+            None => "the drawer is already open".to_owned(),
+        },
+    )
+}
+
+fn not_open() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "the drawer is not open".to_owned())
+}
+
+fn invalid(message: &str) -> (StatusCode, String) {
+    (StatusCode::UNPROCESSABLE_ENTITY, message.to_owned())
+}
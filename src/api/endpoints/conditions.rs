@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{Json, Query, State};
+use axum::response::{Html, IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::api::endpoints::portal::escape_html;
+use crate::api::endpoints::{ColumnFormat, ViewCell};
+use crate::api::localization::Localization;
+use crate::api::{ApiResult, CurrentUser};
+use crate::database::shared_models::ComponentCondition;
+use crate::database::tables::device_conditions::{
+    ConditionReportDevice, DeviceConditionsJunctionTableRecord, DevicePhotosTableRecord,
+};
+use crate::database::views::tickets::TicketsViewRecord;
+use crate::photos::{process_photo, thumbnail_key, PhotoStoreError, MAX_PHOTO_SIDE};
+use crate::ServerState;
+
+/// The largest photo which can be uploaded, which leaves room for full-resolution photos from
+/// phone cameras.
+pub const MAX_PHOTO_SIZE: usize = 20 * 1024 * 1024;
+
+/// The types of image photos can be uploaded as.
+pub const PHOTO_CONTENT_TYPES: &[&str] = &["image/jpeg", "image/png"];
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ConditionTicketParameters {
+    ticket: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ConditionDeviceParameters {
+    ticket: i32,
+    device: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PhotoUploadParameters {
+    ticket: i32,
+    device: i32,
+    caption: Option<String>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PhotoParameters {
+    id: i32,
+    /// Whether to get the photo's thumbnail rather than the full image.
+    #[serde(default)]
+    thumbnail: bool,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PhotoIdParameters {
+    id: i32,
+}
+
+/// The checklist filled in for a device when it is checked in.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ConditionRequest {
+    screen: ComponentCondition,
+    buttons: ComponentCondition,
+    cameras: ComponentCondition,
+    water_indicators_tripped: Option<bool>,
+    #[serde(default)]
+    passcode_provided: bool,
+    #[serde(default)]
+    accessories: Vec<String>,
+    notes: Option<String>,
+}
+
+/// A device on a ticket with its checklist, if one has been recorded, and its photos.
+#[derive(Serialize, ApiSchema)]
+pub struct DeviceConditionReport {
+    #[serde(flatten)]
+    device: ConditionReportDevice,
+    checklist: Option<DeviceConditionsJunctionTableRecord>,
+    photos: Vec<PhotoDetails>,
+}
+
+#[derive(Serialize, ApiSchema)]
+pub struct PhotoDetails {
+    #[serde(flatten)]
+    photo: DevicePhotosTableRecord,
+    /// The path of the full image, relative to the API's address.
+    image: String,
+    /// The path of the thumbnail, relative to the API's address.
+    thumbnail: String,
+}
+
+impl PhotoDetails {
+    fn new(photo: DevicePhotosTableRecord) -> Self {
+        let image = format!("/tickets/condition/photos?id={}", photo.id);
+        Self {
+            thumbnail: format!("{image}&thumbnail=true"),
+            image,
+            photo,
+        }
+    }
+}
+
+/// Get the condition report of every device on a ticket. Any staff member can do this.
+pub async fn condition_report(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<ConditionTicketParameters>,
+) -> ApiResult<Vec<DeviceConditionReport>> {
+    find_ticket(&state, parameters.ticket).await?;
+
+    Ok(Json(query_reports(&state, parameters.ticket).await))
+}
+
+/// Record the checklist of a device on a ticket, replacing any recorded before.
+pub async fn record_condition(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<ConditionDeviceParameters>,
+    Json(request): Json<ConditionRequest>,
+) -> ApiResult<DeviceConditionsJunctionTableRecord> {
+    let accessories = request
+        .accessories
+        .iter()
+        .map(|accessory| accessory.trim())
+        .filter(|accessory| !accessory.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    DeviceConditionsJunctionTableRecord {
+        ticket: parameters.ticket,
+        device: parameters.device,
+        screen: request.screen,
+        buttons: request.buttons,
+        cameras: request.cameras,
+        water_indicators_tripped: request.water_indicators_tripped,
+        passcode_provided: request.passcode_provided,
+        accessories,
+        notes: request.notes,
+        recorded_by: Some(user.id),
+        recorded_at: None,
+    }
+    .record(&state.database)
+    .await
+    .map(Json)
+    .ok_or_else(|| device_not_found(parameters.ticket, parameters.device))
+}
+
+/// Add a photo of a device on a ticket, given as a JPEG or PNG image in the request body.
+pub async fn upload_photo(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<PhotoUploadParameters>,
+    body: Bytes,
+) -> ApiResult<DevicePhotosTableRecord> {
+    let (ticket, device) = (parameters.ticket, parameters.device);
+    if !ConditionReportDevice::exists(&state.database, ticket, device).await {
+        return Err(device_not_found(ticket, device));
+    }
+
+    let contents = body.to_vec();
+    let (contents, processed) = tokio::task::spawn_blocking(move || {
+        let processed = process_photo(&contents);
+        (contents, processed)
+    })
+    .await
+    .unwrap();
+    let processed = processed.ok_or_else(|| {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!(
+                "photos must be JPEG or PNG images no larger than {MAX_PHOTO_SIDE} pixels on \
+                 either side"
+            ),
+        )
+    })?;
+
+    let key = format!(
+        "{ticket}/{device}/{:032x}.{}",
+        rand::random::<u128>(),
+        processed.extension
+    );
+    let stored = match state
+        .photos
+        .put(&key, contents, processed.content_type)
+        .await
+    {
+        Ok(()) => {
+            state
+                .photos
+                .put(&thumbnail_key(&key), processed.thumbnail, "image/jpeg")
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = stored {
+        remove_images(&state, &key).await;
+        return Err(photo_store_unavailable(e));
+    }
+
+    let caption = parameters
+        .caption
+        .as_deref()
+        .map(str::trim)
+        .filter(|caption| !caption.is_empty());
+    match DevicePhotosTableRecord::create(
+        &state.database,
+        ticket,
+        device,
+        &key,
+        processed.content_type,
+        caption,
+        user.id,
+    )
+    .await
+    {
+        Some(photo) => Ok(Json(photo)),
+        // * The device was taken off the ticket while the photo was being stored.
+        None => {
+            remove_images(&state, &key).await;
+            Err(device_not_found(ticket, device))
+        }
+    }
+}
+
+/// Get the image of a photo, or its thumbnail.
+pub async fn photo(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<PhotoParameters>,
+) -> Response {
+    match find_photo_image(&state, &parameters).await {
+        Ok((content_type, contents)) => (
+            [
+                (CONTENT_TYPE, content_type),
+                // * Photos are never changed once uploaded, only deleted.
+                (CACHE_CONTROL, "private, max-age=86400".to_owned()),
+            ],
+            contents,
+        )
+            .into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Delete a photo, responding with whether it existed.
+pub async fn delete_photo(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<PhotoIdParameters>,
+) -> ApiResult<bool> {
+    match DevicePhotosTableRecord::delete(&state.database, parameters.id).await {
+        Some(photo) => {
+            remove_images(&state, &photo.storage_key).await;
+            Ok(Json(true))
+        }
+        None => Ok(Json(false)),
+    }
+}
+
+/// Show the condition of every device on a ticket as a page to print and have the customer sign
+/// when they leave their devices.
+pub async fn receipt(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    localization: Localization,
+    Query(parameters): Query<ConditionTicketParameters>,
+) -> Response {
+    let ticket = match find_ticket(&state, parameters.ticket).await {
+        Ok(ticket) => ticket,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let reports = query_reports(&state, ticket.id).await;
+    // * Thumbnails are embedded in the page so that it prints the same wherever it is opened.
+    let mut thumbnails = HashMap::new();
+    for photo in reports.iter().flat_map(|report| &report.photos) {
+        let key = thumbnail_key(&photo.photo.storage_key);
+        match state.photos.get(&key).await {
+            Ok(Some(thumbnail)) => {
+                thumbnails.insert(photo.photo.id, BASE64.encode(thumbnail));
+            }
+            Ok(None) => eprintln!("The thumbnail of photo {} is missing", photo.photo.id),
+            Err(e) => eprintln!(
+                "Could not get the thumbnail of photo {}: {e}",
+                photo.photo.id
+            ),
+        }
+    }
+
+    Html(render_receipt(
+        &ticket,
+        &reports,
+        &thumbnails,
+        &localization,
+    ))
+    .into_response()
+}
+
+async fn query_reports(state: &ServerState, ticket: i32) -> Vec<DeviceConditionReport> {
+    let mut checklists: HashMap<i32, DeviceConditionsJunctionTableRecord> =
+        DeviceConditionsJunctionTableRecord::query_for_ticket(&state.database, ticket)
+            .await
+            .into_iter()
+            .map(|checklist| (checklist.device, checklist))
+            .collect();
+    let mut photos: HashMap<i32, Vec<PhotoDetails>> = HashMap::new();
+    for photo in DevicePhotosTableRecord::query_for_ticket(&state.database, ticket).await {
+        photos
+            .entry(photo.device)
+            .or_default()
+            .push(PhotoDetails::new(photo));
+    }
+
+    ConditionReportDevice::query_for_ticket(&state.database, ticket)
+        .await
+        .into_iter()
+        .map(|device| DeviceConditionReport {
+            checklist: checklists.remove(&device.device),
+            photos: photos.remove(&device.device).unwrap_or_default(),
+            device,
+        })
+        .collect()
+}
+
+async fn find_photo_image(
+    state: &ServerState,
+    parameters: &PhotoParameters,
+) -> Result<(String, Vec<u8>), (StatusCode, String)> {
+    let photo = DevicePhotosTableRecord::query_by_id(&state.database, parameters.id)
+        .await
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no photo with ID {}", parameters.id),
+            )
+        })?;
+
+    let (key, content_type) = match parameters.thumbnail {
+        true => (thumbnail_key(&photo.storage_key), "image/jpeg".to_owned()),
+        false => (photo.storage_key, photo.content_type),
+    };
+    match state.photos.get(&key).await {
+        Ok(Some(contents)) => Ok((content_type, contents)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!(
+                "the image of photo {} is missing from the photo store",
+                photo.id
+            ),
+        )),
+        Err(e) => Err(photo_store_unavailable(e)),
+    }
+}
+
+/// Remove a photo's image and thumbnail from the photo store, which is not worth failing a request
+/// over once its record is gone.
+async fn remove_images(state: &ServerState, key: &str) {
+    for key in [thumbnail_key(key), key.to_owned()] {
+        if let Err(e) = state.photos.delete(&key).await {
+            eprintln!("Could not remove `{key}` from the photo store: {e}");
+        }
+    }
+}
+
+async fn find_ticket(
+    state: &ServerState,
+    ticket: i32,
+) -> Result<TicketsViewRecord, (StatusCode, String)> {
+    TicketsViewRecord::query_by_id(&state.database, ticket)
+        .await
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no ticket with ID {ticket}")))
+}
+
+fn device_not_found(ticket: i32, device: i32) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("ticket {ticket} has no device {device}"),
+    )
+}
+
+fn photo_store_unavailable(e: PhotoStoreError) -> (StatusCode, String) {
+    eprintln!("Could not reach the photo store: {e}");
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "the photo store is unavailable".to_owned(),
+    )
+}
+
+fn condition_label(condition: ComponentCondition) -> &'static str {
+    match condition {
+        ComponentCondition::Working => "Working",
+        ComponentCondition::Damaged => "Damaged",
+        ComponentCondition::NotWorking => "Not Working",
+        ComponentCondition::NotChecked => "Not Checked",
+    }
+}
+
+fn render_receipt(
+    ticket: &TicketsViewRecord,
+    reports: &[DeviceConditionReport],
+    thumbnails: &HashMap<i32, String>,
+    localization: &Localization,
+) -> String {
+    let translate = |text| escape_html(localization.translate(text));
+    let formatted = |formatted: Option<String>| escape_html(&formatted.unwrap_or_default());
+
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n\
+         <style>figure {{ display: inline-block; margin: 4px; }} img {{ width: 160px; }}</style>\n\
+         </head>\n<body>\n<h1>{title} {id}</h1>\n<dl>\n",
+        title = translate("Check-In Receipt"),
+        id = formatted(ViewCell::new(ticket.id, &ColumnFormat::Id, localization).formatted),
+    );
+    for (label, value) in [
+        (
+            "Customer",
+            escape_html(ticket.customer.as_deref().unwrap_or_default()),
+        ),
+        ("Store", escape_html(&ticket.location)),
+        (
+            "Created",
+            formatted(
+                ViewCell::new(ticket.created_at, &ColumnFormat::Date, localization).formatted,
+            ),
+        ),
+    ] {
+        page.push_str(&format!("<dt>{}</dt><dd>{value}</dd>\n", translate(label)));
+    }
+    page.push_str("</dl>\n");
+
+    for report in reports {
+        page.push_str(&format!("<h2>{}</h2>\n", escape_html(&report.device.model)));
+        let mut rows = Vec::new();
+        if let Some(service) = &report.device.service {
+            rows.push(("Service", escape_html(service)));
+        }
+        if let Some(diagnostic) = &report.device.diagnostic {
+            rows.push(("Diagnostic", escape_html(diagnostic)));
+        }
+        match &report.checklist {
+            Some(checklist) => {
+                let yes_no = |value: bool| translate(if value { "Yes" } else { "No" });
+                rows.extend([
+                    ("Screen", translate(condition_label(checklist.screen))),
+                    ("Buttons", translate(condition_label(checklist.buttons))),
+                    ("Cameras", translate(condition_label(checklist.cameras))),
+                    (
+                        "Water Indicators",
+                        translate(match checklist.water_indicators_tripped {
+                            Some(true) => "Tripped",
+                            Some(false) => "Not Tripped",
+                            None => "Not Checked",
+                        }),
+                    ),
+                    ("Passcode Provided", yes_no(checklist.passcode_provided)),
+                    (
+                        "Accessories",
+                        match checklist.accessories.is_empty() {
+                            true => translate("None"),
+                            false => escape_html(&checklist.accessories.join(", ")),
+                        },
+                    ),
+                ]);
+                if let Some(notes) = &checklist.notes {
+                    rows.push(("Notes", escape_html(notes)));
+                }
+            }
+            None => rows.push(("Condition", translate("Not Checked"))),
+        }
+
+        page.push_str("<dl>\n");
+        for (label, value) in rows {
+            page.push_str(&format!("<dt>{}</dt><dd>{value}</dd>\n", translate(label)));
+        }
+        page.push_str("</dl>\n");
+
+        for photo in &report.photos {
+            let Some(thumbnail) = thumbnails.get(&photo.photo.id) else {
+                continue;
+            };
+            let caption = escape_html(photo.photo.caption.as_deref().unwrap_or_default());
+            page.push_str(&format!(
+                "<figure><img src=\"data:image/jpeg;base64,{thumbnail}\" alt=\"{caption}\">\
+                 <figcaption>{caption}</figcaption></figure>\n"
+            ));
+        }
+    }
+
+    page.push_str(&format!(
+        "<p>{}</p>\n<p>{}: ______________________________</p>\n</body>\n</html>\n",
+        translate("I agree that my devices were left in the condition described above."),
+        translate("Customer Signature"),
+    ));
+
+    page
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::api::{ApiResult, CurrentUser};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::invoice_items::{
+    InvoiceItemError, InvoiceItemsTableRecord, PriceOverride,
+};
+use crate::database::tables::users::UsersTableRecord;
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct InvoiceItemParameters {
+    invoice: i32,
+    item: i32,
+}
+
+/// A request to add an item to an invoice.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct InvoiceItemRequest {
+    invoice: i32,
+    item: i32,
+    /// A price to charge instead of the item's current price.
+    override_price: Option<Decimal>,
+    /// Why the price was set by hand, which is required along with it.
+    override_reason: Option<String>,
+}
+
+/// A request to set the price of an invoice item by hand, or to clear it by leaving out the price.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PriceOverrideRequest {
+    override_price: Option<Decimal>,
+    override_reason: Option<String>,
+}
+
+/// Add an item to an invoice at its current price, which the invoice keeps even if the price
+/// changes later.
+pub async fn add_item(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<InvoiceItemRequest>,
+) -> ApiResult<InvoiceItemsTableRecord> {
+    let price_override = price_override(
+        &user,
+        request.override_price,
+        request.override_reason.as_deref(),
+    )?;
+
+    InvoiceItemsTableRecord::add(
+        &state.database,
+        request.invoice,
+        request.item,
+        price_override,
+    )
+    .await
+    .map(Json)
+    .map_err(|error| match error {
+        InvoiceItemError::InvoiceNotFound => (
+            StatusCode::NOT_FOUND,
+            format!("no invoice with ID {}", request.invoice),
+        ),
+        InvoiceItemError::ItemNotFound => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no item with ID {}", request.item),
+        ),
+        InvoiceItemError::AlreadyAdded => (
+            StatusCode::CONFLICT,
+            format!(
+                "item {} is already on invoice {}",
+                request.item, request.invoice
+            ),
+        ),
+    })
+}
+
+/// Set the price of an invoice item by hand, or go back to charging the price it was added at.
+pub async fn set_price_override(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<InvoiceItemParameters>,
+    Json(request): Json<PriceOverrideRequest>,
+) -> ApiResult<InvoiceItemsTableRecord> {
+    let price_override = price_override(
+        &user,
+        request.override_price,
+        request.override_reason.as_deref(),
+    )?;
+
+    InvoiceItemsTableRecord::set_override(
+        &state.database,
+        parameters.invoice,
+        parameters.item,
+        price_override,
+    )
+    .await
+    .map(Json)
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!(
+                "item {} is not on invoice {}",
+                parameters.item, parameters.invoice
+            ),
+        )
+    })
+}
+
+/// Check a price set by hand, which needs a reason and cannot be set by technicians.
+fn price_override<'a>(
+    user: &UsersTableRecord,
+    price: Option<Decimal>,
+    reason: Option<&'a str>,
+) -> Result<Option<PriceOverride<'a>>, (StatusCode, String)> {
+    let Some(price) = price else {
+        return match reason {
+            Some(_) => Err(invalid("a reason was given without a price")),
+            None => Ok(None),
+        };
+    };
+
+    if user.role == UserRole::Technician {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "technicians cannot set prices by hand".to_owned(),
+        ));
+    }
+    if price.is_sign_negative() {
+        return Err(invalid("prices cannot be negative"));
+    }
+    match reason.map(str::trim) {
+        Some(reason) if !reason.is_empty() => Ok(Some(PriceOverride {
+            price,
+            reason,
+            user: user.id,
+        })),
+        _ => Err(invalid("prices set by hand need a reason")),
+    }
+}
+
+fn invalid(message: &str) -> (StatusCode, String) {
+    (StatusCode::UNPROCESSABLE_ENTITY, message.to_owned())
+}
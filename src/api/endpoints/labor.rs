@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::api::endpoints::assignments::TicketParameter;
+use crate::api::{require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::labor_entries::{LaborEntriesTableRecord, LaborReportRow, TimerError};
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct OwnedEntryParameters {
+    id: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct LaborReportParameters {
+    /// The shop's target labor rate per hour. Services earning less than this per hour of logged
+    /// labor are marked as underpriced.
+    hourly_rate: Option<Decimal>,
+}
+
+/// A request to start timing work on a device of a ticket.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct LaborTimerRequest {
+    ticket: i32,
+    device: i32,
+    notes: Option<String>,
+}
+
+/// A span of work on a device of a ticket entered by hand.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct LaborEntryRequest {
+    ticket: i32,
+    device: i32,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    notes: Option<String>,
+}
+
+/// Every labor entry of a ticket, along with the total time logged for each device and for the
+/// ticket as a whole. Running timers are not counted in the totals.
+#[derive(Serialize, ApiSchema)]
+pub struct TicketLabor {
+    entries: Vec<LaborEntriesTableRecord>,
+    devices: Vec<DeviceLabor>,
+    total_minutes: i32,
+}
+
+#[derive(Serialize, ApiSchema)]
+pub struct DeviceLabor {
+    device: i32,
+    minutes: i32,
+}
+
+#[derive(Serialize, ApiSchema)]
+pub struct ServiceLaborReport {
+    #[serde(flatten)]
+    row: LaborReportRow,
+    /// Whether the service earns less per hour of labor than the target rate, or [`None`] if no
+    /// target rate was given or no time has been logged.
+    underpriced: Option<bool>,
+}
+
+/// Start timing a technician's work on a device of a ticket.
+pub async fn start_timer(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<LaborTimerRequest>,
+) -> ApiResult<LaborEntriesTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Technician],
+        "only technicians can log labor time",
+    )?;
+    if let Some(running) = LaborEntriesTableRecord::query_running(&state.database, user.id).await {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "a timer is already running for device {} of ticket {}",
+                running.device, running.ticket,
+            ),
+        ));
+    }
+
+    LaborEntriesTableRecord::start(
+        &state.database,
+        request.ticket,
+        request.device,
+        user.id,
+        request.notes.as_deref(),
+    )
+    .await
+    .map(Json)
+    .map_err(|error| match error {
+        TimerError::DeviceNotFound => device_not_found(request.ticket, request.device),
+        TimerError::AlreadyRunning => (
+            StatusCode::CONFLICT,
+            "a timer is already running for this user".to_owned(),
+        ),
+    })
+}
+
+/// Stop the timer a technician has running.
+pub async fn stop_timer(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+) -> ApiResult<LaborEntriesTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Technician],
+        "only technicians can log labor time",
+    )?;
+    LaborEntriesTableRecord::stop(&state.database, user.id)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                "this user has no timer running".to_owned(),
+            )
+        })
+}
+
+/// Record time a technician spent on a device of a ticket after the fact.
+pub async fn create_entry(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<LaborEntryRequest>,
+) -> ApiResult<LaborEntriesTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Technician],
+        "only technicians can log labor time",
+    )?;
+    if request.ended_at <= request.started_at {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "the entry must end after it starts".to_owned(),
+        ));
+    }
+    if request.ended_at > Utc::now() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "the entry cannot end in the future".to_owned(),
+        ));
+    }
+
+    LaborEntriesTableRecord::create(
+        &state.database,
+        request.ticket,
+        request.device,
+        user.id,
+        request.started_at,
+        request.ended_at,
+        request.notes.as_deref(),
+    )
+    .await
+    .map(Json)
+    .ok_or_else(|| device_not_found(request.ticket, request.device))
+}
+
+/// Delete one of a technician's labor entries, responding with whether it existed.
+pub async fn delete_entry(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<OwnedEntryParameters>,
+) -> Json<bool> {
+    Json(LaborEntriesTableRecord::delete_owned(&state.database, parameters.id, user.id).await)
+}
+
+/// Get the labor logged against a ticket, with totals for each of its devices.
+pub async fn ticket_labor(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<TicketParameter>,
+) -> Json<TicketLabor> {
+    let entries =
+        LaborEntriesTableRecord::query_for_ticket(&state.database, parameters.ticket).await;
+
+    let mut devices = BTreeMap::new();
+    for entry in &entries {
+        *devices.entry(entry.device).or_insert(0) += entry.minutes().unwrap_or(0);
+    }
+
+    Json(TicketLabor {
+        total_minutes: devices.values().sum(),
+        devices: devices
+            .into_iter()
+            .map(|(device, minutes)| DeviceLabor { device, minutes })
+            .collect(),
+        entries,
+    })
+}
+
+/// Compare the labor logged against each service to its labor fee, to find services which are
+/// priced too low for the time they take.
+pub async fn labor_report(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<LaborReportParameters>,
+) -> Json<Vec<ServiceLaborReport>> {
+    Json(
+        LaborReportRow::query_all(&state.database)
+            .await
+            .into_iter()
+            .map(|row| ServiceLaborReport {
+                underpriced: parameters
+                    .hourly_rate
+                    .zip(row.effective_hourly_rate)
+                    .map(|(target, effective)| effective < target),
+                row,
+            })
+            .collect(),
+    )
+}
+
+fn device_not_found(ticket: i32, device: i32) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("ticket {ticket} has no device {device}"),
+    )
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::Deserialize;
+
+use crate::api::{require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::locations::{
+    LocationsTableRecord, PartStockJunctionTableRecord, PartStockLevel,
+};
+use crate::database::tables::transfers::{TransferError, TransferGoods, TransfersTableRecord};
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct StockParameters {
+    location: Option<i32>,
+    part: Option<i32>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct TransfersParameters {
+    /// Only list transfers from or to this store.
+    location: Option<i32>,
+    /// Only list transfers which have not been received yet.
+    #[serde(default)]
+    pending: bool,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ReceiveTransferParameters {
+    id: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct LocationRequest {
+    display_name: String,
+    address: Option<String>,
+}
+
+/// A request to set how many of a part a store has on hand.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct StockRequest {
+    location: i32,
+    part: i32,
+    quantity: i32,
+    /// How few of the part the store can have before it is warned, which is kept as it was if left
+    /// out.
+    low_stock_threshold: Option<i32>,
+}
+
+/// A request to send either a number of a part or a device from one store to another.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct TransferRequest {
+    from_location: i32,
+    to_location: i32,
+    part: Option<i32>,
+    quantity: Option<i32>,
+    device: Option<i32>,
+    notes: Option<String>,
+}
+
+/// List every store, in the order they were added.
+pub async fn list_locations(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+) -> Json<Vec<LocationsTableRecord>> {
+    Json(LocationsTableRecord::query_all(&state.database).await)
+}
+
+/// Add a store.
+pub async fn create_location(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<LocationRequest>,
+) -> ApiResult<LocationsTableRecord> {
+    require_role(&user, &[UserRole::Admin], "only admins can add locations")?;
+    let display_name = request.display_name.trim();
+    if display_name.is_empty() {
+        return Err(invalid("a location needs a name"));
+    }
+
+    LocationsTableRecord::create(&state.database, display_name, request.address.as_deref())
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::CONFLICT,
+                format!("there is already a location named {display_name}"),
+            )
+        })
+}
+
+/// Get the stock of parts at each store, optionally only at one store or of one part.
+pub async fn list_stock(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<StockParameters>,
+) -> Json<Vec<PartStockLevel>> {
+    Json(PartStockLevel::query(&state.database, parameters.location, parameters.part).await)
+}
+
+/// Set how many of a part a store has on hand, such as after counting it.
+pub async fn set_stock(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<StockRequest>,
+) -> ApiResult<PartStockJunctionTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot change stock levels",
+    )?;
+    if request.quantity < 0 {
+        return Err(invalid("the quantity cannot be negative"));
+    }
+    if request
+        .low_stock_threshold
+        .is_some_and(|threshold| threshold < 0)
+    {
+        return Err(invalid("the low stock threshold cannot be negative"));
+    }
+
+    PartStockJunctionTableRecord::set(
+        &state.database,
+        request.location,
+        request.part,
+        request.quantity,
+        request.low_stock_threshold,
+    )
+    .await
+    .map(Json)
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!(
+                "no location with ID {} or no part with ID {}",
+                request.location, request.part
+            ),
+        )
+    })
+}
+
+/// List transfers between stores, newest first.
+pub async fn list_transfers(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<TransfersParameters>,
+) -> Json<Vec<TransfersTableRecord>> {
+    Json(
+        TransfersTableRecord::query(&state.database, parameters.location, parameters.pending).await,
+    )
+}
+
+/// Send parts or a device from one store to another.
+pub async fn send_transfer(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<TransferRequest>,
+) -> ApiResult<TransfersTableRecord> {
+    if request.from_location == request.to_location {
+        return Err(invalid("a transfer must be to a different location"));
+    }
+    let goods = match (request.part, request.quantity, request.device) {
+        (Some(_), Some(quantity), None) if quantity < 1 => {
+            return Err(invalid("the quantity must be at least one"))
+        }
+        (Some(part), Some(quantity), None) => TransferGoods::Part { part, quantity },
+        (Some(_), None, None) => return Err(invalid("a transfer of a part needs a quantity")),
+        (None, None, Some(device)) => TransferGoods::Device(device),
+        _ => {
+            return Err(invalid(
+                "a transfer is either of a part and a quantity or of a device",
+            ))
+        }
+    };
+
+    TransfersTableRecord::send(
+        &state.database,
+        request.from_location,
+        request.to_location,
+        goods,
+        request.notes.as_deref(),
+        user.id,
+    )
+    .await
+    .map(Json)
+    .map_err(|error| rejection(&state, error, request.from_location))
+}
+
+/// Receive a transfer at the store it was sent to.
+pub async fn receive_transfer(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<ReceiveTransferParameters>,
+) -> ApiResult<TransfersTableRecord> {
+    TransfersTableRecord::receive(&state.database, parameters.id, user.id)
+        .await
+        .map(Json)
+        .map_err(|error| rejection(&state, error, parameters.id))
+}
+
+/// Explain why a transfer could not be sent or received. `id` is the ID of the sending store when
+/// sending a transfer, and of the transfer when receiving one.
+fn rejection(state: &ServerState, error: TransferError, id: i32) -> (StatusCode, String) {
+    match error {
+        TransferError::LocationNotFound(location) => (
+            StatusCode::NOT_FOUND,
+            format!("no location with ID {location}"),
+        ),
+        TransferError::PartNotFound(part) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no part with ID {part}"),
+        ),
+        TransferError::DeviceNotFound(device) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no device with ID {device}"),
+        ),
+        TransferError::InsufficientStock { available } => (
+            StatusCode::CONFLICT,
+            format!("location {id} only has {available} of the part in stock"),
+        ),
+        TransferError::DeviceNotAtLocation(Some(location)) => (
+            StatusCode::CONFLICT,
+            format!("the device is at location {location}"),
+        ),
+        TransferError::DeviceNotAtLocation(None) => (
+            StatusCode::CONFLICT,
+            "the device is not at any location".to_owned(),
+        ),
+        TransferError::TransferNotFound => {
+            (StatusCode::NOT_FOUND, format!("no transfer with ID {id}"))
+        }
+        TransferError::AlreadyReceived(received_at) => (
+            StatusCode::CONFLICT,
+            format!(
+                "the transfer was already received on {}",
+                received_at
+                    .with_timezone(&state.localization.timezone())
+                    .format("%Y-%m-%d %H:%M")
+            ),
+        ),
+    }
+}
+
+fn invalid(message: &str) -> (StatusCode, String) {
+    (StatusCode::UNPROCESSABLE_ENTITY, message.to_owned())
+}
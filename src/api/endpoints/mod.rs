@@ -1,17 +1,38 @@
+pub mod appointments;
+pub mod assignments;
+pub mod cash_drawer;
+pub mod conditions;
+pub mod invoice_items;
+pub mod labor;
+pub mod locations;
+pub mod portal;
+pub mod prices;
 pub mod processed;
+pub mod profit;
+pub mod quotes;
+pub mod saved_views;
+pub mod sessions;
 pub mod utils;
+pub mod warranties;
 
+use std::borrow::Cow;
 use std::fmt::Debug;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
+use proc_macros::ApiSchema;
 use rust_decimal::Decimal;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
+use crate::api::localization::Localization;
+use crate::api::openapi::{object_schema, ApiSchema};
 use crate::database::shared_models::TicketStatus;
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 struct TagOption {
     name: &'static str,
+    /// The English label shown for the tag, which is translated for each response.
+    label: &'static str,
     color: CssColor,
 }
 
@@ -54,13 +75,37 @@ enum FrontendColumnDisplay {
     },
     Tag {
         name: &'static str,
-        options: &'static [TagOption],
+        options: Cow<'static, [TagOption]>,
     },
 }
 
-#[derive(Serialize)]
+impl FrontendColumnMetadata {
+    /// Translate the display name of the column, and the labels of its tags, if it has any.
+    fn localized(self, localization: &Localization) -> Self {
+        let display = match self.display {
+            FrontendColumnDisplay::Text { name, trimmable } => FrontendColumnDisplay::Text {
+                name: localization.translate(name),
+                trimmable,
+            },
+            FrontendColumnDisplay::Tag { name, options } => FrontendColumnDisplay::Tag {
+                name: localization.translate(name),
+                options: options
+                    .iter()
+                    .map(|option| TagOption {
+                        label: localization.translate(option.label),
+                        ..option.clone()
+                    })
+                    .collect(),
+            },
+        };
+
+        Self { display, ..self }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, ApiSchema)]
 #[serde(rename_all = "lowercase")]
-enum FrontendDataType {
+pub enum FrontendDataType {
     Integer,
     Decimal,
     String,
@@ -68,8 +113,67 @@ enum FrontendDataType {
     Tag,
 }
 
+impl FrontendDataType {
+    /// The filters the frontend can offer for columns of this type.
+    fn filters(&self) -> &'static [FilterOperator] {
+        match self {
+            Self::Integer | Self::Decimal | Self::Timestamp => &[
+                FilterOperator::Equals,
+                FilterOperator::NotEquals,
+                FilterOperator::GreaterThan,
+                FilterOperator::LessThan,
+                FilterOperator::Between,
+            ],
+            Self::String => &[
+                FilterOperator::Equals,
+                FilterOperator::NotEquals,
+                FilterOperator::Contains,
+            ],
+            Self::Tag => &[FilterOperator::OneOf],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOperator {
+    Equals,
+    NotEquals,
+    Contains,
+    GreaterThan,
+    LessThan,
+    Between,
+    OneOf,
+}
+
+/// A column of a resource as described by the `/metadata` endpoint, which is its
+/// [`FrontendColumnMetadata`] along with how it can be sorted and filtered.
+#[derive(Serialize)]
+struct ColumnDescription<'a> {
+    key: &'static str,
+    #[serde(flatten)]
+    metadata: &'a FrontendColumnMetadata,
+    sortable: bool,
+    filters: &'static [FilterOperator],
+}
+
+impl<'a> ColumnDescription<'a> {
+    fn new(key: &'static str, metadata: &'a FrontendColumnMetadata) -> Self {
+        Self {
+            key,
+            metadata,
+            sortable: true,
+            filters: metadata.data_type.filters(),
+        }
+    }
+}
+
 trait ViewFormat {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String>;
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        localization: &Localization,
+    ) -> Option<String>;
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -80,15 +184,37 @@ struct ViewCell<T: Debug + Clone + Serialize + ViewFormat> {
 }
 
 impl<T: Debug + Clone + Serialize + ViewFormat> ViewCell<T> {
-    fn new(value: T, formatting: &ColumnFormat) -> Self {
-        let formatted = value.format(formatting);
+    fn new(value: T, formatting: &ColumnFormat, localization: &Localization) -> Self {
+        let formatted = value.format(formatting, localization);
 
         Self { value, formatted }
     }
 }
 
+impl<T: Debug + Clone + Serialize + ViewFormat + ApiSchema> ApiSchema for ViewCell<T> {
+    fn schema() -> Value {
+        object_schema(
+            vec![
+                ("value", T::schema()),
+                (
+                    "formatted",
+                    json!({
+                        "type": "string",
+                        "description": "The value formatted for display, if the column has a format.",
+                    }),
+                ),
+            ],
+            &["value"],
+        )
+    }
+}
+
 impl ViewFormat for i32 {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String> {
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        _localization: &Localization,
+    ) -> Option<String> {
         match column_formatting {
             ColumnFormat::None => None,
             ColumnFormat::Id => Some(format!("#{:0>10}", self)),
@@ -98,17 +224,25 @@ impl ViewFormat for i32 {
 }
 
 impl ViewFormat for Decimal {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String> {
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        localization: &Localization,
+    ) -> Option<String> {
         match column_formatting {
             ColumnFormat::None => None,
-            ColumnFormat::Currency => Some(format!("${self}")),
+            ColumnFormat::Currency => Some(localization.format_currency(*self)),
             _ => panic!("Invalid formatting specifier for Decimal"),
         }
     }
 }
 
 impl ViewFormat for String {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String> {
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        _localization: &Localization,
+    ) -> Option<String> {
         match column_formatting {
             ColumnFormat::None => None,
             _ => panic!("Invalid formatting specifier for String"),
@@ -116,37 +250,54 @@ impl ViewFormat for String {
     }
 }
 
-impl ViewFormat for NaiveDateTime {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String> {
+impl ViewFormat for DateTime<Utc> {
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        localization: &Localization,
+    ) -> Option<String> {
         Some(match column_formatting {
-            ColumnFormat::Date => self.format("%m/%d/%Y %H:%M").to_string(),
-            _ => panic!("Invalid formatting specifier for NaiveDateTime"),
+            ColumnFormat::Date => self
+                .with_timezone(&localization.timezone())
+                .format(localization.date_format())
+                .to_string(),
+            _ => panic!("Invalid formatting specifier for DateTime"),
         })
     }
 }
 
 // ? Should there be a trait or some way to write this in a less-specific way?
 impl ViewFormat for TicketStatus {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String> {
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        localization: &Localization,
+    ) -> Option<String> {
         Some(match column_formatting {
-            ColumnFormat::Tag => match self {
-                TicketStatus::New => "New".to_string(),
-                TicketStatus::WaitingForParts => "Waiting for Parts".to_string(),
-                TicketStatus::WaitingForCustomer => "Waiting for Customer".to_string(),
-                TicketStatus::InRepair => "In Repair".to_string(),
-                TicketStatus::ReadyForPickup => "Ready for Pickup".to_string(),
-                TicketStatus::Closed => "Closed".to_string(),
-            },
+            ColumnFormat::Tag => localization
+                .translate(match self {
+                    TicketStatus::New => "New",
+                    TicketStatus::WaitingForParts => "Waiting for Parts",
+                    TicketStatus::WaitingForCustomer => "Waiting for Customer",
+                    TicketStatus::InRepair => "In Repair",
+                    TicketStatus::ReadyForPickup => "Ready for Pickup",
+                    TicketStatus::Closed => "Closed",
+                })
+                .to_owned(),
             _ => panic!("Invalid formatting specifier for ApiTag"),
         })
     }
 }
 
 impl<T: ViewFormat> ViewFormat for Option<T> {
-    fn format(&self, column_formatting: &ColumnFormat) -> Option<String> {
+    fn format(
+        &self,
+        column_formatting: &ColumnFormat,
+        localization: &Localization,
+    ) -> Option<String> {
         match self {
-            Some(value) => value.format(column_formatting),
-            None => Some("N/A".to_owned()),
+            Some(value) => value.format(column_formatting, localization),
+            None => Some(localization.translate("N/A").to_owned()),
         }
     }
 }
@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use axum::response::{Html, IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::api::endpoints::{ColumnFormat, ViewCell};
+use crate::api::localization::Localization;
+use crate::api::{ApiResult, CurrentUser};
+use crate::database::shared_models::TicketStatus;
+use crate::database::tables::business_hours::BusinessHoursTableRecord;
+use crate::database::tables::ticket_devices::TicketDeviceSummary;
+use crate::database::tables::tickets::TicketsTableRecord;
+use crate::database::views::tickets::TicketsViewRecord;
+use crate::ServerState;
+
+/// The shortest secret portal links can be signed with, since shorter ones could be guessed.
+pub const MIN_SECRET_LENGTH: usize = 32;
+
+/// How many bytes of the signature are kept in a portal token, which is plenty to make tokens
+/// unguessable while keeping links short.
+const SIGNATURE_LENGTH: usize = 16;
+
+/// What customers are told when their device is ready, unless the shop configures its own pickup
+/// instructions.
+const DEFAULT_PICKUP_INSTRUCTIONS: &str =
+    "Your device is ready. Please pick it up at the front desk during our opening hours.";
+
+/// Signs the tokens in links which are opened without signing in: customer status portal links
+/// and appointment calendar feeds.
+///
+/// A token is an ID followed by a signature of it, so customers can only see the tickets they were
+/// given links to, and the links cannot be guessed from IDs. Each kind of link is signed
+/// separately, so the token of a ticket's portal link cannot be used as a calendar feed token.
+#[derive(Clone)]
+pub struct PortalSigner {
+    secret: Vec<u8>,
+}
+
+impl PortalSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    /// Create the token for a ticket's portal link.
+    pub fn token(&self, ticket: i32) -> String {
+        self.sign("ticket", ticket)
+    }
+
+    /// Get the ticket a token was created for, or [`None`] if it was not signed by this server.
+    pub fn verify(&self, token: &str) -> Option<i32> {
+        self.verify_signed("ticket", token)
+    }
+
+    /// Create the token for a user's appointment calendar feed.
+    pub fn calendar_token(&self, user: i32) -> String {
+        self.sign("calendar", user)
+    }
+
+    /// Get the user a calendar feed token was created for, or [`None`] if it was not signed by
+    /// this server.
+    pub fn verify_calendar(&self, token: &str) -> Option<i32> {
+        self.verify_signed("calendar", token)
+    }
+
+    fn sign(&self, kind: &str, id: i32) -> String {
+        let signature = self.mac(kind, id).finalize().into_bytes();
+        let signature: String = signature[..SIGNATURE_LENGTH]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("{id}-{signature}")
+    }
+
+    fn verify_signed(&self, kind: &str, token: &str) -> Option<i32> {
+        let (id, signature) = token.split_once('-')?;
+        let id: i32 = id.parse().ok()?;
+        if signature.len() != SIGNATURE_LENGTH * 2 {
+            return None;
+        }
+        let signature = (0..signature.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(signature.get(index..index + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        self.mac(kind, id)
+            .verify_truncated_left(&signature)
+            .ok()
+            .map(|_| id)
+    }
+
+    fn mac(&self, kind: &str, id: i32) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(format!("{kind}:{id}").as_bytes());
+        mac
+    }
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct StaffTicketParameters {
+    ticket: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PortalTokenParameter {
+    token: String,
+}
+
+#[derive(Deserialize, Serialize, ApiSchema)]
+pub struct EstimatedCompletionRequest {
+    /// When the shop expects to finish the ticket, or [`None`] to stop showing an estimate.
+    estimated_completion: Option<DateTime<Utc>>,
+}
+
+/// The portal link of a ticket, for staff to send to its customer.
+#[derive(Serialize, ApiSchema)]
+pub struct PortalLink {
+    ticket: i32,
+    token: String,
+    /// The path of the status page, relative to the API's address.
+    page: String,
+    /// The path of the status as JSON, relative to the API's address.
+    status: String,
+}
+
+/// What a customer can see about their ticket on the status portal.
+///
+/// This only has fields which are safe to show to anyone with the link, so it leaves out the names
+/// of the customer and staff, notes and diagnostics.
+#[derive(Serialize, ApiSchema)]
+pub struct PortalStatus {
+    ticket: ViewCell<i32>,
+    status: ViewCell<TicketStatus>,
+    estimated_completion: ViewCell<Option<DateTime<Utc>>>,
+    devices: Vec<TicketDeviceSummary>,
+    balance_due: ViewCell<Decimal>,
+    updated_at: ViewCell<DateTime<Utc>>,
+    /// How to collect the repaired devices, once they are ready for pickup.
+    pickup_instructions: Option<String>,
+    opening_hours: Vec<BusinessHoursTableRecord>,
+}
+
+/// Get the portal link of a ticket. Any staff member can do this.
+pub async fn portal_link(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<StaffTicketParameters>,
+) -> ApiResult<PortalLink> {
+    if TicketsViewRecord::query_by_id(&state.database, parameters.ticket)
+        .await
+        .is_none()
+    {
+        return Err(ticket_not_found(parameters.ticket));
+    }
+
+    let token = state.portal_signer.token(parameters.ticket);
+    Ok(Json(PortalLink {
+        ticket: parameters.ticket,
+        page: format!("/portal?token={token}"),
+        status: format!("/portal/status?token={token}"),
+        token,
+    }))
+}
+
+/// Change when the shop expects to finish a ticket, as shown on its portal.
+pub async fn set_estimated_completion(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<StaffTicketParameters>,
+    Json(request): Json<EstimatedCompletionRequest>,
+) -> ApiResult<EstimatedCompletionRequest> {
+    match TicketsTableRecord::set_estimated_completion(
+        &state.database,
+        parameters.ticket,
+        request.estimated_completion,
+    )
+    .await
+    {
+        true => Ok(Json(request)),
+        false => Err(ticket_not_found(parameters.ticket)),
+    }
+}
+
+/// Get the status of the ticket a portal token was created for.
+pub async fn portal_status(
+    State(state): State<Arc<ServerState>>,
+    localization: Localization,
+    Query(parameters): Query<PortalTokenParameter>,
+) -> ApiResult<PortalStatus> {
+    find_portal_status(&state, &localization, &parameters.token)
+        .await
+        .map(Json)
+}
+
+/// Show the status of the ticket a portal token was created for as a web page.
+pub async fn portal_page(
+    State(state): State<Arc<ServerState>>,
+    localization: Localization,
+    Query(parameters): Query<PortalTokenParameter>,
+) -> Response {
+    match find_portal_status(&state, &localization, &parameters.token).await {
+        Ok(status) => Html(render_page(&status, &localization)).into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+async fn find_portal_status(
+    state: &ServerState,
+    localization: &Localization,
+    token: &str,
+) -> Result<PortalStatus, (StatusCode, String)> {
+    // * Invalid tokens and deleted tickets are rejected the same way, so that the response does
+    // * not reveal which tickets exist.
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            "this status link is not valid".to_owned(),
+        )
+    };
+    let id = state.portal_signer.verify(token).ok_or_else(not_found)?;
+    let ticket = TicketsViewRecord::query_by_id(&state.database, id)
+        .await
+        .ok_or_else(not_found)?;
+
+    let pickup_instructions = (ticket.status == TicketStatus::ReadyForPickup).then(|| {
+        state.pickup_instructions.clone().unwrap_or_else(|| {
+            localization
+                .translate(DEFAULT_PICKUP_INSTRUCTIONS)
+                .to_owned()
+        })
+    });
+
+    Ok(PortalStatus {
+        ticket: ViewCell::new(ticket.id, &ColumnFormat::Id, localization),
+        status: ViewCell::new(ticket.status, &ColumnFormat::Tag, localization),
+        estimated_completion: ViewCell::new(
+            ticket.estimated_completion,
+            &ColumnFormat::Date,
+            localization,
+        ),
+        devices: TicketDeviceSummary::query_for_ticket(&state.database, id).await,
+        balance_due: ViewCell::new(
+            ticket.balance.max(Decimal::ZERO),
+            &ColumnFormat::Currency,
+            localization,
+        ),
+        updated_at: ViewCell::new(ticket.updated_at, &ColumnFormat::Date, localization),
+        pickup_instructions,
+        opening_hours: BusinessHoursTableRecord::query_week(&state.database).await,
+    })
+}
+
+fn render_page(status: &PortalStatus, localization: &Localization) -> String {
+    let formatted =
+        |cell_formatted: &Option<String>| escape_html(cell_formatted.as_deref().unwrap_or(""));
+
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <meta name=\"robots\" content=\"noindex\">\n<title>{title}</title>\n</head>\n<body>\n\
+         <h1>{title} {ticket}</h1>\n<p><strong>{status}</strong></p>\n<dl>\n",
+        title = escape_html(localization.translate("Repair Status")),
+        ticket = formatted(&status.ticket.formatted),
+        status = formatted(&status.status.formatted),
+    );
+    for (label, value) in [
+        (
+            "Estimated Completion",
+            formatted(&status.estimated_completion.formatted),
+        ),
+        ("Balance Due", formatted(&status.balance_due.formatted)),
+        ("Updated", formatted(&status.updated_at.formatted)),
+    ] {
+        page.push_str(&format!(
+            "<dt>{}</dt><dd>{value}</dd>\n",
+            escape_html(localization.translate(label)),
+        ));
+    }
+    page.push_str("</dl>\n");
+
+    if !status.devices.is_empty() {
+        page.push_str(&format!(
+            "<h2>{}</h2>\n<ul>\n",
+            escape_html(localization.translate("Devices")),
+        ));
+        for device in &status.devices {
+            page.push_str(&format!("<li>{}", escape_html(&device.model)));
+            if let Some(service) = &device.service {
+                page.push_str(&format!(" &ndash; {}", escape_html(service)));
+            }
+            page.push_str("</li>\n");
+        }
+        page.push_str("</ul>\n");
+    }
+
+    if let Some(instructions) = &status.pickup_instructions {
+        page.push_str(&format!("<p>{}</p>\n", escape_html(instructions)));
+    }
+    page.push_str("</body>\n</html>\n");
+
+    page
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn ticket_not_found(ticket: i32) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("no ticket with ID {ticket}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn tokens_verify_as_the_ticket_they_were_created_for() {
+        let signer = PortalSigner::new(SECRET);
+        for ticket in [0, 1, 42, i32::MAX] {
+            assert_eq!(signer.verify(&signer.token(ticket)), Some(ticket));
+        }
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let signer = PortalSigner::new(SECRET);
+        let token = signer.token(42);
+        let (_, signature) = token.split_once('-').unwrap();
+        assert_eq!(signer.verify(&format!("43-{signature}")), None);
+
+        // * Change the last digit of the signature to any other hex digit.
+        let last = signature.chars().last().unwrap();
+        let changed = if last == '0' { '1' } else { '0' };
+        let tampered = format!("{}{changed}", &token[..token.len() - 1]);
+        assert_eq!(signer.verify(&tampered), None);
+    }
+
+    #[test]
+    fn malformed_signatures_are_rejected() {
+        let signer = PortalSigner::new(SECRET);
+        let token = signer.token(42);
+        assert_eq!(signer.verify(&token[..token.len() - 2]), None);
+        assert_eq!(signer.verify(&format!("{token}00")), None);
+        assert_eq!(
+            signer.verify(&format!("42-{}", "zz".repeat(SIGNATURE_LENGTH))),
+            None
+        );
+        assert_eq!(signer.verify("42"), None);
+        assert_eq!(signer.verify(""), None);
+
+        // * Non-ASCII signatures of the right length must not panic when split into byte pairs.
+        let multibyte = format!("42-{}", "é".repeat(SIGNATURE_LENGTH));
+        assert_eq!(signer.verify(&multibyte), None);
+    }
+
+    #[test]
+    fn tokens_from_another_secret_are_rejected() {
+        let signer = PortalSigner::new(SECRET);
+        let other_tenant = PortalSigner::new("fedcba9876543210fedcba9876543210");
+        assert_eq!(signer.verify(&other_tenant.token(42)), None);
+        assert_eq!(
+            signer.verify_calendar(&other_tenant.calendar_token(42)),
+            None
+        );
+    }
+
+    #[test]
+    fn ticket_and_calendar_tokens_are_not_interchangeable() {
+        let signer = PortalSigner::new(SECRET);
+        assert_eq!(signer.verify_calendar(&signer.token(42)), None);
+        assert_eq!(signer.verify(&signer.calendar_token(42)), None);
+        assert_eq!(signer.verify_calendar(&signer.calendar_token(42)), Some(42));
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::api::{require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::{ItemType, UserRole};
+use crate::database::tables::items::{
+    AdjustmentPreview, NewPrice, PriceAdjustment, PriceChange, PriceError, PriceHistory,
+    PriceHistoryEntry,
+};
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PriceItemParameter {
+    item: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ScheduledPriceParameters {
+    item: i32,
+    /// The ID of the scheduled price in the item's price history.
+    id: i32,
+}
+
+/// A request to change the price of an item. Products take a cost and a price, and services take
+/// a base fee and a labor fee. Anything left out stays as it is.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct NewPriceRequest {
+    item: i32,
+    /// When the price takes effect, which defaults to now.
+    effective_at: Option<DateTime<Utc>>,
+    cost: Option<Decimal>,
+    price: Option<Decimal>,
+    base_fee: Option<Decimal>,
+    labor_fee: Option<Decimal>,
+}
+
+/// A request to change the prices of many items by a percentage.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct PriceAdjustmentRequest {
+    /// How much to raise prices by, or lower them by if negative.
+    percent: Decimal,
+    /// When the new prices take effect, which defaults to now.
+    effective_at: Option<DateTime<Utc>>,
+    item_type: Option<ItemType>,
+    service_type: Option<i32>,
+    device_category: Option<i32>,
+    device_manufacturer: Option<i32>,
+}
+
+/// Get every price an item has had or is scheduled to have, with the margins of products.
+pub async fn price_history(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<PriceItemParameter>,
+) -> ApiResult<PriceHistory> {
+    PriceHistory::query_for_item(&state.database, parameters.item)
+        .await
+        .map(Json)
+        .ok_or_else(|| item_not_found(parameters.item))
+}
+
+/// Change the price of an item now or at a time in the future.
+pub async fn schedule_price(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<NewPriceRequest>,
+) -> ApiResult<PriceHistoryEntry> {
+    require_role(&user, &[UserRole::Admin], "only admins can change prices")?;
+    let effective_at = effective_at(request.effective_at)?;
+    let new_price = NewPrice {
+        cost: request.cost,
+        price: request.price,
+        base_fee: request.base_fee,
+        labor_fee: request.labor_fee,
+    };
+    let values = [
+        new_price.cost,
+        new_price.price,
+        new_price.base_fee,
+        new_price.labor_fee,
+    ];
+    if values.iter().all(Option::is_none) {
+        return Err(invalid("a new price needs at least one value"));
+    }
+    if values.iter().flatten().any(Decimal::is_sign_negative) {
+        return Err(invalid("prices cannot be negative"));
+    }
+
+    PriceHistory::schedule(&state.database, request.item, effective_at, &new_price)
+        .await
+        .map(Json)
+        .map_err(|error| rejection(error, request.item))
+}
+
+/// Cancel a price change which has yet to take effect.
+pub async fn cancel_price(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<ScheduledPriceParameters>,
+) -> ApiResult<bool> {
+    require_role(&user, &[UserRole::Admin], "only admins can change prices")?;
+    PriceHistory::cancel(&state.database, parameters.item, parameters.id)
+        .await
+        .map(|_| Json(true))
+        .map_err(|error| rejection(error, parameters.item))
+}
+
+/// Preview changing the prices of many items by a percentage, including the open quotes and
+/// invoices with items that would change.
+pub async fn preview_adjustment(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Json(request): Json<PriceAdjustmentRequest>,
+) -> ApiResult<AdjustmentPreview> {
+    let adjustment = adjustment(request)?;
+    Ok(Json(adjustment.preview(&state.database).await))
+}
+
+/// Change the prices of many items by a percentage, now or at a time in the future.
+pub async fn adjust_prices(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<PriceAdjustmentRequest>,
+) -> ApiResult<Vec<PriceChange>> {
+    require_role(&user, &[UserRole::Admin], "only admins can change prices")?;
+    let adjustment = adjustment(request)?;
+    Ok(Json(adjustment.apply(&state.database).await))
+}
+
+fn adjustment(request: PriceAdjustmentRequest) -> Result<PriceAdjustment, (StatusCode, String)> {
+    if request.percent.is_zero() {
+        return Err(invalid("the adjustment must change prices"));
+    }
+    if request.percent <= Decimal::from(-100) {
+        return Err(invalid("prices cannot be lowered by 100% or more"));
+    }
+
+    Ok(PriceAdjustment {
+        percent: request.percent,
+        effective_at: effective_at(request.effective_at)?,
+        item_type: request.item_type,
+        service_type: request.service_type,
+        device_category: request.device_category,
+        device_manufacturer: request.device_manufacturer,
+    })
+}
+
+/// Get when a price change takes effect. Changes cannot take effect in the past, since that would
+/// rewrite the price history that quotes and reports rely on.
+fn effective_at(
+    effective_at: Option<DateTime<Utc>>,
+) -> Result<DateTime<Utc>, (StatusCode, String)> {
+    let now = Utc::now();
+    match effective_at {
+        Some(effective_at) if effective_at < now => {
+            Err(invalid("price changes cannot take effect in the past"))
+        }
+        Some(effective_at) => Ok(effective_at),
+        None => Ok(now),
+    }
+}
+
+fn rejection(error: PriceError, item: i32) -> (StatusCode, String) {
+    match error {
+        PriceError::ItemNotFound => item_not_found(item),
+        PriceError::WrongFields(ItemType::Product) => {
+            invalid("products have a cost and a price rather than fees")
+        }
+        PriceError::WrongFields(ItemType::Service) => {
+            invalid("services have a base fee and a labor fee rather than a cost and a price")
+        }
+        PriceError::EntryNotFound => (
+            StatusCode::NOT_FOUND,
+            format!("item {item} has no such price"),
+        ),
+        PriceError::AlreadyInEffect => (
+            StatusCode::CONFLICT,
+            "the price has already taken effect".to_owned(),
+        ),
+    }
+}
+
+fn item_not_found(item: i32) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("no item with ID {item}"))
+}
+
+fn invalid(message: &str) -> (StatusCode, String) {
+    (StatusCode::UNPROCESSABLE_ENTITY, message.to_owned())
+}
@@ -23,4 +23,6 @@ pub struct DevicesResourceRecord {
     model: ViewCell<String>,
     #[col_format(preset = "string")]
     owner: ViewCell<Option<String>>,
+    #[col_format(preset = "string")]
+    location: ViewCell<Option<String>>,
 }
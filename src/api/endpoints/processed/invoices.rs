@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::Serialize;
 
@@ -22,11 +22,13 @@ pub struct InvoicesResourceRecord {
     #[col_format(preset = "id")]
     id: ViewCell<i32>,
     #[col_format(preset = "date", display_name = "Created")]
-    created_at: ViewCell<NaiveDateTime>,
+    created_at: ViewCell<DateTime<Utc>>,
     #[col_format(preset = "date", display_name = "Updated")]
-    updated_at: ViewCell<NaiveDateTime>,
+    updated_at: ViewCell<DateTime<Utc>>,
     #[col_format(preset = "currency")]
     invoice_total: ViewCell<Decimal>,
     #[col_format(preset = "currency")]
     payment_total: ViewCell<Decimal>,
+    #[col_format(preset = "string")]
+    location: ViewCell<String>,
 }
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::Serialize;
 
@@ -13,6 +13,7 @@ use crate::database::Relation;
 const STATUS_TAG_OPTIONS: &[TagOption] = &[
     TagOption {
         name: "new",
+        label: "New",
         color: CssColor::Preset {
             name: "royalblue",
             opacity: 0.45,
@@ -20,6 +21,7 @@ const STATUS_TAG_OPTIONS: &[TagOption] = &[
     },
     TagOption {
         name: "waiting_for_parts",
+        label: "Waiting for Parts",
         color: CssColor::Preset {
             name: "red",
             opacity: 0.37,
@@ -27,6 +29,7 @@ const STATUS_TAG_OPTIONS: &[TagOption] = &[
     },
     TagOption {
         name: "waiting_for_customer",
+        label: "Waiting for Customer",
         color: CssColor::Preset {
             name: "yellow",
             opacity: 0.43,
@@ -34,6 +37,7 @@ const STATUS_TAG_OPTIONS: &[TagOption] = &[
     },
     TagOption {
         name: "in_repair",
+        label: "In Repair",
         color: CssColor::Preset {
             name: "orange",
             opacity: 0.54,
@@ -41,6 +45,7 @@ const STATUS_TAG_OPTIONS: &[TagOption] = &[
     },
     TagOption {
         name: "ready_for_pickup",
+        label: "Ready for Pickup",
         color: CssColor::Preset {
             name: "limegreen",
             opacity: 0.37,
@@ -48,6 +53,7 @@ const STATUS_TAG_OPTIONS: &[TagOption] = &[
     },
     TagOption {
         name: "closed",
+        label: "Closed",
         color: CssColor::Preset {
             name: "gray",
             opacity: 0.45,
@@ -71,10 +77,16 @@ pub struct TicketsResourceRecord {
     status: ViewCell<TicketStatus>,
     #[col_format(preset = "string")]
     customer: ViewCell<Option<String>>,
+    #[col_format(preset = "string")]
+    assignee: ViewCell<Option<String>>,
     #[col_format(preset = "currency")]
     balance: ViewCell<Decimal>,
+    #[col_format(preset = "date", display_name = "Estimated Completion")]
+    estimated_completion: ViewCell<Option<DateTime<Utc>>>,
     #[col_format(preset = "date", display_name = "Created")]
-    created_at: ViewCell<NaiveDateTime>,
+    created_at: ViewCell<DateTime<Utc>>,
     #[col_format(preset = "date", display_name = "Updated")]
-    updated_at: ViewCell<NaiveDateTime>,
+    updated_at: ViewCell<DateTime<Utc>>,
+    #[col_format(preset = "string")]
+    location: ViewCell<String>,
 }
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::Deserialize;
+
+use crate::api::CurrentUser;
+use crate::database::tables::invoices::{ProfitGrouping, ProfitReportRow, ReportPeriod};
+use crate::ServerState;
+
+/// How many days the profit report covers when no start date is given.
+const DEFAULT_REPORT_DAYS: i64 = 30;
+
+const CSV_HEADER: &str =
+    "group_id,group_name,invoices,revenue,cost_of_goods,gross_margin,margin_percent,labor_minutes";
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ProfitReportParameters {
+    /// The first day of invoices to report on, which defaults to 30 days before the last.
+    from: Option<NaiveDate>,
+    /// The last day of invoices to report on, which defaults to today.
+    to: Option<NaiveDate>,
+    /// What to group the report by, which defaults to the period.
+    group_by: Option<ProfitGrouping>,
+    /// How long each period is when grouping by period, which defaults to a month.
+    period: Option<ReportPeriod>,
+}
+
+/// Report the revenue, cost of goods, gross margin and labor of the invoices created over a span
+/// of days.
+pub async fn profit_report(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<ProfitReportParameters>,
+) -> Result<Json<Vec<ProfitReportRow>>, (StatusCode, String)> {
+    query_report(&state, &parameters).await.map(Json)
+}
+
+/// Export the profit report as CSV, for spreadsheets and accounting software.
+pub async fn profit_report_csv(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<ProfitReportParameters>,
+) -> Response {
+    let rows = match query_report(&state, &parameters).await {
+        Ok(rows) => rows,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let mut body = format!("{CSV_HEADER}\r\n");
+    for row in rows {
+        let fields = [
+            row.group_id.map(|id| id.to_string()),
+            row.group_name.as_deref().map(csv_field),
+            Some(row.invoices.to_string()),
+            Some(row.revenue.to_string()),
+            Some(row.cost_of_goods.to_string()),
+            Some(row.gross_margin.to_string()),
+            row.margin_percent.map(|percent| percent.to_string()),
+            Some(row.labor_minutes.to_string()),
+        ];
+        body.push_str(&fields.map(Option::unwrap_or_default).join(","));
+        body.push_str("\r\n");
+    }
+
+    (
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (CONTENT_DISPOSITION, "attachment; filename=\"profit.csv\""),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+async fn query_report(
+    state: &ServerState,
+    parameters: &ProfitReportParameters,
+) -> Result<Vec<ProfitReportRow>, (StatusCode, String)> {
+    let timezone = state.localization.timezone();
+    let to = parameters
+        .to
+        .unwrap_or_else(|| Utc::now().with_timezone(&timezone).date_naive());
+    let from = parameters
+        .from
+        .unwrap_or(to - Duration::days(DEFAULT_REPORT_DAYS));
+    if from > to {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "the report cannot start after it ends".to_owned(),
+        ));
+    }
+
+    let start_of = |date: NaiveDate| -> DateTime<Utc> {
+        timezone
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+    Ok(ProfitReportRow::query(
+        &state.database,
+        start_of(from),
+        start_of(to + Duration::days(1)),
+        parameters.group_by.unwrap_or(ProfitGrouping::Period),
+        parameters.period.unwrap_or(ReportPeriod::Month),
+        timezone.name(),
+    )
+    .await)
+}
+
+/// Quote a CSV field if it contains anything which would otherwise end it early.
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_owned(),
+    }
+}
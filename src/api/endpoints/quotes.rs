@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use chrono::{Duration, Utc};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::Deserialize;
+
+use crate::api::{find_location, require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::quotes::{QuoteDetails, QuoteError};
+use crate::ServerState;
+
+/// How many days customers have to decide on a quote when no validity is given.
+const DEFAULT_VALID_DAYS: i64 = 30;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct QuotesParameters {
+    customer: Option<i32>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct QuoteIdParameter {
+    id: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct ConvertQuoteParameters {
+    id: i32,
+    /// The store the repair is done at, which defaults to the first one.
+    location: Option<i32>,
+}
+
+/// A device to quote a repair for, along with the service it would be repaired with.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct QuotedDevice {
+    device: i32,
+    service: i32,
+}
+
+/// A request to quote a repair for a customer.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct QuoteRequest {
+    customer: i32,
+    #[serde(default)]
+    devices: Vec<QuotedDevice>,
+    /// Items to quote besides the services of the devices, such as parts or accessories.
+    #[serde(default)]
+    items: Vec<i32>,
+    /// How many days the customer has to decide on the quote.
+    valid_days: Option<i64>,
+    notes: Option<String>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct QuoteDecisionRequest {
+    approved: bool,
+}
+
+/// List quotes, newest first, optionally only those of a customer.
+pub async fn list_quotes(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<QuotesParameters>,
+) -> Json<Vec<QuoteDetails>> {
+    Json(QuoteDetails::query_all(&state.database, parameters.customer).await)
+}
+
+/// Get a quote by its ID.
+pub async fn get_quote(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<QuoteIdParameter>,
+) -> ApiResult<QuoteDetails> {
+    QuoteDetails::query_by_id(&state.database, parameters.id)
+        .await
+        .map(Json)
+        .ok_or_else(|| not_found(parameters.id))
+}
+
+/// Quote a repair for a customer. Every item is priced as of now, and keeps that price even if
+/// prices change before the quote is converted. Only admins and front desk staff can do this.
+pub async fn create_quote(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Json(request): Json<QuoteRequest>,
+) -> ApiResult<QuoteDetails> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot quote repairs",
+    )?;
+    if request.devices.is_empty() && request.items.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "a quote needs at least one device or item".to_owned(),
+        ));
+    }
+    let valid_days = request.valid_days.unwrap_or(DEFAULT_VALID_DAYS);
+    if valid_days < 1 {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "quotes must be valid for at least a day".to_owned(),
+        ));
+    }
+
+    let devices: Vec<(i32, i32)> = request
+        .devices
+        .iter()
+        .map(|device| (device.device, device.service))
+        .collect();
+    QuoteDetails::create(
+        &state.database,
+        request.customer,
+        &devices,
+        &request.items,
+        Utc::now() + Duration::days(valid_days),
+        request.notes.as_deref(),
+    )
+    .await
+    .map(Json)
+    .map_err(|error| rejection(&state, error, request.customer))
+}
+
+/// Record that the customer approved or declined a quote. Only admins and front desk staff can do
+/// this.
+pub async fn decide_quote(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<QuoteIdParameter>,
+    Json(request): Json<QuoteDecisionRequest>,
+) -> ApiResult<QuoteDetails> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot quote repairs",
+    )?;
+    QuoteDetails::decide(&state.database, parameters.id, request.approved)
+        .await
+        .map(Json)
+        .map_err(|error| rejection(&state, error, parameters.id))
+}
+
+/// Turn a quote into a ticket for its devices and an invoice charging its quoted prices. Only admins
+/// and front desk staff can do this.
+pub async fn convert_quote(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<ConvertQuoteParameters>,
+) -> ApiResult<QuoteDetails> {
+    require_role(
+        &user,
+        &[UserRole::Admin, UserRole::FrontDesk],
+        "technicians cannot quote repairs",
+    )?;
+    let location = find_location(&state, parameters.location).await?.id;
+    QuoteDetails::convert(&state.database, parameters.id, location)
+        .await
+        .map(Json)
+        .map_err(|error| rejection(&state, error, parameters.id))
+}
+
+fn not_found(id: i32) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("no quote with ID {id}"))
+}
+
+/// Explain why a quote could not be created or changed. `id` is the ID of the customer when
+/// creating a quote, and of the quote otherwise.
+fn rejection(state: &ServerState, error: QuoteError, id: i32) -> (StatusCode, String) {
+    match error {
+        QuoteError::CustomerNotFound => {
+            (StatusCode::NOT_FOUND, format!("no customer with ID {id}"))
+        }
+        QuoteError::DeviceNotFound(device) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no device with ID {device}"),
+        ),
+        QuoteError::DeviceNotOwned(device) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("device {device} belongs to another customer"),
+        ),
+        QuoteError::ServiceNotFound(service) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no service with ID {service}"),
+        ),
+        QuoteError::ItemNotFound(item) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no item with ID {item}"),
+        ),
+        QuoteError::QuoteNotFound => not_found(id),
+        QuoteError::Expired(expires_at) => (
+            StatusCode::CONFLICT,
+            format!(
+                "the quote expired on {}",
+                expires_at
+                    .with_timezone(&state.localization.timezone())
+                    .format("%Y-%m-%d")
+            ),
+        ),
+        QuoteError::AlreadyDecided(status) => (
+            StatusCode::CONFLICT,
+            format!(
+                "the quote has already been {}",
+                serde_json::to_value(status).unwrap().as_str().unwrap()
+            ),
+        ),
+    }
+}
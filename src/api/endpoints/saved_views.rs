@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::extract::{Extension, Json, Query, State};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::api::endpoints::FilterOperator;
+use crate::api::{require_role, ApiResult, CurrentUser, ResourceCatalog, ResourceColumn};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::saved_views::{SavedViewLayout, SavedViewsTableRecord};
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct UserViewsParameters {
+    resource: String,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct OwnedViewParameters {
+    id: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct RoleDefaultParameters {
+    role: UserRole,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct RoleDefaultDeleteParameters {
+    role: UserRole,
+    resource: String,
+}
+
+/// The content of a view sent by the frontend when saving it.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct SavedViewRequest {
+    resource: String,
+    name: String,
+    layout: SavedViewLayout,
+}
+
+/// Every view of a resource available to a user, meaning their own views and the default view for
+/// their role.
+#[derive(Serialize, ApiSchema)]
+pub struct SavedViewList {
+    role_default: Option<SavedViewsTableRecord>,
+    views: Vec<SavedViewsTableRecord>,
+}
+
+/// Get every view of a resource available to a user.
+pub async fn list_views(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<UserViewsParameters>,
+) -> ApiResult<SavedViewList> {
+    Ok(Json(SavedViewList {
+        role_default: SavedViewsTableRecord::query_role_default(
+            &state.database,
+            user.role,
+            &parameters.resource,
+        )
+        .await,
+        views: SavedViewsTableRecord::query_for_user(
+            &state.database,
+            user.id,
+            &parameters.resource,
+        )
+        .await,
+    }))
+}
+
+/// Save a new view for a user.
+pub async fn create_view(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Extension(catalog): Extension<Arc<ResourceCatalog>>,
+    Json(request): Json<SavedViewRequest>,
+) -> ApiResult<SavedViewsTableRecord> {
+    request.validate(&catalog)?;
+    Ok(Json(
+        SavedViewsTableRecord::create(
+            &state.database,
+            user.id,
+            &request.resource,
+            &request.name,
+            request.layout,
+        )
+        .await,
+    ))
+}
+
+/// Replace one of a user's views.
+pub async fn update_view(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Extension(catalog): Extension<Arc<ResourceCatalog>>,
+    Query(parameters): Query<OwnedViewParameters>,
+    Json(request): Json<SavedViewRequest>,
+) -> ApiResult<SavedViewsTableRecord> {
+    request.validate(&catalog)?;
+    SavedViewsTableRecord::update(
+        &state.database,
+        parameters.id,
+        user.id,
+        &request.resource,
+        &request.name,
+        request.layout,
+    )
+    .await
+    .map(Json)
+    .ok_or_else(|| view_not_found(parameters.id))
+}
+
+/// Delete one of a user's views, responding with whether it existed.
+pub async fn delete_view(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<OwnedViewParameters>,
+) -> Json<bool> {
+    Json(SavedViewsTableRecord::delete_owned(&state.database, parameters.id, user.id).await)
+}
+
+/// Set the default view of a resource for every user with a role. Only admins can do this.
+pub async fn set_role_default(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Extension(catalog): Extension<Arc<ResourceCatalog>>,
+    Query(parameters): Query<RoleDefaultParameters>,
+    Json(request): Json<SavedViewRequest>,
+) -> ApiResult<SavedViewsTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin],
+        "only admins can change the default views of a role",
+    )?;
+    request.validate(&catalog)?;
+    Ok(Json(
+        SavedViewsTableRecord::set_role_default(
+            &state.database,
+            parameters.role,
+            &request.resource,
+            &request.name,
+            request.layout,
+        )
+        .await,
+    ))
+}
+
+/// Remove the default view of a resource for a role, responding with whether one was set. Only
+/// admins can do this.
+pub async fn delete_role_default(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<RoleDefaultDeleteParameters>,
+) -> ApiResult<bool> {
+    require_role(
+        &user,
+        &[UserRole::Admin],
+        "only admins can change the default views of a role",
+    )?;
+    Ok(Json(
+        SavedViewsTableRecord::delete_role_default(
+            &state.database,
+            parameters.role,
+            &parameters.resource,
+        )
+        .await,
+    ))
+}
+
+fn view_not_found(id: i32) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("no view with ID {id} belongs to this user"),
+    )
+}
+
+impl SavedViewRequest {
+    /// Check that the view is named and only refers to columns, sorts and filters that its
+    /// resource actually has.
+    fn validate(&self, catalog: &ResourceCatalog) -> Result<(), (StatusCode, String)> {
+        let invalid = |message: String| (StatusCode::UNPROCESSABLE_ENTITY, message);
+
+        if self.name.trim().is_empty() {
+            return Err(invalid("the view must have a name".to_owned()));
+        }
+
+        let columns = catalog
+            .columns(&self.resource)
+            .ok_or_else(|| invalid(format!("there is no resource `{}`", self.resource)))?;
+        let find_column = |key: &str| -> Result<&ResourceColumn, (StatusCode, String)> {
+            columns
+                .iter()
+                .find(|column| column.key == key)
+                .ok_or_else(|| invalid(format!("`{}` has no column `{key}`", self.resource)))
+        };
+
+        if self.layout.columns.is_empty() {
+            return Err(invalid("the view must show at least one column".to_owned()));
+        }
+
+        let mut shown = HashSet::new();
+        for column in &self.layout.columns {
+            find_column(&column.key)?;
+            if !shown.insert(column.key.as_str()) {
+                return Err(invalid(format!("column `{}` is shown twice", column.key)));
+            }
+            if column.width == Some(0) {
+                return Err(invalid(format!("column `{}` has no width", column.key)));
+            }
+        }
+
+        if let Some(sort) = &self.layout.sort {
+            if !find_column(&sort.column)?.sortable {
+                return Err(invalid(format!(
+                    "column `{}` cannot be sorted",
+                    sort.column
+                )));
+            }
+        }
+
+        for filter in &self.layout.filters {
+            if !find_column(&filter.column)?
+                .filters
+                .contains(&filter.operator)
+            {
+                return Err(invalid(format!(
+                    "column `{}` cannot be filtered with `{}`",
+                    filter.column,
+                    serde_json::to_value(filter.operator)
+                        .unwrap()
+                        .as_str()
+                        .unwrap(),
+                )));
+            }
+
+            let well_formed = match filter.operator {
+                FilterOperator::Between => filter.value.as_array().is_some_and(|v| v.len() == 2),
+                FilterOperator::OneOf => filter.value.is_array(),
+                _ => !filter.value.is_array() && !filter.value.is_object(),
+            };
+            if !well_formed {
+                return Err(invalid(format!(
+                    "the value of the filter on column `{}` does not match its operator",
+                    filter.column,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Utc};
+use http::header::SET_COOKIE;
+use http::{HeaderMap, StatusCode};
+use proc_macros::ApiSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{session_token, CurrentUser, SESSION_COOKIE};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::sessions::SessionsTableRecord;
+use crate::database::tables::users::UsersTableRecord;
+use crate::ServerState;
+
+/// How long a session lasts before its user has to sign in again.
+const SESSION_LIFETIME_HOURS: i64 = 12;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+/// A session started by signing in, with the token which authenticates it.
+#[derive(Serialize, ApiSchema)]
+pub struct Session {
+    token: String,
+    expires_at: DateTime<Utc>,
+    user: SessionUser,
+}
+
+/// The user a session belongs to, without their password hash.
+#[derive(Serialize, ApiSchema)]
+pub struct SessionUser {
+    id: i32,
+    username: String,
+    display_name: String,
+    role: UserRole,
+}
+
+impl From<UsersTableRecord> for SessionUser {
+    fn from(user: UsersTableRecord) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            display_name: user.display_name,
+            role: user.role,
+        }
+    }
+}
+
+/// Sign in with a username and password, starting a session.
+///
+/// The session's token is returned for clients to send as an `Authorization: Bearer` header, and
+/// is also set as a cookie for browsers.
+pub async fn login(
+    State(state): State<Arc<ServerState>>,
+    Json(request): Json<LoginRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let user =
+        UsersTableRecord::authenticate(&state.database, &request.username, &request.password)
+            .await
+            .ok_or_else(|| {
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "the username or password is incorrect".to_owned(),
+                )
+            })?;
+
+    let lifetime = Duration::hours(SESSION_LIFETIME_HOURS);
+    let (session, token) = SessionsTableRecord::create(&state.database, user.id, lifetime).await;
+    let cookie = format!(
+        "{SESSION_COOKIE}={token}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Strict",
+        lifetime.num_seconds()
+    );
+
+    Ok((
+        [(SET_COOKIE, cookie)],
+        Json(Session {
+            token,
+            expires_at: session.expires_at,
+            user: user.into(),
+        }),
+    )
+        .into_response())
+}
+
+/// End the session a request was made with, responding with whether it was still current.
+pub async fn logout(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    headers: HeaderMap,
+) -> Response {
+    let ended =
+        SessionsTableRecord::delete(&state.database, session_token(&headers).unwrap()).await;
+    let cookie = format!("{SESSION_COOKIE}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Strict");
+    ([(SET_COOKIE, cookie)], Json(ended)).into_response()
+}
+
+/// Get the user signed in with the session a request was made with.
+pub async fn current_user(CurrentUser(user): CurrentUser) -> Json<SessionUser> {
+    Json(user.into())
+}
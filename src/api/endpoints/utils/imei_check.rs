@@ -6,7 +6,8 @@ use serde::{Deserialize, Serialize};
 
 use proc_macros::FromRecord;
 
-use crate::api::{IdParameter, ServeRecordJson};
+use crate::api::localization::Localization;
+use crate::api::{CurrentUser, IdParameter, ServeRecordJson};
 use crate::database::tables::type_allocation_codes::TypeAllocationCodesTableRecord;
 use crate::database::{Record, SingleInsert};
 use crate::ServerState;
@@ -26,18 +27,22 @@ pub struct ImeiInfoApiUtil {
 impl ServeRecordJson<ImeiParameter> for ImeiInfoApiUtil {
     async fn serve_one(
         state: State<Arc<ServerState>>,
+        user: CurrentUser,
+        localization: Localization,
         imei_param: Query<ImeiParameter>,
     ) -> Json<Option<Self>> {
         let imei = Imei::try_from(imei_param.0.id()).unwrap();
         let tac = Tac::from(imei.clone());
         if let Json(Some(existing_row)) = Self::Record::query_one_handler(
             state.clone(),
+            user,
             Query(ImeiParameter::new(tac.clone().into())),
         )
         .await
         {
             Json(Some(<Self as crate::api::FromRecord>::from_record(
                 existing_row,
+                &localization,
             )))
         } else {
             let PhoneInfo {
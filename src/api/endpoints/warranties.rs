@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use axum::extract::{Json, Query, State};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use serde::Deserialize;
+
+use crate::api::{require_role, ApiResult, CurrentUser};
+use crate::database::shared_models::UserRole;
+use crate::database::tables::service_types::ServiceTypesTableRecord;
+use crate::database::tables::services::ServicesTableRecord;
+use crate::database::tables::warranties::{
+    ClaimError, WarrantyClaimDetails, WarrantyDetails, WarrantyReportRow,
+};
+use crate::ServerState;
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct WarrantyLookupParameters {
+    device: Option<i32>,
+    customer: Option<i32>,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct WarrantyIdParameter {
+    id: i32,
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct WarrantyPeriodParameters {
+    id: i32,
+    /// The number of days repairs are guaranteed for. Services can leave this out to use the
+    /// warranty period of their type.
+    days: Option<i32>,
+}
+
+/// A request to open a ticket claiming a warranty.
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct WarrantyClaimRequest {
+    description: Option<String>,
+    /// Items to add to the invoice besides the service under warranty. Products sold on the
+    /// original ticket are covered, while anything else is charged.
+    #[serde(default)]
+    items: Vec<i32>,
+}
+
+/// Look up the warranties of a device or of a customer.
+pub async fn lookup(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<WarrantyLookupParameters>,
+) -> ApiResult<Vec<WarrantyDetails>> {
+    if parameters.device.is_none() && parameters.customer.is_none() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "a device or a customer is required".to_owned(),
+        ));
+    }
+
+    Ok(Json(
+        WarrantyDetails::query_for(&state.database, parameters.device, parameters.customer).await,
+    ))
+}
+
+/// Open a ticket to repair a device again under one of its warranties, with the covered items of
+/// its invoice costing nothing.
+pub async fn open_claim(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+    Query(parameters): Query<WarrantyIdParameter>,
+    Json(request): Json<WarrantyClaimRequest>,
+) -> ApiResult<WarrantyClaimDetails> {
+    WarrantyClaimDetails::open(
+        &state.database,
+        parameters.id,
+        &request.items,
+        request.description.as_deref(),
+    )
+    .await
+    .map(Json)
+    .map_err(|error| match error {
+        ClaimError::WarrantyNotFound => (
+            StatusCode::NOT_FOUND,
+            format!("no warranty with ID {}", parameters.id),
+        ),
+        ClaimError::Expired(expires_at) => (
+            StatusCode::CONFLICT,
+            format!(
+                "the warranty expired on {}",
+                expires_at
+                    .with_timezone(&state.localization.timezone())
+                    .format("%Y-%m-%d")
+            ),
+        ),
+        ClaimError::ItemNotFound(item) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("no item with ID {item}"),
+        ),
+    })
+}
+
+/// Summarize how often each type of service is claimed under warranty.
+pub async fn warranty_report(
+    State(state): State<Arc<ServerState>>,
+    _user: CurrentUser,
+) -> Json<Vec<WarrantyReportRow>> {
+    Json(WarrantyReportRow::query_all(&state.database).await)
+}
+
+/// Change how many days repairs of a type of service are guaranteed for.
+pub async fn set_service_type_warranty(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<WarrantyPeriodParameters>,
+) -> ApiResult<ServiceTypesTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin],
+        "only admins can change warranty periods",
+    )?;
+    let days = parameters.days.ok_or_else(|| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "service types need a warranty period".to_owned(),
+        )
+    })?;
+    validate_days(Some(days))?;
+
+    ServiceTypesTableRecord::set_warranty(&state.database, parameters.id, days)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no service type with ID {}", parameters.id),
+            )
+        })
+}
+
+/// Change how many days repairs with a service are guaranteed for, or make it use the warranty
+/// period of its type again.
+pub async fn set_service_warranty(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<WarrantyPeriodParameters>,
+) -> ApiResult<ServicesTableRecord> {
+    require_role(
+        &user,
+        &[UserRole::Admin],
+        "only admins can change warranty periods",
+    )?;
+    validate_days(parameters.days)?;
+
+    ServicesTableRecord::set_warranty(&state.database, parameters.id, parameters.days)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no service with ID {}", parameters.id),
+            )
+        })
+}
+
+fn validate_days(days: Option<i32>) -> Result<(), (StatusCode, String)> {
+    match days {
+        Some(days) if days < 0 => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "the warranty period cannot be negative".to_owned(),
+        )),
+        _ => Ok(()),
+    }
+}
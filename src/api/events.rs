@@ -0,0 +1,177 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use http::StatusCode;
+use proc_macros::ApiSchema;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::api::openapi::{object_schema, ApiSchema};
+use crate::api::CurrentUser;
+use crate::database::events::ChangeEvent;
+use crate::database::shared_models::{PaymentType, TicketStatus, UserRole};
+use crate::ServerState;
+
+/// A kind of record whose changes can be subscribed to.
+#[derive(Clone, Copy, PartialEq)]
+enum EventResource {
+    Tickets,
+    Payments,
+    Stock,
+}
+
+impl EventResource {
+    const ALL: [Self; 3] = [Self::Tickets, Self::Payments, Self::Stock];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Tickets => "tickets",
+            Self::Payments => "payments",
+            Self::Stock => "stock",
+        }
+    }
+
+    fn of(event: &ChangeEvent) -> Self {
+        match event {
+            ChangeEvent::TicketCreated { .. } | ChangeEvent::TicketStatusChanged { .. } => {
+                Self::Tickets
+            }
+            ChangeEvent::PaymentRecorded { .. } => Self::Payments,
+            ChangeEvent::StockLow { .. } => Self::Stock,
+        }
+    }
+
+    /// Whether users with a role may see changes to this resource. Technicians work from the
+    /// ticket queue and have no need to see payments, nor to restock parts.
+    fn allowed_for(&self, role: UserRole) -> bool {
+        match self {
+            Self::Tickets => true,
+            Self::Payments | Self::Stock => matches!(role, UserRole::Admin | UserRole::FrontDesk),
+        }
+    }
+}
+
+#[derive(Deserialize, ApiSchema)]
+#[api_schema(input)]
+pub struct EventsParameters {
+    /// A comma-separated list of the resources to subscribe to, which defaults to every resource
+    /// the user is allowed to see.
+    #[api_schema(schema = json!({
+            "type": "string",
+            "description": "A comma-separated list of resources to subscribe to",
+            "example": "tickets,payments",
+        }))]
+    resources: Option<String>,
+}
+
+/// Stream changes to the resources a user subscribes to as server-sent events.
+///
+/// Each event is named after the type of change, with the [`ChangeEvent`] as its JSON data. If the
+/// client falls too far behind, it is sent a `lagged` event, after which it should refetch the
+/// resources it shows since some changes were missed.
+pub async fn serve_events(
+    State(state): State<Arc<ServerState>>,
+    CurrentUser(user): CurrentUser,
+    Query(parameters): Query<EventsParameters>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let resources: Vec<EventResource> = match parameters.resources {
+        Some(names) => names
+            .split(',')
+            .map(|name| {
+                let resource = EventResource::ALL
+                    .into_iter()
+                    .find(|resource| resource.name() == name.trim())
+                    .ok_or_else(|| {
+                        (
+                            StatusCode::UNPROCESSABLE_ENTITY,
+                            format!("there are no events for `{name}`"),
+                        )
+                    })?;
+                match resource.allowed_for(user.role) {
+                    true => Ok(resource),
+                    false => Err((
+                        StatusCode::FORBIDDEN,
+                        format!("this user cannot subscribe to `{name}`"),
+                    )),
+                }
+            })
+            .collect::<Result<_, _>>()?,
+        None => EventResource::ALL
+            .into_iter()
+            .filter(|resource| resource.allowed_for(user.role))
+            .collect(),
+    };
+
+    let stream =
+        BroadcastStream::new(state.events.subscribe()).filter_map(move |event| match event {
+            Ok(event) => resources.contains(&EventResource::of(&event)).then(|| {
+                let data = serde_json::to_value(&event).unwrap();
+                Ok(Event::default()
+                    .event(data["type"].as_str().unwrap())
+                    .json_data(data)
+                    .unwrap())
+            }),
+            Err(BroadcastStreamRecvError::Lagged(missed)) => Some(Ok(Event::default()
+                .event("lagged")
+                .json_data(json!({ "type": "lagged", "missed": missed }))
+                .unwrap())),
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+impl ApiSchema for ChangeEvent {
+    fn schema() -> Value {
+        let event = |name: &str, mut properties: Vec<(&str, Value)>| {
+            properties.insert(0, ("type", json!({ "type": "string", "enum": [name] })));
+            let required: Vec<&str> = properties.iter().map(|(name, _)| *name).collect();
+            object_schema(properties, &required)
+        };
+
+        json!({
+            "oneOf": [
+                event(
+                    "ticket_created",
+                    vec![
+                        ("ticket", i32::schema()),
+                        ("status", TicketStatus::schema()),
+                        ("customer", Option::<i32>::schema()),
+                    ],
+                ),
+                event(
+                    "ticket_status_changed",
+                    vec![
+                        ("ticket", i32::schema()),
+                        ("old_status", TicketStatus::schema()),
+                        ("new_status", TicketStatus::schema()),
+                    ],
+                ),
+                event(
+                    "payment_recorded",
+                    vec![
+                        ("payment", i32::schema()),
+                        ("invoice", i32::schema()),
+                        ("amount", Decimal::schema()),
+                        ("payment_type", PaymentType::schema()),
+                    ],
+                ),
+                event(
+                    "stock_low",
+                    vec![
+                        ("location", i32::schema()),
+                        ("part", i32::schema()),
+                        ("quantity", i32::schema()),
+                        ("threshold", i32::schema()),
+                    ],
+                ),
+                event("lagged", vec![("missed", json!({ "type": "integer" }))]),
+            ],
+        })
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use axum::extract::FromRequestParts;
+use chrono_tz::Tz;
+use http::header::ACCEPT_LANGUAGE;
+use http::request::Parts;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::ServerState;
+
+/// The timezone used to display timestamps when the shop does not configure one.
+pub const DEFAULT_TIMEZONE: Tz = Tz::UTC;
+/// The locale used when neither the shop nor the request asks for a supported one.
+pub const DEFAULT_LOCALE: &str = "en-US";
+/// The currency used when the shop does not configure one.
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+#[derive(Clone, Copy)]
+enum SymbolPosition {
+    /// The currency symbol comes directly before the amount, like `$1,234.56`.
+    Before,
+    /// The currency symbol comes after the amount and a space, like `1.234,56 €`.
+    After,
+}
+
+/// The conventions used to format values for a language and region.
+///
+/// Translations are read from `locales/<language>.toml`, which maps English text (column display
+/// names, tag labels and the like) to its translation. Text missing from a catalog is left in
+/// English.
+pub struct Locale {
+    tag: &'static str,
+    decimal_separator: char,
+    group_separator: char,
+    symbol_position: SymbolPosition,
+    date_format: &'static str,
+    catalog: Option<&'static str>,
+    translations: OnceLock<HashMap<String, String>>,
+}
+
+impl Locale {
+    const fn new(
+        tag: &'static str,
+        separators: (char, char),
+        symbol_position: SymbolPosition,
+        date_format: &'static str,
+        catalog: Option<&'static str>,
+    ) -> Self {
+        Self {
+            tag,
+            decimal_separator: separators.0,
+            group_separator: separators.1,
+            symbol_position,
+            date_format,
+            catalog,
+            translations: OnceLock::new(),
+        }
+    }
+
+    fn language(&self) -> &'static str {
+        self.tag.split('-').next().unwrap()
+    }
+
+    fn translations(&self) -> &HashMap<String, String> {
+        self.translations.get_or_init(|| match self.catalog {
+            Some(catalog) => toml::from_str(catalog).unwrap(),
+            None => HashMap::new(),
+        })
+    }
+}
+
+/// Every supported locale. The first locale of each language is used for requests which only name
+/// the language, like `de`.
+static LOCALES: [Locale; 6] = [
+    Locale::new(
+        "en-US",
+        ('.', ','),
+        SymbolPosition::Before,
+        "%m/%d/%Y %H:%M",
+        None,
+    ),
+    Locale::new(
+        "en-GB",
+        ('.', ','),
+        SymbolPosition::Before,
+        "%d/%m/%Y %H:%M",
+        None,
+    ),
+    Locale::new(
+        "en-CA",
+        ('.', ','),
+        SymbolPosition::Before,
+        "%Y-%m-%d %H:%M",
+        None,
+    ),
+    Locale::new(
+        "de-DE",
+        (',', '.'),
+        SymbolPosition::After,
+        "%d.%m.%Y %H:%M",
+        Some(include_str!("../../locales/de.toml")),
+    ),
+    Locale::new(
+        "fr-FR",
+        (',', '\u{202f}'),
+        SymbolPosition::After,
+        "%d/%m/%Y %H:%M",
+        Some(include_str!("../../locales/fr.toml")),
+    ),
+    Locale::new(
+        "es-ES",
+        (',', '.'),
+        SymbolPosition::After,
+        "%d/%m/%Y %H:%M",
+        Some(include_str!("../../locales/es.toml")),
+    ),
+];
+
+/// A currency that prices can be formatted in.
+pub struct Currency {
+    code: &'static str,
+    symbol: &'static str,
+    /// The number of digits after the decimal point, which is 0 for currencies without minor units.
+    minor_units: u32,
+}
+
+static CURRENCIES: [Currency; 7] = [
+    Currency {
+        code: "USD",
+        symbol: "$",
+        minor_units: 2,
+    },
+    Currency {
+        code: "CAD",
+        symbol: "CA$",
+        minor_units: 2,
+    },
+    Currency {
+        code: "AUD",
+        symbol: "A$",
+        minor_units: 2,
+    },
+    Currency {
+        code: "MXN",
+        symbol: "MX$",
+        minor_units: 2,
+    },
+    Currency {
+        code: "EUR",
+        symbol: "€",
+        minor_units: 2,
+    },
+    Currency {
+        code: "GBP",
+        symbol: "£",
+        minor_units: 2,
+    },
+    Currency {
+        code: "JPY",
+        symbol: "¥",
+        minor_units: 0,
+    },
+];
+
+/// Find a supported locale by its exact tag, such as `en-GB`, ignoring case.
+pub fn find_locale(tag: &str) -> Option<&'static Locale> {
+    LOCALES
+        .iter()
+        .find(|locale| locale.tag.eq_ignore_ascii_case(tag))
+}
+
+/// Find a supported currency by its ISO 4217 code, such as `EUR`, ignoring case.
+pub fn find_currency(code: &str) -> Option<&'static Currency> {
+    CURRENCIES
+        .iter()
+        .find(|currency| currency.code.eq_ignore_ascii_case(code))
+}
+
+pub fn supported_locales() -> impl Iterator<Item = &'static str> {
+    LOCALES.iter().map(|locale| locale.tag)
+}
+
+pub fn supported_currencies() -> impl Iterator<Item = &'static str> {
+    CURRENCIES.iter().map(|currency| currency.code)
+}
+
+/// How values are formatted and text is translated for a single response.
+///
+/// The shop's locale, currency and timezone are configured with `LOCALE`, `CURRENCY` and
+/// `TIMEZONE`. The locale can be overridden by each request through its `Accept-Language` header,
+/// but the currency and timezone cannot, since prices are stored in the shop's currency and
+/// timestamps are shown in the shop's local time.
+#[derive(Clone, Copy)]
+pub struct Localization {
+    locale: &'static Locale,
+    currency: &'static Currency,
+    timezone: Tz,
+}
+
+impl Localization {
+    pub fn new(locale: &'static Locale, currency: &'static Currency, timezone: Tz) -> Self {
+        Self {
+            locale,
+            currency,
+            timezone,
+        }
+    }
+
+    /// Use the most preferred supported locale of an `Accept-Language` header value, if there is
+    /// one, falling back to the current locale otherwise.
+    ///
+    /// A language range without a region (like `de`) matches the first locale of that language,
+    /// and a region that is not supported (like `de-AT`) falls back to the language the same way.
+    pub fn with_accept_language(self, header: &str) -> Self {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|parameter| parameter.strip_prefix("q="))
+                    .map_or(Some(1.0), |quality| quality.parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // * The sort is stable, so ranges with equal quality keep the order they were given in.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let locale = ranges.into_iter().find_map(|(tag, _)| {
+            find_locale(tag).or_else(|| {
+                let language = tag.split('-').next().unwrap();
+                LOCALES
+                    .iter()
+                    .find(|locale| locale.language().eq_ignore_ascii_case(language))
+            })
+        });
+
+        match locale {
+            Some(locale) => Self { locale, ..self },
+            None => self,
+        }
+    }
+
+    /// Translate English text into the language of the locale, or leave it as-is if there is no
+    /// translation.
+    pub fn translate(&self, text: &'static str) -> &'static str {
+        match self.locale.translations().get(text) {
+            Some(translation) => translation,
+            None => text,
+        }
+    }
+
+    /// Format an amount of money in the shop's currency, like `$1,234.56` or `1.234,56 €`.
+    pub fn format_currency(&self, amount: Decimal) -> String {
+        let amount = amount.round_dp_with_strategy(
+            self.currency.minor_units,
+            RoundingStrategy::MidpointAwayFromZero,
+        );
+        let number = self.format_number(amount.abs(), self.currency.minor_units);
+        let sign = if amount.is_sign_negative() && !amount.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+
+        match self.locale.symbol_position {
+            SymbolPosition::Before => format!("{sign}{}{number}", self.currency.symbol),
+            SymbolPosition::After => format!("{sign}{number}\u{a0}{}", self.currency.symbol),
+        }
+    }
+
+    /// Format a non-negative number with a fixed number of decimal places, grouping the digits of
+    /// its integer part in threes.
+    fn format_number(&self, number: Decimal, decimal_places: u32) -> String {
+        let digits = format!("{:.*}", decimal_places as usize, number);
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits.as_str(), None),
+        };
+
+        let mut formatted = String::new();
+        for (index, digit) in integer.chars().enumerate() {
+            if index > 0 && (integer.len() - index) % 3 == 0 {
+                formatted.push(self.locale.group_separator);
+            }
+            formatted.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            formatted.push(self.locale.decimal_separator);
+            formatted.push_str(fraction);
+        }
+
+        formatted
+    }
+
+    pub fn date_format(&self) -> &'static str {
+        self.locale.date_format
+    }
+
+    /// The timezone that timestamps are displayed in.
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Self::new(
+            find_locale(DEFAULT_LOCALE).unwrap(),
+            find_currency(DEFAULT_CURRENCY).unwrap(),
+            DEFAULT_TIMEZONE,
+        )
+    }
+}
+
+/// The localization of a request is the shop's, with its locale overridden by the request's
+/// `Accept-Language` header.
+#[axum::async_trait]
+impl FromRequestParts<Arc<ServerState>> for Localization {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(
+            match parts
+                .headers
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok())
+            {
+                Some(header) => state.localization.with_accept_language(header),
+                None => state.localization,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn localization(locale: &str, currency: &str) -> Localization {
+        Localization::new(
+            find_locale(locale).unwrap(),
+            find_currency(currency).unwrap(),
+            DEFAULT_TIMEZONE,
+        )
+    }
+
+    fn accepted_locale(header: &str) -> &'static str {
+        localization("en-GB", "EUR")
+            .with_accept_language(header)
+            .locale
+            .tag
+    }
+
+    #[test]
+    fn the_most_preferred_supported_locale_is_used() {
+        assert_eq!(accepted_locale("de-DE"), "de-DE");
+        assert_eq!(accepted_locale("EN-us"), "en-US");
+        assert_eq!(accepted_locale("de;q=0.5, es;q=0.9, fr;q=0.7"), "es-ES");
+        assert_eq!(accepted_locale(" fr-FR ; q=0.8 , de ; q=0.4"), "fr-FR");
+        // * Ranges without a quality have a quality of 1, and ties keep their order.
+        assert_eq!(accepted_locale("de;q=0.9, es, fr"), "es-ES");
+    }
+
+    #[test]
+    fn unacceptable_and_malformed_ranges_are_skipped() {
+        assert_eq!(accepted_locale("de;q=0, fr;q=0.1"), "fr-FR");
+        assert_eq!(accepted_locale("de;q=high, es;q=0.2"), "es-ES");
+        assert_eq!(accepted_locale(",, ;q=1, fr"), "fr-FR");
+    }
+
+    #[test]
+    fn languages_fall_back_to_their_first_locale() {
+        assert_eq!(accepted_locale("de"), "de-DE");
+        assert_eq!(accepted_locale("de-AT"), "de-DE");
+        assert_eq!(accepted_locale("en"), "en-US");
+        assert_eq!(accepted_locale("fr-CH, en-GB;q=0.9"), "fr-FR");
+    }
+
+    #[test]
+    fn unsupported_headers_keep_the_current_locale() {
+        assert_eq!(accepted_locale(""), "en-GB");
+        assert_eq!(accepted_locale("*"), "en-GB");
+        assert_eq!(accepted_locale("ja-JP, tlh;q=0.5"), "en-GB");
+        assert_eq!(accepted_locale("de;q=0"), "en-GB");
+    }
+
+    #[test]
+    fn currency_is_formatted_for_every_locale() {
+        let amount = Decimal::new(1_234_567_891, 3);
+        let expected = [
+            ("en-US", "€1,234,567.89"),
+            ("en-GB", "€1,234,567.89"),
+            ("en-CA", "€1,234,567.89"),
+            ("de-DE", "1.234.567,89\u{a0}€"),
+            ("fr-FR", "1\u{202f}234\u{202f}567,89\u{a0}€"),
+            ("es-ES", "1.234.567,89\u{a0}€"),
+        ];
+        assert_eq!(expected.len(), LOCALES.len());
+
+        for (locale, formatted) in expected {
+            assert_eq!(
+                localization(locale, "EUR").format_currency(amount),
+                formatted,
+                "{locale}"
+            );
+        }
+    }
+
+    #[test]
+    fn currency_is_rounded_to_its_minor_units() {
+        let usd = localization("en-US", "USD");
+        assert_eq!(usd.format_currency(Decimal::new(5005, 3)), "$5.01");
+        assert_eq!(usd.format_currency(Decimal::new(-5005, 3)), "-$5.01");
+        assert_eq!(usd.format_currency(Decimal::new(99999, 2)), "$999.99");
+        assert_eq!(usd.format_currency(Decimal::new(100000, 2)), "$1,000.00");
+        // * Amounts which round to zero are not shown as negative.
+        assert_eq!(usd.format_currency(Decimal::new(-1, 3)), "$0.00");
+
+        assert_eq!(
+            localization("en-US", "JPY").format_currency(Decimal::new(12345, 1)),
+            "¥1,235"
+        );
+        assert_eq!(
+            localization("de-DE", "JPY").format_currency(Decimal::new(-12344, 1)),
+            "-1.234\u{a0}¥"
+        );
+    }
+}
@@ -1,12 +1,27 @@
 pub mod endpoints;
+pub mod events;
+pub mod localization;
+pub mod openapi;
+pub mod tenancy;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use axum::extract::{Json, Query, State};
+use axum::extract::{FromRequestParts, Json, Query, State};
+use axum::response::{IntoResponse, Response};
+use http::header::{AUTHORIZATION, COOKIE, WWW_AUTHENTICATE};
+use http::request::Parts;
+use http::{HeaderMap, StatusCode};
 use serde::{Deserialize, Serialize};
 
 use proc_macros::IdParameter;
 
+use crate::api::endpoints::FilterOperator;
+use crate::api::localization::Localization;
+use crate::database::shared_models::UserRole;
+use crate::database::tables::locations::LocationsTableRecord;
+use crate::database::tables::sessions::SessionsTableRecord;
+use crate::database::tables::users::UsersTableRecord;
 use crate::database::{Record, Relation};
 use crate::ServerState;
 
@@ -18,11 +33,175 @@ use crate::ServerState;
 pub trait ServeResourceJson: FromRelation + Serialize + Sized {
     /// Serve a JSON collection endpoint.
     ///
+    /// Values are formatted and column names translated for the [`Localization`] of the request.
+    /// The column metadata is omitted from the response if the `metadata=false` query parameter is
+    /// given, for clients which already have it from the `/metadata` endpoint. Records are limited
+    /// to those of a store if the `location=<id>` query parameter is given.
+    ///
     /// This function is used as an axum handler via [`axum::routing::method_routing::get`].
-    async fn serve_all(state: State<Arc<ServerState>>) -> Json<Self> {
-        Json(Self::from_relation(
-            Self::Relation::query_all_handler(state).await.0,
-        ))
+    async fn serve_all(
+        state: State<Arc<ServerState>>,
+        _user: CurrentUser,
+        localization: Localization,
+        Query(options): Query<ResourceOptions>,
+    ) -> Response {
+        let resource = Self::from_relation(
+            Self::Relation::query_all_at(&state.database, options.location).await,
+            &localization,
+        );
+        match options.metadata {
+            true => Json(resource).into_response(),
+            false => {
+                let mut resource = serde_json::to_value(resource).unwrap();
+                resource.as_object_mut().unwrap().remove("metadata");
+                Json(resource).into_response()
+            }
+        }
+    }
+}
+
+/// The query parameters accepted by every [`ServeResourceJson`] endpoint.
+#[derive(Deserialize)]
+pub struct ResourceOptions {
+    #[serde(default = "default_include_metadata")]
+    metadata: bool,
+    /// The store to list the records of. Resources shared by every store, such as vendors, are
+    /// listed in full.
+    location: Option<i32>,
+}
+
+fn default_include_metadata() -> bool {
+    true
+}
+
+/// The result of a JSON endpoint, which is rejected with a status code and a message if the
+/// request refers to something that does not exist or is not allowed.
+pub type ApiResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+/// The name of the cookie a session token is kept in by browsers, for requests which cannot set
+/// the `Authorization` header, like those for images and event streams.
+pub const SESSION_COOKIE: &str = "fixwise_session";
+
+/// The user a request is made by, found from the session token it carries.
+///
+/// The token is read from an `Authorization: Bearer <token>` header, or from the session cookie set
+/// when signing in. Requests without a current session are rejected with `401 Unauthorized`, so
+/// every handler taking this as an extractor can only be used by signed-in users.
+pub struct CurrentUser(pub UsersTableRecord);
+
+#[axum::async_trait]
+impl FromRequestParts<Arc<ServerState>> for CurrentUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = match session_token(&parts.headers) {
+            Some(token) => SessionsTableRecord::query_user(&state.database, token).await,
+            None => None,
+        };
+
+        user.map(CurrentUser).ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                [(WWW_AUTHENTICATE, "Bearer")],
+                "sign in at /login to use this endpoint",
+            )
+                .into_response()
+        })
+    }
+}
+
+/// Get the session token a request carries, if it has one.
+pub fn session_token(headers: &HeaderMap) -> Option<&str> {
+    if let Some(authorization) = headers.get(AUTHORIZATION) {
+        return authorization.to_str().ok()?.strip_prefix("Bearer ");
+    }
+
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|cookies| cookies.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then_some(value)
+        })
+}
+
+/// Reject a request with `forbidden` as its reason unless the user making it has one of `roles`.
+pub fn require_role(
+    user: &UsersTableRecord,
+    roles: &[UserRole],
+    forbidden: &str,
+) -> Result<(), (StatusCode, String)> {
+    match roles.contains(&user.role) {
+        true => Ok(()),
+        false => Err((StatusCode::FORBIDDEN, forbidden.to_owned())),
+    }
+}
+
+/// Get the store a request is for, or the first store if none is given, rejecting the request if
+/// there is no such store.
+pub async fn find_location(
+    state: &ServerState,
+    location: Option<i32>,
+) -> Result<LocationsTableRecord, (StatusCode, String)> {
+    LocationsTableRecord::query_or_default(&state.database, location)
+        .await
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("no location with ID {}", location.unwrap_or_default()),
+            )
+        })
+}
+
+/// A trait that allows a resource to describe its columns for the `/metadata` endpoint.
+///
+/// This is implemented by the `FromRelation` derive for every resource with column metadata.
+pub trait DescribeResource {
+    /// Describe each column of the resource, in order, with its metadata and the sorts and filters
+    /// it supports.
+    fn describe_columns(localization: &Localization) -> serde_json::Value;
+}
+
+/// The columns of every resource served by the API, as described by the `/metadata` endpoint.
+///
+/// This is added to every route as an [`axum::Extension`] by
+/// [`openapi::DocumentedRouter::finish`], so that handlers can check client-provided column names.
+pub struct ResourceCatalog {
+    resources: HashMap<String, Vec<ResourceColumn>>,
+}
+
+#[derive(Deserialize)]
+pub struct ResourceColumn {
+    pub key: String,
+    pub sortable: bool,
+    pub filters: Vec<FilterOperator>,
+}
+
+impl ResourceCatalog {
+    fn from_metadata(metadata: &serde_json::Value) -> Self {
+        let resources = metadata["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|resource| {
+                (
+                    resource["name"].as_str().unwrap().to_owned(),
+                    serde_json::from_value(resource["columns"].clone()).unwrap(),
+                )
+            })
+            .collect();
+
+        Self { resources }
+    }
+
+    /// Get the columns of a resource by its name, or [`None`] if there is no such resource.
+    pub fn columns(&self, resource: &str) -> Option<&[ResourceColumn]> {
+        self.resources.get(resource).map(Vec::as_slice)
     }
 }
 
@@ -42,12 +221,18 @@ pub trait ServeRecordJson<I: IdParameter>: FromRecord + Serialize + Sized {
     /// Serve a JSON record endpoint.
     ///
     /// This function is used as an axum handler via [`axum::routing::method_routing::get`].
-    async fn serve_one(state: State<Arc<ServerState>>, id_param: Query<I>) -> Json<Option<Self>> {
+    async fn serve_one(
+        state: State<Arc<ServerState>>,
+        user: CurrentUser,
+        localization: Localization,
+        id_param: Query<I>,
+    ) -> Json<Option<Self>> {
         Json(Some(Self::from_record(
-            Self::Record::query_one_handler(state, id_param)
+            Self::Record::query_one_handler(state, user, id_param)
                 .await
                 .0
                 .unwrap(),
+            &localization,
         )))
     }
 }
@@ -65,8 +250,9 @@ pub trait FromRelation {
     /// [`FromRelation::from_relation`].
     type Relation: Relation;
 
-    /// Convert the database relation into the data required for the endpoint.
-    fn from_relation(relation: Self::Relation) -> Self;
+    /// Convert the database relation into the data required for the endpoint, formatting it for a
+    /// [`Localization`].
+    fn from_relation(relation: Self::Relation, localization: &Localization) -> Self;
 }
 
 /// A trait that allows a database record to be converted into data that can be used by an API
@@ -78,8 +264,9 @@ pub trait FromRecord {
     /// The record type in the database to be converted by [`FromRecord::from_record`].
     type Record: Record;
 
-    /// Convert the database record into the data required for the endpoint.
-    fn from_record(record: Self::Record) -> Self;
+    /// Convert the database record into the data required for the endpoint, formatting it for a
+    /// [`Localization`].
+    fn from_record(record: Self::Record, localization: &Localization) -> Self;
 }
 
 /// A trait that allows queries including an ID field to use unique nomenclature if desired.
@@ -88,6 +275,9 @@ pub trait FromRecord {
 /// `https://fixwise.io/some/record/endpoint?id_parameter_name=123456`. If the ID parameter is just
 /// named `id`, simply use [`GenericIdParameter`].
 pub trait IdParameter {
+    /// The name of the query parameter, which is the name of the struct's field.
+    const NAME: &'static str;
+
     /// Create the parameter with an inner [`usize`].
     fn new(value: usize) -> Self;
     /// Get the inner [`usize`] ID parameter.
@@ -0,0 +1,621 @@
+use std::sync::Arc;
+
+use axum::extract::{Extension, Json};
+use axum::response::Html;
+use axum::routing::{get, MethodRouter};
+use axum::Router;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+use sqlx::types::Json as DatabaseJson;
+
+use crate::api::endpoints::FrontendDataType;
+use crate::api::localization::Localization;
+use crate::api::{CurrentUser, DescribeResource, IdParameter, ResourceCatalog, SESSION_COOKIE};
+use crate::ServerState;
+
+const DOCS_PAGE: &str = include_str!("docs.html");
+
+/// A trait that allows a type returned by the API to describe its JSON shape as an OpenAPI schema.
+///
+/// This is implemented for column value types here, for resources and records by the
+/// `ProcessEndpoint`, `FromRelation` and `FromRecord` derives, and for the parameters, requests and
+/// responses of other endpoints and the enums they use by the `ApiSchema` derive, so the
+/// specification served at `/openapi.json` always matches what the endpoints actually accept and
+/// return.
+///
+/// The `ApiSchema` derive follows `#[serde(rename_all = ...)]` on the type and the `#[serde(...)]`
+/// options of each field or variant, and only supports enums whose variants are all units. Every
+/// field is required, unless the struct is marked `#[api_schema(input)]`, in which case fields
+/// which are optional or have a default can be left out. The schema of a field can be replaced with
+/// `#[api_schema(schema = ...)]` when its type says too little about it, or with the schema of
+/// another type with `#[api_schema(value_type = ...)]`, such as for columns which are only optional
+/// when inserting.
+pub trait ApiSchema {
+    fn schema() -> Value;
+}
+
+impl ApiSchema for i32 {
+    fn schema() -> Value {
+        json!({ "type": "integer", "format": "int32" })
+    }
+}
+
+impl ApiSchema for i64 {
+    fn schema() -> Value {
+        json!({ "type": "integer", "format": "int64" })
+    }
+}
+
+impl ApiSchema for u32 {
+    fn schema() -> Value {
+        json!({ "type": "integer", "format": "int32", "minimum": 0 })
+    }
+}
+
+impl ApiSchema for Decimal {
+    fn schema() -> Value {
+        json!({ "type": "string", "format": "decimal" })
+    }
+}
+
+impl ApiSchema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl ApiSchema for DateTime<Utc> {
+    fn schema() -> Value {
+        json!({ "type": "string", "format": "date-time" })
+    }
+}
+
+impl ApiSchema for NaiveDate {
+    fn schema() -> Value {
+        json!({ "type": "string", "format": "date" })
+    }
+}
+
+impl ApiSchema for NaiveTime {
+    fn schema() -> Value {
+        json!({ "type": "string", "format": "time", "example": "09:00:00" })
+    }
+}
+
+impl ApiSchema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+/// The absence of query parameters or a request body.
+impl ApiSchema for () {
+    fn schema() -> Value {
+        Value::Null
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn schema() -> Value {
+        array_schema(T::schema())
+    }
+}
+
+/// Any JSON value.
+impl ApiSchema for Value {
+    fn schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for DatabaseJson<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Option<T> {
+    fn schema() -> Value {
+        let mut schema = T::schema();
+        schema["nullable"] = Value::Bool(true);
+        schema
+    }
+}
+
+/// Build the schema of a JSON object from the schemas of its properties.
+///
+/// This is mostly used by derive macros, which pass every property as required.
+pub fn object_schema(properties: Vec<(&str, Value)>, required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_owned(), schema))
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// A field of a struct deriving `ApiSchema`.
+pub enum SchemaField {
+    Property {
+        name: &'static str,
+        schema: Value,
+        required: bool,
+    },
+    /// A field whose properties are flattened into the struct by `#[serde(flatten)]`.
+    Flattened(Value),
+}
+
+/// Build the schema of a struct deriving `ApiSchema` from the schemas of its fields, in order.
+pub fn struct_schema(fields: Vec<SchemaField>) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for field in fields {
+        match field {
+            SchemaField::Property {
+                name,
+                schema,
+                required: is_required,
+            } => {
+                properties.insert(name.to_owned(), schema);
+                if is_required {
+                    required.push(Value::from(name));
+                }
+            }
+            SchemaField::Flattened(mut schema) => {
+                if let Value::Object(flattened) = schema["properties"].take() {
+                    properties.extend(flattened);
+                }
+                if let Value::Array(flattened) = schema["required"].take() {
+                    required.extend(flattened);
+                }
+            }
+        }
+    }
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+pub fn array_schema(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+/// Build the schema of an enum deriving `ApiSchema`, which serializes to one of its variant names.
+pub fn string_enum_schema(variants: &[&str]) -> Value {
+    json!({ "type": "string", "enum": variants })
+}
+
+/// Build the schema of an `EndpointMetadata` struct generated by the `ProcessEndpoint` derive.
+///
+/// The metadata of an endpoint never changes, so its actual value is included as the example.
+pub fn metadata_schema(columns: &[&str], metadata: Value) -> Value {
+    let column_schema = json!({ "$ref": "#/components/schemas/FrontendColumnMetadata" });
+    let mut schema = object_schema(
+        columns
+            .iter()
+            .map(|column| (*column, column_schema.clone()))
+            .collect(),
+        columns,
+    );
+    schema["example"] = metadata;
+    schema
+}
+
+/// The schemas shared between endpoints, referenced from the schemas of individual endpoints.
+fn component_schemas() -> Value {
+    let css_color = json!({
+        "oneOf": [
+            object_schema(
+                vec![
+                    ("name", json!({ "type": "string" })),
+                    ("opacity", json!({ "type": "number" })),
+                ],
+                &["name", "opacity"],
+            ),
+            object_schema(
+                vec![
+                    ("r", json!({ "type": "integer" })),
+                    ("g", json!({ "type": "integer" })),
+                    ("b", json!({ "type": "integer" })),
+                    ("a", json!({ "type": "number" })),
+                ],
+                &["r", "g", "b", "a"],
+            ),
+        ],
+    });
+    let tag_option = object_schema(
+        vec![("name", json!({ "type": "string" })), ("color", css_color)],
+        &["name", "color"],
+    );
+    let text_display = object_schema(
+        vec![
+            ("name", json!({ "type": "string" })),
+            ("trimmable", json!({ "type": "boolean" })),
+        ],
+        &["name", "trimmable"],
+    );
+    let tag_display = object_schema(
+        vec![
+            ("name", json!({ "type": "string" })),
+            ("options", array_schema(tag_option)),
+        ],
+        &["name", "options"],
+    );
+
+    json!({
+        "FrontendColumnMetadata": object_schema(
+            vec![
+                ("data_type", FrontendDataType::schema()),
+                (
+                    "display",
+                    json!({
+                        "oneOf": [
+                            object_schema(vec![("text", text_display)], &["text"]),
+                            object_schema(vec![("tag", tag_display)], &["tag"]),
+                        ],
+                    }),
+                ),
+            ],
+            &["data_type", "display"],
+        ),
+    })
+}
+
+/// An API router which records an OpenAPI description of every route added to it.
+///
+/// Routes must be added through the typed methods so that the description cannot fall out of sync
+/// with the routes actually served. [`DocumentedRouter::finish`] adds `/openapi.json` and `/docs`,
+/// along with `/metadata` which describes the columns of every resource added.
+pub struct DocumentedRouter {
+    router: Router<Arc<ServerState>>,
+    paths: Map<String, Value>,
+    resources: Vec<ResourceDescriber>,
+    /// Whether routes being added can be used without signing in.
+    public: bool,
+}
+
+/// A resource added to a [`DocumentedRouter`], which can describe its columns in any locale.
+#[derive(Clone)]
+struct ResourceDescriber {
+    path: String,
+    describe_columns: fn(&Localization) -> Value,
+}
+
+/// Describe every resource for the `/metadata` endpoint.
+fn describe_resources(resources: &[ResourceDescriber], localization: &Localization) -> Value {
+    let resources: Vec<Value> = resources
+        .iter()
+        .map(|resource| {
+            json!({
+                "name": resource.path.trim_start_matches('/'),
+                "path": resource.path,
+                "columns": (resource.describe_columns)(localization),
+            })
+        })
+        .collect();
+
+    json!({ "resources": resources })
+}
+
+impl DocumentedRouter {
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            paths: Map::new(),
+            resources: Vec::new(),
+            public: false,
+        }
+    }
+
+    fn route(
+        mut self,
+        path: &str,
+        method: &str,
+        mut operation: Value,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        match self.public {
+            true => operation["security"] = json!([]),
+            false => {
+                operation["responses"]["401"] = json!({ "description": "Not signed in" });
+            }
+        }
+
+        // * Axum merges the handlers of routes with the same path, so their operations are merged
+        // * here as well.
+        let methods = self
+            .paths
+            .entry(path.to_owned())
+            .or_insert_with(|| json!({}));
+        methods[method] = operation;
+        self.router = self.router.route(path, handler);
+        self
+    }
+
+    /// Add the routes added by `add` without requiring their users to sign in, such as the sign in
+    /// endpoint itself and the pages shared with customers.
+    pub fn public(mut self, add: impl FnOnce(Self) -> Self) -> Self {
+        self.public = true;
+        let mut router = add(self);
+        router.public = false;
+        router
+    }
+
+    /// Add a collection endpoint serving a [`super::ServeResourceJson`] type.
+    pub fn resource<R: ApiSchema + DescribeResource>(
+        mut self,
+        path: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let mut operation = operation(
+            path,
+            "Get every record with its column metadata",
+            &[],
+            R::schema(),
+        );
+        operation["parameters"] = json!([{
+            "name": "metadata",
+            "in": "query",
+            "required": false,
+            "description": "Whether to include the column metadata in the response",
+            "schema": { "type": "boolean", "default": true },
+        }, {
+            "name": "location",
+            "in": "query",
+            "required": false,
+            "description": "The store to list the records of, for resources which belong to one",
+            "schema": { "type": "integer" },
+        }, {
+            "name": "Accept-Language",
+            "in": "header",
+            "required": false,
+            "description": "The language to format values and translate column names into",
+            "schema": { "type": "string" },
+        }]);
+
+        self.resources.push(ResourceDescriber {
+            path: path.to_owned(),
+            describe_columns: R::describe_columns,
+        });
+        self.route(path, "get", operation, handler)
+    }
+
+    /// Add a record endpoint serving a [`super::ServeRecordJson`] type.
+    pub fn record<R: ApiSchema, I: IdParameter>(
+        self,
+        path: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let mut schema = R::schema();
+        schema["nullable"] = Value::Bool(true);
+        let operation = operation(path, "Get a single record", &[I::NAME], schema);
+        self.route(path, "get", operation, handler)
+    }
+
+    /// Add an endpoint serving the unprocessed records of a database view.
+    pub fn raw(self, path: &str, handler: MethodRouter<Arc<ServerState>>) -> Self {
+        let schema = array_schema(json!({ "type": "object" }));
+        let operation = operation(path, "Get every unprocessed record", &[], schema);
+        self.route(path, "get", operation, handler)
+    }
+
+    /// Add an endpoint deleting a single record, which responds with whether it succeeded.
+    pub fn delete<I: IdParameter>(
+        self,
+        path: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let schema = json!({ "type": "boolean" });
+        let operation = operation(path, "Delete a single record", &[I::NAME], schema);
+        self.route(path, "delete", operation, handler)
+    }
+
+    /// Add an endpoint taking the query parameters `Q` and a JSON request body `B`, and responding
+    /// with `R`. Use `()` for `Q` or `B` if the endpoint takes no query parameters or body.
+    pub fn json<Q: ApiSchema, B: ApiSchema, R: ApiSchema>(
+        self,
+        path: &str,
+        method: &str,
+        summary: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let mut operation = operation(path, summary, &[], R::schema());
+        operation["operationId"] = json!(format!(
+            "{method}_{}",
+            operation["operationId"].as_str().unwrap()
+        ));
+        operation["parameters"] = query_parameters(Q::schema());
+
+        let body = B::schema();
+        if !body.is_null() {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": body } },
+            });
+        }
+
+        self.route(path, method, operation, handler)
+    }
+
+    /// Add an endpoint taking the query parameters `Q` and a file in one of `content_types` as its
+    /// request body, such as a photo, and responding with `R` as JSON.
+    pub fn upload<Q: ApiSchema, R: ApiSchema>(
+        self,
+        path: &str,
+        content_types: &[&str],
+        summary: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let mut operation = operation(path, summary, &[], R::schema());
+        operation["operationId"] = json!(format!(
+            "post_{}",
+            operation["operationId"].as_str().unwrap()
+        ));
+        operation["parameters"] = query_parameters(Q::schema());
+
+        let content: Map<String, Value> = content_types
+            .iter()
+            .map(|content_type| {
+                (
+                    (*content_type).to_owned(),
+                    json!({ "schema": { "type": "string", "format": "binary" } }),
+                )
+            })
+            .collect();
+        operation["requestBody"] = json!({ "required": true, "content": content });
+
+        self.route(path, "post", operation, handler)
+    }
+
+    /// Add an endpoint taking the query parameters `Q` and responding with a document of another
+    /// format than JSON, such as a calendar feed.
+    pub fn document<Q: ApiSchema>(
+        self,
+        path: &str,
+        content_type: &str,
+        summary: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let mut operation = operation(path, summary, &[], Value::Null);
+        operation["parameters"] = query_parameters(Q::schema());
+        operation["responses"]["200"]["content"] = json!({
+            content_type: { "schema": { "type": "string" } },
+        });
+        self.route(path, "get", operation, handler)
+    }
+
+    /// Add an endpoint taking the query parameters `Q` and streaming events of type `E` to the
+    /// client as server-sent events.
+    pub fn event_stream<Q: ApiSchema, E: ApiSchema>(
+        self,
+        path: &str,
+        summary: &str,
+        handler: MethodRouter<Arc<ServerState>>,
+    ) -> Self {
+        let mut operation = operation(path, summary, &[], Value::Null);
+        operation["parameters"] = query_parameters(Q::schema());
+        operation["responses"]["200"]["content"] = json!({
+            "text/event-stream": {
+                "schema": {
+                    "type": "string",
+                    "description": "Server-sent events named after their type, with JSON data",
+                },
+                "x-event-data": E::schema(),
+            },
+        });
+        self.route(path, "get", operation, handler)
+    }
+
+    /// Get the OpenAPI specification describing every route added so far.
+    pub fn specification(&self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Fixwise API",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": self.paths,
+            "components": {
+                "schemas": component_schemas(),
+                "securitySchemes": {
+                    "bearer": { "type": "http", "scheme": "bearer" },
+                    "cookie": { "type": "apiKey", "in": "cookie", "name": SESSION_COOKIE },
+                },
+            },
+            "security": [{ "bearer": [] }, { "cookie": [] }],
+        })
+    }
+
+    /// Add the `/metadata`, `/openapi.json` and `/docs` routes and return the finished router.
+    ///
+    /// The columns of every resource are also made available to handlers as a
+    /// [`ResourceCatalog`] extension.
+    pub fn finish(mut self) -> Router<Arc<ServerState>> {
+        let metadata = describe_resources(&self.resources, &Localization::default());
+        let mut operation = operation(
+            "/metadata",
+            "Describe the columns of every resource, with the sorts and filters they support",
+            &[],
+            json!({ "type": "object", "example": metadata }),
+        );
+        operation["parameters"] = json!([{
+            "name": "Accept-Language",
+            "in": "header",
+            "required": false,
+            "description": "The language to translate column names and tag labels into",
+            "schema": { "type": "string" },
+        }]);
+        self.paths
+            .insert("/metadata".to_owned(), json!({ "get": operation }));
+
+        let catalog = Arc::new(ResourceCatalog::from_metadata(&metadata));
+        let specification = self.specification();
+        let resources = self.resources;
+        self.router
+            .route(
+                "/metadata",
+                get(
+                    |_user: CurrentUser, localization: Localization| async move {
+                        Json(describe_resources(&resources, &localization))
+                    },
+                ),
+            )
+            .route("/openapi.json", get(|| async move { Json(specification) }))
+            .route("/docs", get(|| async { Html(DOCS_PAGE) }))
+            .layer(Extension(catalog))
+    }
+}
+
+/// Describe each property of a query parameter struct's schema as a query parameter.
+fn query_parameters(schema: Value) -> Value {
+    let Some(properties) = schema["properties"].as_object() else {
+        return json!([]);
+    };
+    let required = schema["required"].as_array().unwrap();
+
+    properties
+        .iter()
+        .map(|(name, property)| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": required.contains(&json!(name)),
+                "schema": property,
+            })
+        })
+        .collect()
+}
+
+fn operation(path: &str, summary: &str, query_parameters: &[&str], response: Value) -> Value {
+    let parameters: Vec<Value> = query_parameters
+        .iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": true,
+                "schema": { "type": "integer", "minimum": 0 },
+            })
+        })
+        .collect();
+
+    json!({
+        "operationId": path.trim_start_matches('/').replace('/', "_"),
+        "summary": summary,
+        "parameters": parameters,
+        "responses": {
+            "200": {
+                "description": "OK",
+                "content": { "application/json": { "schema": response } },
+            },
+        },
+    })
+}
@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use chrono::{DateTime, Utc};
+use http::header::HOST;
+use http::StatusCode;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+
+use crate::database::{Database, SCHEMA_VERSION};
+use crate::{ServerState, ShopSettings};
+
+/// The header naming the shop a request is for, for clients which cannot use its subdomain.
+///
+/// This is only accepted when the server allows it for development, since anything able to send
+/// a request could otherwise pick its shop regardless of the host it was sent to.
+const TENANT_HEADER: &str = "x-tenant";
+/// How often tenants which have been removed since they were served are disconnected from, even if
+/// no request for them has come in.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The shops of a multi-tenant server, each of which is served by its own copy of the routes with
+/// a connection to its own database.
+pub struct Tenants {
+    /// The configured database, which holds the registry of tenants.
+    registry: Database,
+    database_url: String,
+    domain: String,
+    /// Whether the [`TENANT_HEADER`] is accepted as well as subdomains.
+    allow_header: bool,
+    settings: ShopSettings,
+    routes: Router<Arc<ServerState>>,
+    /// The tenants which requests have been served for so far, by slug.
+    served: Mutex<HashMap<String, ServedTenant>>,
+}
+
+struct ServedTenant {
+    /// When the tenant was created, which tells it apart from a tenant with the same slug that was
+    /// created after it was removed.
+    created_at: DateTime<Utc>,
+    router: Router,
+    state: Arc<ServerState>,
+    relay: JoinHandle<()>,
+}
+
+impl ServedTenant {
+    async fn stop(self) {
+        self.relay.abort();
+        self.state.database.close_connection().await;
+    }
+}
+
+impl Tenants {
+    pub fn new(
+        registry: Database,
+        database_url: String,
+        domain: String,
+        allow_header: bool,
+        settings: ShopSettings,
+        routes: Router<Arc<ServerState>>,
+    ) -> Self {
+        Self {
+            registry,
+            database_url,
+            domain,
+            allow_header,
+            settings,
+            routes,
+            served: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Serve every request with the routes of the tenant it is for.
+    pub fn into_router(self) -> Router {
+        let tenants = Arc::new(self);
+        let sweeper = tenants.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(SWEEP_INTERVAL).await;
+                sweeper.sweep().await;
+            }
+        });
+
+        Router::new().fallback(dispatch).with_state(tenants)
+    }
+
+    /// Stop serving every tenant which has been removed or recreated since it was connected to.
+    async fn sweep(&self) {
+        let current: HashMap<String, DateTime<Utc>> = self
+            .registry
+            .query_tenants()
+            .await
+            .into_iter()
+            .map(|tenant| (tenant.slug, tenant.created_at))
+            .collect();
+
+        let stale: Vec<ServedTenant> = {
+            let mut served = self.served.lock().await;
+            let slugs: Vec<String> = served
+                .iter()
+                .filter(|(slug, tenant)| current.get(*slug) != Some(&tenant.created_at))
+                .map(|(slug, _)| slug.clone())
+                .collect();
+            slugs
+                .iter()
+                .map(|slug| served.remove(slug).unwrap())
+                .collect()
+        };
+        for tenant in stale {
+            tenant.stop().await;
+        }
+    }
+
+    /// Find the slug of the tenant a request is for, from its subdomain or, if it is allowed, the
+    /// `X-Tenant` header.
+    fn resolve(&self, request: &Request) -> Result<String, (StatusCode, String)> {
+        let from_header = match request.headers().get(TENANT_HEADER) {
+            Some(_) if !self.allow_header => return Err((
+                StatusCode::BAD_REQUEST,
+                "the X-Tenant header is not accepted by this server, so name the shop with its \
+                     subdomain instead"
+                    .to_owned(),
+            )),
+            Some(value) => Some(value.to_str().map(str::to_ascii_lowercase).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "the X-Tenant header is not valid text".to_owned(),
+                )
+            })?),
+            None => None,
+        };
+        // * HTTP/2 requests carry their host in the URI rather than the `Host` header.
+        let host = request.uri().host().or_else(|| {
+            request
+                .headers()
+                .get(HOST)
+                .and_then(|host| host.to_str().ok())
+        });
+        let from_host = host.and_then(|host| self.subdomain_of(host));
+
+        match (from_header, from_host) {
+            (Some(header), Some(host)) if header != host => Err((
+                StatusCode::BAD_REQUEST,
+                format!("the X-Tenant header names `{header}`, but the request is for `{host}`"),
+            )),
+            (Some(slug), _) | (None, Some(slug)) => Ok(slug),
+            (None, None) => Err((
+                StatusCode::NOT_FOUND,
+                match self.allow_header {
+                    true => format!(
+                        "requests must name a shop with a subdomain of {} or the X-Tenant header",
+                        self.domain
+                    ),
+                    false => format!(
+                        "requests must name a shop with a subdomain of {}",
+                        self.domain
+                    ),
+                },
+            )),
+        }
+    }
+
+    /// Get the subdomain of the tenant domain a host is at, if it is at one.
+    fn subdomain_of(&self, host: &str) -> Option<String> {
+        let host = host
+            .rsplit_once(':')
+            .map_or(host, |(name, _)| name)
+            .to_ascii_lowercase();
+        let label = host.strip_suffix(&self.domain)?.strip_suffix('.')?;
+        match label.is_empty() || label.contains('.') {
+            true => None,
+            false => Some(label.to_owned()),
+        }
+    }
+
+    /// Get the routes of a tenant, connecting to its database if this is the first request for it.
+    ///
+    /// The registry is checked on every request so tenants can be created and removed while the
+    /// server is running, and a removed tenant stops being served straight away. The served
+    /// tenants are only locked while they are looked up and updated, so connecting to one shop's
+    /// database never holds up requests for the others.
+    async fn router_for(&self, slug: &str) -> Result<Router, (StatusCode, String)> {
+        let tenant = self.registry.query_tenant(slug).await;
+        let stale = {
+            let mut served = self.served.lock().await;
+            match (&tenant, served.get(slug)) {
+                (Some(tenant), Some(existing)) if existing.created_at == tenant.created_at => {
+                    return Ok(existing.router.clone());
+                }
+                _ => served.remove(slug),
+            }
+        };
+
+        if let Some(stale) = stale {
+            stale.stop().await;
+        }
+        let tenant = tenant.ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("there is no shop named `{slug}`"),
+            )
+        })?;
+
+        let unavailable = || {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("the database of `{slug}` is unavailable"),
+            )
+        };
+        let database = tenant.connect(&self.database_url).await.map_err(|e| {
+            eprintln!("Could not connect to the database of tenant `{slug}`: {e}");
+            unavailable()
+        })?;
+        let schema_version = database.schema_version().await;
+        if schema_version != SCHEMA_VERSION {
+            eprintln!(
+                "The database of tenant `{slug}` is at version {schema_version}, but version \
+                 {SCHEMA_VERSION} is required. Run `fixwise --tenant {slug} migrate`."
+            );
+            database.close_connection().await;
+            return Err(unavailable());
+        }
+
+        let (state, relay) = self.settings.serve(database, Some(slug));
+        let router = self.routes.clone().with_state(state.clone());
+        let connected = ServedTenant {
+            created_at: tenant.created_at,
+            router: router.clone(),
+            state,
+            relay,
+        };
+
+        let mut served = self.served.lock().await;
+        // * Other requests for the tenant may have connected to it at the same time, in which case
+        // * the first connection to be served is kept and the others are closed.
+        if let Some(existing) = served.get(slug) {
+            if existing.created_at == tenant.created_at {
+                let router = existing.router.clone();
+                drop(served);
+                connected.stop().await;
+                return Ok(router);
+            }
+        }
+        let replaced = served.insert(slug.to_owned(), connected);
+        drop(served);
+
+        if let Some(replaced) = replaced {
+            replaced.stop().await;
+        }
+        Ok(router)
+    }
+}
+
+async fn dispatch(State(tenants): State<Arc<Tenants>>, request: Request) -> Response {
+    let router = match tenants.resolve(&request) {
+        Ok(slug) => tenants.router_for(&slug).await,
+        Err(rejection) => Err(rejection),
+    };
+    match router {
+        Ok(router) => router.oneshot(request).await.unwrap(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::routing::{get, post};
+    use http::header::AUTHORIZATION;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::endpoints::sessions;
+    use crate::api::localization::{
+        find_currency, find_locale, Localization, DEFAULT_CURRENCY, DEFAULT_LOCALE,
+        DEFAULT_TIMEZONE,
+    };
+    use crate::config::Config;
+    use crate::database::shared_models::UserRole;
+    use crate::database::tables::users::UsersTableRecord;
+    use crate::photos::PhotoStore;
+
+    const DOMAIN: &str = "fixwise.test";
+
+    /// Two freshly-created tenants, each with a single user named after it, served with the
+    /// session routes.
+    struct TestTenants {
+        registry: Database,
+        slugs: [String; 2],
+        router: Router,
+        _photos: tempfile::TempDir,
+    }
+
+    impl TestTenants {
+        async fn new(name: &str, allow_header: bool) -> Self {
+            let database_url = Config::from_env().database_url;
+            let registry = Database::connect(&database_url).await.unwrap();
+            registry.prepare_tenant_registry().await;
+
+            // * Tests run in parallel, so each one gets tenants of its own.
+            let slugs = [format!("test-{name}-a"), format!("test-{name}-b")];
+            for slug in &slugs {
+                registry.delete_tenant(slug).await.ok();
+                let tenant = registry.create_tenant(slug).await.unwrap();
+                let database = tenant.connect(&database_url).await.unwrap();
+                database.migrate().await;
+                UsersTableRecord::create(&database, slug, slug, "password", UserRole::Admin)
+                    .await
+                    .unwrap();
+                database.close_connection().await;
+            }
+
+            let photos = tempfile::tempdir().unwrap();
+            let settings = ShopSettings {
+                imei_info_api_key: String::new(),
+                localization: Localization::new(
+                    find_locale(DEFAULT_LOCALE).unwrap(),
+                    find_currency(DEFAULT_CURRENCY).unwrap(),
+                    DEFAULT_TIMEZONE,
+                ),
+                portal_secret: "0123456789abcdef0123456789abcdef".to_owned(),
+                pickup_instructions: None,
+                photos: PhotoStore::directory(photos.path()),
+            };
+            let routes = Router::new()
+                .route("/login", post(sessions::login))
+                .route("/session", get(sessions::current_user));
+            let router = Tenants::new(
+                registry.clone(),
+                database_url,
+                DOMAIN.to_owned(),
+                allow_header,
+                settings,
+                routes,
+            )
+            .into_router();
+
+            Self {
+                registry,
+                slugs,
+                router,
+                _photos: photos,
+            }
+        }
+
+        async fn send(&self, request: http::request::Builder, body: Body) -> (StatusCode, Value) {
+            let response = self
+                .router
+                .clone()
+                .oneshot(request.body(body).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+        }
+
+        async fn log_in(&self, host: &str, username: &str) -> (StatusCode, Value) {
+            let request = Request::post("/login")
+                .header(HOST, host)
+                .header("content-type", "application/json");
+            let body = json!({ "username": username, "password": "password" }).to_string();
+            self.send(request, Body::from(body)).await
+        }
+
+        async fn current_user(&self, host: &str, token: &str) -> (StatusCode, Value) {
+            let request = Request::get("/session")
+                .header(HOST, host)
+                .header(AUTHORIZATION, format!("Bearer {token}"));
+            self.send(request, Body::empty()).await
+        }
+
+        fn host(&self, index: usize) -> String {
+            format!("{}.{DOMAIN}", self.slugs[index])
+        }
+
+        async fn remove(self) {
+            for slug in &self.slugs {
+                self.registry.delete_tenant(slug).await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at DATABASE_URL"]
+    async fn tenants_cannot_read_each_others_data() {
+        let tenants = TestTenants::new("isolation", false).await;
+        let [first, second] = tenants.slugs.clone();
+
+        let (status, session) = tenants.log_in(&tenants.host(0), &first).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = session["token"].as_str().unwrap().to_owned();
+        let (status, user) = tenants.current_user(&tenants.host(0), &token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["username"], first.as_str());
+
+        // * The session and the user only exist in the first tenant's database.
+        let (status, _) = tenants.current_user(&tenants.host(1), &token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = tenants.log_in(&tenants.host(1), &first).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, session) = tenants.log_in(&tenants.host(1), &second).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = session["token"].as_str().unwrap().to_owned();
+        let (status, _) = tenants.current_user(&tenants.host(0), &token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        tenants.remove().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at DATABASE_URL"]
+    async fn tenant_header_is_refused_unless_allowed() {
+        let tenants = TestTenants::new("header-refused", false).await;
+        let [first, _] = tenants.slugs.clone();
+
+        let (status, session) = tenants.log_in(&tenants.host(0), &first).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = session["token"].as_str().unwrap().to_owned();
+
+        // * Naming the first tenant in the header must not reach it from the second's subdomain,
+        // * nor from a host which is not a subdomain at all.
+        for host in [tenants.host(1), DOMAIN.to_owned()] {
+            let request = Request::get("/session")
+                .header(HOST, host)
+                .header(TENANT_HEADER, &first)
+                .header(AUTHORIZATION, format!("Bearer {token}"));
+            let (status, _) = tenants.send(request, Body::empty()).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+
+        tenants.remove().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at DATABASE_URL"]
+    async fn tenant_header_names_the_tenant_when_allowed() {
+        let tenants = TestTenants::new("header-allowed", true).await;
+        let [first, second] = tenants.slugs.clone();
+
+        let (status, session) = tenants.log_in(&tenants.host(0), &first).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = session["token"].as_str().unwrap().to_owned();
+
+        let request = Request::get("/session")
+            .header(HOST, DOMAIN)
+            .header(TENANT_HEADER, &first)
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let (status, user) = tenants.send(request, Body::empty()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["username"], first.as_str());
+
+        // * The header still cannot send a request for one tenant's subdomain to another.
+        let request = Request::get("/session")
+            .header(HOST, tenants.host(0))
+            .header(TENANT_HEADER, &second)
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let (status, _) = tenants.send(request, Body::empty()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        tenants.remove().await;
+    }
+}
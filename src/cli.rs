@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::database::generation::GenerationProfile;
+use crate::database::shared_models::UserRole;
+
+#[derive(Parser)]
+#[command(
+    name = "fixwise",
+    version,
+    about = "Fixwise repair shop management server"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+    /// Act on the database of a tenant shop rather than the configured database.
+    #[arg(long, global = true)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Serve the API, refusing to start if the database schema is out of date.
+    Serve,
+    /// Wipe the database and fill it with synthetic data for development and testing.
+    Seed {
+        /// The size of the generated dataset.
+        #[arg(long, value_enum, default_value_t = SeedProfile::Small)]
+        profile: SeedProfile,
+        /// Multiply the number of records of every table in the profile, for load testing.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        scale: u32,
+        /// The seed for the random number generator. The same seed and profile always generate
+        /// the same dataset. If omitted, a random seed is chosen and printed.
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Apply any pending database schema migrations.
+    Migrate,
+    /// Create a new user account, prompting for its password.
+    CreateUser {
+        #[arg(long)]
+        username: String,
+        /// The name shown for the user in the interface. Defaults to the username.
+        #[arg(long)]
+        display_name: Option<String>,
+        #[arg(long, value_enum, default_value_t = UserRole::Technician)]
+        role: UserRole,
+    },
+    /// Check that the configuration is valid and the database is reachable and up to date.
+    CheckConfig,
+    /// Write all shop data to a portable backup archive.
+    Backup {
+        /// The path of the archive to create.
+        path: PathBuf,
+    },
+    /// Replace all shop data with the contents of a backup archive.
+    Restore {
+        /// The path of the archive to restore from.
+        path: PathBuf,
+    },
+    /// Manage the shops hosted by a multi-tenant server, each of which has its own database.
+    Tenant {
+        #[command(subcommand)]
+        command: TenantCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TenantCommand {
+    /// List every tenant.
+    List,
+    /// Add a tenant, creating and migrating its database.
+    Create {
+        /// The name of the tenant, which is also its subdomain.
+        slug: String,
+    },
+    /// Remove a tenant, dropping its database along with all of its data.
+    Delete { slug: String },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SeedProfile {
+    Small,
+    Large,
+}
+
+impl From<SeedProfile> for GenerationProfile {
+    fn from(value: SeedProfile) -> Self {
+        match value {
+            SeedProfile::Small => GenerationProfile::SMALL,
+            SeedProfile::Large => GenerationProfile::LARGE,
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+use super::tables::appointments::{AppointmentServicesJunctionTable, AppointmentsTable};
+use super::tables::bundled_parts::BundledPartsJunctionTable;
+use super::tables::business_hours::BusinessHoursTable;
+use super::tables::compatible_parts::CompatiblePartsJunctionTable;
+use super::tables::customers::CustomersTable;
+use super::tables::device_categories::DeviceCategoriesTable;
+use super::tables::device_conditions::{DeviceConditionsJunctionTable, DevicePhotosTable};
+use super::tables::device_manufacturers::DeviceManufacturersTable;
+use super::tables::device_models::DeviceModelsTable;
+use super::tables::devices::DevicesTable;
+use super::tables::invoice_items::InvoiceItemsTable;
+use super::tables::invoice_payments::InvoicePaymentsTable;
+use super::tables::invoices::InvoicesTable;
+use super::tables::items::ItemsTable;
+use super::tables::labor_entries::LaborEntriesTable;
+use super::tables::locations::{LocationsTable, PartStockJunctionTable};
+use super::tables::part_categories::PartCategoriesTable;
+use super::tables::part_manufacturers::PartManufacturersTable;
+use super::tables::parts::PartsTable;
+use super::tables::product_prices::ProductPricesTable;
+use super::tables::products::ProductsTable;
+use super::tables::quotes::{QuoteDevicesJunctionTable, QuoteItemsJunctionTable, QuotesTable};
+use super::tables::register_sessions::{CashMovementsTable, RegisterSessionsTable};
+use super::tables::saved_views::SavedViewsTable;
+use super::tables::service_prices::ServicePricesTable;
+use super::tables::service_types::ServiceTypesTable;
+use super::tables::services::ServicesTable;
+use super::tables::ticket_assignments::TicketAssignmentsTable;
+use super::tables::ticket_devices::TicketDevicesJunctionTable;
+use super::tables::tickets::TicketsTable;
+use super::tables::transfers::TransfersTable;
+use super::tables::type_allocation_codes::TypeAllocationCodesTable;
+use super::tables::users::UsersTable;
+use super::tables::vendors::VendorsTable;
+use super::tables::warranties::{WarrantiesTable, WarrantyClaimsTable};
+use super::{BulkInsert, Database, SCHEMA_VERSION};
+
+/// The version of the archive layout itself, independent of the database schema version.
+///
+/// This only needs to change if the structure of the archive (file naming, manifest fields, record
+/// encoding) changes in a way that older versions of Fixwise could not read.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// The manifest stored at the start of every backup archive.
+///
+/// The manifest lists every table in the archive in dependency order, meaning the order in which
+/// they must be restored to keep foreign keys valid.
+#[derive(Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub schema_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub tables: Vec<BackupManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BackupManifestEntry {
+    pub schema_name: String,
+    pub relation_name: String,
+    pub file_name: String,
+    pub record_count: usize,
+    pub sha256: String,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Database(sqlx::Error),
+    MissingFile(String),
+    ChecksumMismatch(String),
+    IncompatibleVersion {
+        field: &'static str,
+        found: u32,
+        expected: u32,
+    },
+}
+
+impl Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Json(e) => write!(f, "invalid JSON in archive: {e}"),
+            Self::Database(e) => write!(f, "database error: {e}"),
+            Self::MissingFile(name) => write!(f, "archive is missing `{name}`"),
+            Self::ChecksumMismatch(name) => write!(f, "checksum mismatch for `{name}`"),
+            Self::IncompatibleVersion {
+                field,
+                found,
+                expected,
+            } => write!(
+                f,
+                "archive has {field} {found}, but this version of Fixwise expects {expected}"
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Database(value)
+    }
+}
+
+/// A table which has been exported from the database, ready to be written into an archive.
+pub struct ExportedTable {
+    entry: BackupManifestEntry,
+    contents: Vec<u8>,
+}
+
+/// A trait that allows a database table to be exported to and restored from a backup archive.
+///
+/// Each table is stored in the archive as a JSON Lines file, with one serialized record per line.
+/// Restoring a table relies on [`BulkInsert`], so records are inserted with their original IDs.
+pub trait BackupTable: BulkInsert<Record: Serialize + DeserializeOwned> {
+    /// The name of the file which holds this table's records within a backup archive.
+    fn archive_file_name() -> String {
+        format!("{}.{}.jsonl", Self::SCHEMA_NAME, Self::RELATION_NAME)
+    }
+
+    /// Query all records of the table over `connection` and serialize them as JSON Lines.
+    async fn export(connection: &mut PgConnection) -> Result<ExportedTable, sqlx::Error> {
+        let records: Vec<Self::Record> = sqlx::query_as(&format!(
+            "SELECT * FROM {}.{} ORDER BY {}",
+            Self::SCHEMA_NAME,
+            Self::RELATION_NAME,
+            Self::PRIMARY_KEY,
+        ))
+        .fetch_all(connection)
+        .await?;
+        let mut contents = Vec::new();
+        for record in &records {
+            serde_json::to_writer(&mut contents, record).unwrap();
+            contents.push(b'\n');
+        }
+
+        Ok(ExportedTable {
+            entry: BackupManifestEntry {
+                schema_name: Self::SCHEMA_NAME.to_owned(),
+                relation_name: Self::RELATION_NAME.to_owned(),
+                file_name: Self::archive_file_name(),
+                record_count: records.len(),
+                sha256: sha256_hex(&contents),
+            },
+            contents,
+        })
+    }
+
+    /// Deserialize the table's JSON Lines file from the files of an archive.
+    fn parse(files: &HashMap<String, Vec<u8>>) -> Result<Self, BackupError> {
+        let file_name = Self::archive_file_name();
+        let contents = files
+            .get(&file_name)
+            .ok_or(BackupError::MissingFile(file_name))?;
+
+        let mut records = Vec::new();
+        for line in contents.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            records.push(serde_json::from_slice(line)?);
+        }
+
+        Ok(Self::with_records(records))
+    }
+
+    /// Insert the table's records over `connection`.
+    ///
+    /// Records which are already in the table are kept, so tables which the schema or triggers
+    /// fill with records of their own are marked `(replace)` in [`backup_tables!`] to be cleared
+    /// first. Once the records are inserted, the serial sequence for the primary key (if there is
+    /// one) is advanced past the highest restored ID so that records created afterwards do not
+    /// collide with restored ones.
+    async fn import(self, connection: &mut PgConnection) -> Result<(), sqlx::Error> {
+        self.insert_all_with(connection).await?;
+        Self::reset_primary_key_sequence(connection).await
+    }
+
+    /// Delete every record of the table over `connection`.
+    async fn clear(connection: &mut PgConnection) -> Result<(), sqlx::Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {}.{}",
+            Self::SCHEMA_NAME,
+            Self::RELATION_NAME
+        ))
+        .execute(connection)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Define [`ArchivedTables`] from the tables included in backups, which are listed in dependency
+/// order so that restoring them in that order keeps foreign keys valid.
+///
+/// Tables marked with `(replace)` are emptied right before they are restored, since the schema or
+/// the triggers on earlier tables fill them with records of their own.
+macro_rules! backup_tables {
+    ($($field:ident: $table:ident $(($replace:ident))?),* $(,)?) => {
+        /// Every table of a backup archive, parsed into records.
+        struct ArchivedTables {
+            $($field: $table,)*
+        }
+
+        impl ArchivedTables {
+            /// Export every table over `connection`, in dependency order.
+            async fn export(connection: &mut PgConnection) -> Result<Vec<ExportedTable>, sqlx::Error> {
+                Ok(vec![$(<$table>::export(connection).await?,)*])
+            }
+
+            /// Parse every table from the files of an archive, failing if any is missing or
+            /// malformed.
+            fn parse(files: &HashMap<String, Vec<u8>>) -> Result<Self, BackupError> {
+                Ok(Self {
+                    $($field: <$table>::parse(files)?,)*
+                })
+            }
+
+            /// Insert every table over `connection`, in dependency order.
+            async fn import(self, connection: &mut PgConnection) -> Result<(), sqlx::Error> {
+                $(
+                    $(backup_tables!(@$replace $table, connection);)?
+                    self.$field.import(connection).await?;
+                )*
+
+                Ok(())
+            }
+        }
+    };
+    (@replace $table:ident, $connection:ident) => {
+        <$table>::clear($connection).await?;
+    };
+}
+
+backup_tables! {
+    users: UsersTable,
+    saved_views: SavedViewsTable,
+    device_categories: DeviceCategoriesTable,
+    part_categories: PartCategoriesTable,
+    service_types: ServiceTypesTable,
+    // * The schema comes with default business hours, which are replaced by the archived ones.
+    business_hours: BusinessHoursTable (replace),
+    // * The same goes for the default store.
+    locations: LocationsTable (replace),
+    vendors: VendorsTable,
+    device_manufacturers: DeviceManufacturersTable,
+    part_manufacturers: PartManufacturersTable,
+    device_models: DeviceModelsTable,
+    parts: PartsTable,
+    part_stock: PartStockJunctionTable,
+    products: ProductsTable,
+    product_prices: ProductPricesTable,
+    services: ServicesTable,
+    service_prices: ServicePricesTable,
+    // * Inserting products and services creates new items through triggers, so they need to be
+    // * cleared out before the archived items (and their original IDs) are restored.
+    items: ItemsTable (replace),
+    customers: CustomersTable,
+    devices: DevicesTable,
+    // * Transfers come before ticket devices so devices on their way between stores are not
+    // * taken to be held by the store of their ticket.
+    transfers: TransfersTable,
+    invoices: InvoicesTable,
+    invoice_items: InvoiceItemsTable,
+    invoice_payments: InvoicePaymentsTable,
+    tickets: TicketsTable,
+    compatible_parts: CompatiblePartsJunctionTable,
+    ticket_devices: TicketDevicesJunctionTable,
+    device_conditions: DeviceConditionsJunctionTable,
+    device_photos: DevicePhotosTable,
+    ticket_assignments: TicketAssignmentsTable,
+    labor_entries: LaborEntriesTable,
+    appointments: AppointmentsTable,
+    appointment_services: AppointmentServicesJunctionTable,
+    // * Tickets which were closed when they were backed up start warranties through triggers as
+    // * they and their devices are restored, so those are replaced by the archived warranties.
+    warranties: WarrantiesTable (replace),
+    warranty_claims: WarrantyClaimsTable,
+    quotes: QuotesTable,
+    quote_devices: QuoteDevicesJunctionTable,
+    quote_items: QuoteItemsJunctionTable,
+    register_sessions: RegisterSessionsTable,
+    cash_movements: CashMovementsTable,
+    bundled_parts: BundledPartsJunctionTable,
+    // * Type allocation codes live in the `persistent` schema, which resetting the database leaves
+    // * alone.
+    type_allocation_codes: TypeAllocationCodesTable (replace),
+}
+
+impl Database {
+    /// Export every table in the `main` and `persistent` schemas into a backup archive at `path`.
+    ///
+    /// The archive is a tar file containing a `manifest.json` followed by one JSON Lines file per
+    /// table, in dependency order. Every table is read from the same snapshot of the database, so
+    /// backing up a running server cannot capture records without the ones they refer to.
+    pub async fn backup(&self, path: &Path) -> Result<BackupManifest, BackupError> {
+        let mut transaction = self.connection.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(&mut *transaction)
+            .await?;
+        let tables = ArchivedTables::export(&mut transaction).await?;
+        transaction.commit().await?;
+
+        let (entries, contents): (Vec<_>, Vec<_>) = tables
+            .into_iter()
+            .map(|table| (table.entry, table.contents))
+            .unzip();
+        let manifest = BackupManifest {
+            format_version: ARCHIVE_FORMAT_VERSION,
+            schema_version: SCHEMA_VERSION,
+            created_at: Utc::now(),
+            tables: entries,
+        };
+
+        let mut archive = tar::Builder::new(File::create(path)?);
+        append_archive_file(
+            &mut archive,
+            MANIFEST_FILE_NAME,
+            &serde_json::to_vec_pretty(&manifest)?,
+        )?;
+        for (entry, contents) in manifest.tables.iter().zip(contents) {
+            append_archive_file(&mut archive, &entry.file_name, &contents)?;
+        }
+        archive.into_inner()?.sync_all()?;
+
+        Ok(manifest)
+    }
+
+    /// Replace the contents of the database with the contents of the backup archive at `path`.
+    ///
+    /// The archive is fully read, verified against its manifest and parsed before anything in the
+    /// database is touched. Restoring recreates the `main` schema and clears the `persistent`
+    /// schema, so any existing data is lost, but all of it happens in one transaction: if
+    /// anything fails, the database is left as it was.
+    ///
+    /// The custom types of the schema are recreated too, so this should be called on a new
+    /// connection rather than one which has already used them.
+    pub async fn restore(&self, path: &Path) -> Result<BackupManifest, BackupError> {
+        let mut files = read_archive(path)?;
+        let manifest: BackupManifest = serde_json::from_slice(
+            &files
+                .remove(MANIFEST_FILE_NAME)
+                .ok_or_else(|| BackupError::MissingFile(MANIFEST_FILE_NAME.to_owned()))?,
+        )?;
+
+        if manifest.format_version != ARCHIVE_FORMAT_VERSION {
+            return Err(BackupError::IncompatibleVersion {
+                field: "format version",
+                found: manifest.format_version,
+                expected: ARCHIVE_FORMAT_VERSION,
+            });
+        }
+
+        if manifest.schema_version != SCHEMA_VERSION {
+            return Err(BackupError::IncompatibleVersion {
+                field: "schema version",
+                found: manifest.schema_version,
+                expected: SCHEMA_VERSION,
+            });
+        }
+
+        for entry in &manifest.tables {
+            let contents = files
+                .get(&entry.file_name)
+                .ok_or_else(|| BackupError::MissingFile(entry.file_name.clone()))?;
+            if sha256_hex(contents) != entry.sha256 {
+                return Err(BackupError::ChecksumMismatch(entry.file_name.clone()));
+            }
+        }
+
+        let tables = ArchivedTables::parse(&files)?;
+        drop(files);
+
+        let mut transaction = self.connection.begin().await?;
+        Self::reset_with(&mut transaction).await?;
+        tables.import(&mut transaction).await?;
+        transaction.commit().await?;
+
+        Ok(manifest)
+    }
+}
+
+fn read_archive(path: &Path) -> Result<HashMap<String, Vec<u8>>, BackupError> {
+    let mut archive = tar::Archive::new(File::open(path)?);
+    let mut files = HashMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let file_name = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.insert(file_name, contents);
+    }
+
+    Ok(files)
+}
+
+fn append_archive_file(
+    archive: &mut tar::Builder<File>,
+    file_name: &str,
+    contents: &[u8],
+) -> Result<(), BackupError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, file_name, contents)?;
+
+    Ok(())
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::database::generation::GenerationProfile;
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at DATABASE_URL"]
+    async fn restoring_a_backup_brings_back_every_record() {
+        let database_url = Config::from_env().database_url;
+        let registry = Database::connect(&database_url).await.unwrap();
+        registry.prepare_tenant_registry().await;
+        let slug = "test-backup-round-trip";
+        registry.delete_tenant(slug).await.ok();
+        let tenant = registry.create_tenant(slug).await.unwrap();
+        let database = tenant.connect(&database_url).await.unwrap();
+        database.reset().await;
+        database
+            .add_generated_items(GenerationProfile::TINY, 7)
+            .await;
+
+        let directory = tempfile::tempdir().unwrap();
+        let backed_up = database
+            .backup(&directory.path().join("first.tar"))
+            .await
+            .unwrap();
+        // * A record created after the backup should be gone once it is restored.
+        sqlx::query("INSERT INTO main.vendors (display_name) VALUES ('Not backed up')")
+            .execute(&database.connection)
+            .await
+            .unwrap();
+        // * Connections remember the IDs of the types they have used, which restoring recreates, so
+        // * the backup is restored over a new connection like `fixwise restore` does.
+        database.close_connection().await;
+        let database = tenant.connect(&database_url).await.unwrap();
+        database
+            .restore(&directory.path().join("first.tar"))
+            .await
+            .unwrap();
+        let restored = database
+            .backup(&directory.path().join("second.tar"))
+            .await
+            .unwrap();
+
+        database.close_connection().await;
+        registry.delete_tenant(slug).await.unwrap();
+
+        assert!(backed_up.tables.iter().any(|table| table.record_count > 0));
+        assert_eq!(backed_up.tables.len(), restored.tables.len());
+        for (before, after) in backed_up.tables.iter().zip(&restored.tables) {
+            assert_eq!(
+                (before.record_count, &before.sha256),
+                (after.record_count, &after.sha256),
+                "{} changed after being restored",
+                before.relation_name
+            );
+        }
+    }
+}
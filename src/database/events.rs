@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast;
+
+use super::shared_models::{PaymentType, TicketStatus};
+use super::Database;
+
+/// The Postgres channel that the triggers in the `change_events` migration notify.
+const EVENTS_CHANNEL: &str = "fixwise_events";
+/// How long the listener waits before trying to reconnect after losing its connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A change to the database that clients can subscribe to.
+///
+/// These are sent by database triggers rather than by the server itself, so changes made outside
+/// of the API (like from `psql` or another server) are announced as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChangeEvent {
+    TicketCreated {
+        ticket: i32,
+        status: TicketStatus,
+        customer: Option<i32>,
+    },
+    TicketStatusChanged {
+        ticket: i32,
+        old_status: TicketStatus,
+        new_status: TicketStatus,
+    },
+    PaymentRecorded {
+        payment: i32,
+        invoice: i32,
+        amount: Decimal,
+        payment_type: PaymentType,
+    },
+    StockLow {
+        location: i32,
+        part: i32,
+        quantity: i32,
+        threshold: i32,
+    },
+}
+
+impl Database {
+    /// Relay every [`ChangeEvent`] announced by the database to `sender` until the server stops.
+    ///
+    /// The listener reconnects by itself if its connection is lost, although any events announced
+    /// while it was disconnected are missed.
+    pub async fn relay_change_events(&self, sender: broadcast::Sender<ChangeEvent>) {
+        let mut listener = PgListener::connect_with(&self.connection).await.unwrap();
+        listener.listen(EVENTS_CHANNEL).await.unwrap();
+
+        loop {
+            let notification = match listener.recv().await {
+                Ok(notification) => notification,
+                Err(e) => {
+                    eprintln!("Lost connection to change events: {e}");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            match serde_json::from_str(notification.payload()) {
+                // * Sending only fails when nobody is subscribed, which is fine.
+                Ok(event) => drop(sender.send(event)),
+                Err(e) => eprintln!("Ignoring malformed change event: {e}"),
+            }
+        }
+    }
+}
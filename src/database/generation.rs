@@ -0,0 +1,574 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use tokio::sync::{mpsc, oneshot};
+
+use super::tables::bundled_parts::BundledPartsJunctionTable;
+use super::tables::compatible_parts::CompatiblePartsJunctionTable;
+use super::tables::customers::CustomersTable;
+use super::tables::device_categories::DeviceCategoriesTable;
+use super::tables::device_manufacturers::DeviceManufacturersTable;
+use super::tables::device_models::DeviceModelsTable;
+use super::tables::devices::DevicesTable;
+use super::tables::generators::{GenerationRng, GroupedIds};
+use super::tables::invoice_items::InvoiceItemsTable;
+use super::tables::invoice_payments::InvoicePaymentsTable;
+use super::tables::invoices::{InvoiceLedger, InvoicesTable};
+use super::tables::items::{ItemPrices, ItemsTable};
+use super::tables::locations::LocationsTable;
+use super::tables::part_categories::PartCategoriesTable;
+use super::tables::part_manufacturers::PartManufacturersTable;
+use super::tables::parts::PartsTable;
+use super::tables::product_prices::ProductPricesTable;
+use super::tables::products::ProductsTable;
+use super::tables::service_prices::ServicePricesTable;
+use super::tables::service_types::ServiceTypesTable;
+use super::tables::services::ServicesTable;
+use super::tables::ticket_devices::TicketDevicesJunctionTable;
+use super::tables::tickets::TicketsTable;
+use super::tables::vendors::VendorsTable;
+use super::tables::IdentifiableRecord;
+use super::{
+    BulkInsert, Database, GenerateRecord, GenerateStaticTable, GenerateTable, Relation, Table,
+};
+
+/// The number of records of each table to generate when seeding the database with synthetic data.
+#[derive(Clone, Copy)]
+pub struct GenerationProfile {
+    vendors: usize,
+    device_manufacturers: usize,
+    part_manufacturers: usize,
+    device_models: usize,
+    parts: usize,
+    products: usize,
+    product_prices: usize,
+    services: usize,
+    service_prices: usize,
+    customers: usize,
+    devices: usize,
+    invoices: usize,
+    invoice_items: usize,
+    invoice_payments: usize,
+    tickets: usize,
+    compatible_parts: usize,
+    ticket_devices: usize,
+    bundled_parts: usize,
+}
+
+impl GenerationProfile {
+    /// A dataset of a few thousand records per table, suitable for local development.
+    pub const SMALL: Self = Self {
+        vendors: 123,
+        device_manufacturers: 123,
+        part_manufacturers: 123,
+        device_models: 123,
+        parts: 1234,
+        products: 1234,
+        product_prices: 1234,
+        services: 1234,
+        service_prices: 1234,
+        customers: 1234,
+        devices: 1234,
+        invoices: 1234,
+        invoice_items: 12345,
+        invoice_payments: 1234,
+        tickets: 1234,
+        compatible_parts: 1234,
+        ticket_devices: 1234,
+        bundled_parts: 1234,
+    };
+
+    /// A dataset roughly ten times the size of [`GenerationProfile::SMALL`], suitable for testing
+    /// the performance of the API and the frontend.
+    pub const LARGE: Self = Self {
+        vendors: 250,
+        device_manufacturers: 250,
+        part_manufacturers: 250,
+        device_models: 1234,
+        parts: 12345,
+        products: 12345,
+        product_prices: 12345,
+        services: 12345,
+        service_prices: 12345,
+        customers: 12345,
+        devices: 12345,
+        invoices: 12345,
+        invoice_items: 123456,
+        invoice_payments: 12345,
+        tickets: 12345,
+        compatible_parts: 12345,
+        ticket_devices: 12345,
+        bundled_parts: 12345,
+    };
+
+    /// A dataset small enough to review changes to the seeded dataset snapshot by eye, for tests.
+    #[cfg(test)]
+    pub const TINY: Self = Self {
+        vendors: 3,
+        device_manufacturers: 3,
+        part_manufacturers: 3,
+        device_models: 5,
+        parts: 8,
+        products: 4,
+        product_prices: 12,
+        services: 6,
+        service_prices: 12,
+        customers: 5,
+        devices: 6,
+        invoices: 6,
+        invoice_items: 12,
+        invoice_payments: 2,
+        tickets: 5,
+        compatible_parts: 8,
+        ticket_devices: 8,
+        bundled_parts: 5,
+    };
+
+    /// Multiply the number of records of every table by `factor`, for load testing with datasets
+    /// larger than [`GenerationProfile::LARGE`].
+    pub fn scaled(self, factor: usize) -> Self {
+        Self {
+            vendors: self.vendors * factor,
+            device_manufacturers: self.device_manufacturers * factor,
+            part_manufacturers: self.part_manufacturers * factor,
+            device_models: self.device_models * factor,
+            parts: self.parts * factor,
+            products: self.products * factor,
+            product_prices: self.product_prices * factor,
+            services: self.services * factor,
+            service_prices: self.service_prices * factor,
+            customers: self.customers * factor,
+            devices: self.devices * factor,
+            invoices: self.invoices * factor,
+            invoice_items: self.invoice_items * factor,
+            invoice_payments: self.invoice_payments * factor,
+            tickets: self.tickets * factor,
+            compatible_parts: self.compatible_parts * factor,
+            ticket_devices: self.ticket_devices * factor,
+            bundled_parts: self.bundled_parts * factor,
+        }
+    }
+}
+
+/// A generated table waiting to be inserted into the database.
+///
+/// The table is shared with the generation thread, which may still need it to generate the tables
+/// that depend on it, so it is inserted from a borrow rather than being copied.
+trait PendingInsert: Send + Sync {
+    fn insert(self: Arc<Self>, database: Database) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+impl<T: BulkInsert + Send + Sync + 'static> PendingInsert for T {
+    fn insert(self: Arc<Self>, database: Database) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async move {
+            let mut connection = database.connection.acquire().await.unwrap();
+            self.insert_all_with(&mut connection).await.unwrap();
+            // * IDs are generated rather than drawn from the sequences, which would otherwise hand
+            // * out the same IDs again to the first records created afterwards.
+            T::reset_primary_key_sequence(&mut connection)
+                .await
+                .unwrap();
+        })
+    }
+}
+
+/// A message sent from the generation thread to the task inserting generated tables.
+enum GenerationMessage {
+    Insert(Arc<dyn PendingInsert>, usize),
+    /// Fetch the items table once every product and service before this message has been
+    /// inserted, as items are created by triggers rather than generated.
+    FetchItems(oneshot::Sender<ItemsTable>),
+}
+
+/// The generation half of [`Database::add_generated_items`], which is run on its own thread.
+///
+/// Each table is sent to be inserted as soon as it has been generated, in dependency order.
+struct DatasetGenerator {
+    rng: GenerationRng,
+    sender: mpsc::UnboundedSender<GenerationMessage>,
+}
+
+impl DatasetGenerator {
+    fn generate_static<T: GenerateStaticTable + BulkInsert + Send + Sync + 'static>(
+        &self,
+    ) -> Arc<T> {
+        let table = Arc::new(T::generate());
+        self.insert(&table);
+        table
+    }
+
+    fn generate<T: GenerateTable + BulkInsert + Send + Sync + 'static>(
+        &mut self,
+        name: &str,
+        count: usize,
+        dependencies: <T::Record as GenerateRecord>::Dependencies<'_>,
+    ) -> Arc<T> {
+        eprintln!("Generating {count} {name}");
+        let start_time = Instant::now();
+        let table = Arc::new(T::generate(count, dependencies, &mut self.rng));
+        let generated = table.records().len();
+        eprintln!(
+            "Generated {generated} {name} in {}",
+            format_throughput(generated, start_time.elapsed())
+        );
+
+        self.insert(&table);
+        table
+    }
+
+    fn insert<T: BulkInsert + Send + Sync + 'static>(&self, table: &Arc<T>) {
+        let count = table.records().len();
+        // * The receiver is only dropped once every message has been handled.
+        self.sender
+            .send(GenerationMessage::Insert(Arc::clone(table) as _, count))
+            .ok()
+            .unwrap();
+    }
+
+    fn fetch_items(&self) -> ItemsTable {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(GenerationMessage::FetchItems(sender))
+            .ok()
+            .unwrap();
+        receiver.blocking_recv().unwrap()
+    }
+
+    fn run(&mut self, profile: GenerationProfile) {
+        let device_categories = self.generate_static::<DeviceCategoriesTable>();
+        let part_categories = self.generate_static::<PartCategoriesTable>();
+        let service_types = self.generate_static::<ServiceTypesTable>();
+        let locations = self.generate_static::<LocationsTable>();
+
+        let vendors = self.generate::<VendorsTable>("vendors", profile.vendors, ());
+        let device_manufacturers = self.generate::<DeviceManufacturersTable>(
+            "device manufacturers",
+            profile.device_manufacturers,
+            (),
+        );
+        let part_manufacturers = self.generate::<PartManufacturersTable>(
+            "part manufacturers",
+            profile.part_manufacturers,
+            (),
+        );
+        let device_models = self.generate::<DeviceModelsTable>(
+            "device models",
+            profile.device_models,
+            (&device_manufacturers, &device_categories),
+        );
+        let parts = self.generate::<PartsTable>(
+            "parts",
+            profile.parts,
+            (
+                &vendors,
+                &part_manufacturers,
+                &part_categories,
+                &device_models,
+            ),
+        );
+        let products = self.generate::<ProductsTable>("products", profile.products, ());
+        let product_prices = self.generate::<ProductPricesTable>(
+            "product prices",
+            profile.product_prices,
+            &products,
+        );
+        let services = self.generate::<ServicesTable>(
+            "services",
+            profile.services,
+            (&service_types, &device_models),
+        );
+        let service_prices = self.generate::<ServicePricesTable>(
+            "service prices",
+            profile.service_prices,
+            &services,
+        );
+        let customers = self.generate::<CustomersTable>("customers", profile.customers, ());
+        let devices =
+            self.generate::<DevicesTable>("devices", profile.devices, (&device_models, &customers));
+
+        let items = self.fetch_items();
+        let item_prices = ItemPrices::new(&items, &product_prices, &service_prices);
+
+        let invoices = self.generate::<InvoicesTable>("invoices", profile.invoices, &locations);
+        let invoice_items = self.generate::<InvoiceItemsTable>(
+            "invoice items",
+            profile.invoice_items,
+            (&invoices, &items, &item_prices),
+        );
+        let ledger = InvoiceLedger::new(&invoices, &invoice_items);
+        self.generate::<InvoicePaymentsTable>(
+            "invoice payments",
+            profile.invoice_payments,
+            &ledger,
+        );
+        let tickets = self.generate::<TicketsTable>(
+            "tickets",
+            profile.tickets,
+            (&customers, &invoices, &ledger, &locations),
+        );
+
+        let models_by_name = GroupedIds::new(
+            device_models
+                .records()
+                .iter()
+                .map(|m| (m.display_name.clone(), m.id())),
+        );
+        self.generate::<CompatiblePartsJunctionTable>(
+            "compatible parts",
+            profile.compatible_parts,
+            (&device_models, &parts, &models_by_name),
+        );
+
+        let services_by_model =
+            GroupedIds::new(services.records().iter().map(|s| (s.device, s.id())));
+        let ticket_devices = self.generate::<TicketDevicesJunctionTable>(
+            "ticket devices",
+            profile.ticket_devices,
+            (&tickets, &devices, &services, &services_by_model),
+        );
+        self.generate::<BundledPartsJunctionTable>(
+            "bundled parts",
+            profile.bundled_parts,
+            (&ticket_devices, &parts),
+        );
+    }
+}
+
+fn format_throughput(count: usize, elapsed: Duration) -> String {
+    format!(
+        "{}ms ({:.0} records/s)",
+        elapsed.as_millis(),
+        count as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    )
+}
+
+impl Database {
+    /// Generate synthetic data for every table and insert it into the database.
+    ///
+    /// The data is entirely determined by `profile` and `seed`, so seeding a freshly-reset database
+    /// twice with the same arguments yields identical datasets.
+    ///
+    /// Generation is CPU-bound, so it runs on its own thread while each finished table is inserted
+    /// on this task, overlapping the generation of each table with the insertion of the last.
+    pub async fn add_generated_items(&self, profile: GenerationProfile, seed: u64) {
+        let start_time = Instant::now();
+        // * The schema comes with a default store, which is replaced by the generated ones.
+        LocationsTable::delete_all(self).await;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let generator = thread::spawn(move || {
+            DatasetGenerator {
+                rng: GenerationRng::seed_from_u64(seed),
+                sender,
+            }
+            .run(profile)
+        });
+
+        let mut inserted = 0;
+        while let Some(message) = receiver.recv().await {
+            match message {
+                GenerationMessage::Insert(table, count) => {
+                    table.insert(self.clone()).await;
+                    inserted += count;
+                }
+                GenerationMessage::FetchItems(sender) => {
+                    sender.send(ItemsTable::query_all(self).await).ok().unwrap();
+                }
+            }
+        }
+        generator.join().unwrap();
+
+        println!(
+            "Generated and inserted {inserted} records in {}",
+            format_throughput(inserted, start_time.elapsed())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use rust_decimal::Decimal;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::database::tables::invoice_items::InvoiceItemsTableRecord;
+
+    /// The file holding the dataset generated by [`GenerationProfile::TINY`] and [`SEED`].
+    const SNAPSHOT_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/database/snapshots/seeded_dataset.jsonl"
+    );
+    const SEED: u64 = 42;
+
+    async fn append_table<T: Table<Record: Serialize>>(database: &Database, snapshot: &mut String) {
+        writeln!(snapshot, "# {}.{}", T::SCHEMA_NAME, T::RELATION_NAME).unwrap();
+        for record in T::query_all(database).await.take_records() {
+            writeln!(snapshot, "{}", serde_json::to_string(&record).unwrap()).unwrap();
+        }
+    }
+
+    /// Every generated table (and the items created for its products and services) of the
+    /// database, one JSON record per line.
+    async fn dataset_snapshot(database: &Database) -> String {
+        let mut snapshot = String::new();
+        append_table::<DeviceCategoriesTable>(database, &mut snapshot).await;
+        append_table::<PartCategoriesTable>(database, &mut snapshot).await;
+        append_table::<ServiceTypesTable>(database, &mut snapshot).await;
+        append_table::<LocationsTable>(database, &mut snapshot).await;
+        append_table::<VendorsTable>(database, &mut snapshot).await;
+        append_table::<DeviceManufacturersTable>(database, &mut snapshot).await;
+        append_table::<PartManufacturersTable>(database, &mut snapshot).await;
+        append_table::<DeviceModelsTable>(database, &mut snapshot).await;
+        append_table::<PartsTable>(database, &mut snapshot).await;
+        append_table::<ProductsTable>(database, &mut snapshot).await;
+        append_table::<ProductPricesTable>(database, &mut snapshot).await;
+        append_table::<ServicesTable>(database, &mut snapshot).await;
+        append_table::<ServicePricesTable>(database, &mut snapshot).await;
+        append_table::<ItemsTable>(database, &mut snapshot).await;
+        append_table::<CustomersTable>(database, &mut snapshot).await;
+        append_table::<DevicesTable>(database, &mut snapshot).await;
+        append_table::<InvoicesTable>(database, &mut snapshot).await;
+        append_table::<InvoiceItemsTable>(database, &mut snapshot).await;
+        append_table::<InvoicePaymentsTable>(database, &mut snapshot).await;
+        append_table::<TicketsTable>(database, &mut snapshot).await;
+        append_table::<CompatiblePartsJunctionTable>(database, &mut snapshot).await;
+        append_table::<TicketDevicesJunctionTable>(database, &mut snapshot).await;
+        append_table::<BundledPartsJunctionTable>(database, &mut snapshot).await;
+        snapshot
+    }
+
+    /// Set `UPDATE_SNAPSHOTS=true` to rewrite the snapshot after changing how data is generated.
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at DATABASE_URL"]
+    async fn seeded_dataset_matches_snapshot() {
+        // * The dataset is generated in a scratch database so the configured one is left alone.
+        let database_url = Config::from_env().database_url;
+        let registry = Database::connect(&database_url).await.unwrap();
+        registry.prepare_tenant_registry().await;
+        let slug = "test-seeded-dataset";
+        registry.delete_tenant(slug).await.ok();
+        let database = registry
+            .create_tenant(slug)
+            .await
+            .unwrap()
+            .connect(&database_url)
+            .await
+            .unwrap();
+
+        database.reset().await;
+        database
+            .add_generated_items(GenerationProfile::TINY, SEED)
+            .await;
+        let snapshot = dataset_snapshot(&database).await;
+        database.close_connection().await;
+        registry.delete_tenant(slug).await.unwrap();
+
+        if std::env::var("UPDATE_SNAPSHOTS").is_ok_and(|value| value == "true") {
+            std::fs::write(SNAPSHOT_PATH, &snapshot).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(SNAPSHOT_PATH).unwrap_or_default();
+        assert!(
+            snapshot == expected,
+            "the dataset generated from seed {SEED} does not match {SNAPSHOT_PATH}; rerun with \
+             UPDATE_SNAPSHOTS=true if the change is intended"
+        );
+    }
+
+    /// Insert the same synthetic customers with chunked `INSERT` statements and with `COPY`,
+    /// printing the time taken by each.
+    ///
+    /// Run it with `cargo test --release bulk_insert_benchmark -- --ignored --nocapture`, setting
+    /// `BENCHMARK_RECORDS` to insert another number of customers than 100,000.
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at DATABASE_URL"]
+    async fn bulk_insert_benchmark() {
+        let count = std::env::var("BENCHMARK_RECORDS")
+            .map(|records| records.parse().unwrap())
+            .unwrap_or(100_000);
+        let database_url = Config::from_env().database_url;
+        let registry = Database::connect(&database_url).await.unwrap();
+        registry.prepare_tenant_registry().await;
+        let slug = "test-bulk-insert-benchmark";
+        registry.delete_tenant(slug).await.ok();
+        let database = registry
+            .create_tenant(slug)
+            .await
+            .unwrap()
+            .connect(&database_url)
+            .await
+            .unwrap();
+        database.reset().await;
+
+        let customers = CustomersTable::generate(count, (), &mut GenerationRng::seed_from_u64(0));
+        let mut connection = database.connection.acquire().await.unwrap();
+
+        let start_time = Instant::now();
+        customers.insert_all_chunked(&mut connection).await.unwrap();
+        let chunked_elapsed = start_time.elapsed();
+        assert_eq!(
+            CustomersTable::query_all(&database).await.records().len(),
+            count
+        );
+        println!(
+            "Chunked INSERT: {count} records in {}",
+            format_throughput(count, chunked_elapsed)
+        );
+
+        CustomersTable::delete_all(&database).await;
+        let start_time = Instant::now();
+        customers.copy_all(&mut connection).await.unwrap();
+        let copy_elapsed = start_time.elapsed();
+        assert_eq!(
+            CustomersTable::query_all(&database).await.records().len(),
+            count
+        );
+        println!(
+            "COPY: {count} records in {}",
+            format_throughput(count, copy_elapsed)
+        );
+        println!(
+            "COPY was {:.1}x as fast as chunked INSERT",
+            chunked_elapsed.as_secs_f64() / copy_elapsed.as_secs_f64().max(f64::EPSILON)
+        );
+
+        drop(connection);
+        database.close_connection().await;
+        registry.delete_tenant(slug).await.unwrap();
+    }
+
+    #[test]
+    fn payments_stop_once_every_invoice_is_paid() {
+        let mut rng = GenerationRng::seed_from_u64(SEED);
+        let locations = LocationsTable::generate();
+        let invoices = InvoicesTable::generate(3, &locations, &mut rng);
+        let invoice_items = InvoiceItemsTable::with_records(
+            invoices
+                .records()
+                .iter()
+                .map(|invoice| InvoiceItemsTableRecord {
+                    invoice: invoice.id,
+                    item: 1,
+                    covered: false,
+                    unit_price: Decimal::new(1000, 2),
+                    override_price: None,
+                    override_reason: None,
+                    overridden_by: None,
+                })
+                .collect(),
+        );
+        let ledger = InvoiceLedger::new(&invoices, &invoice_items);
+
+        let payments = InvoicePaymentsTable::generate(1000, &ledger, &mut rng);
+
+        assert!(!payments.records().is_empty());
+        assert!(payments.records().len() < 1000);
+        assert!(!ledger.has_unpaid());
+    }
+}
@@ -0,0 +1,120 @@
+use sqlx::{raw_sql, PgConnection, Row};
+
+use super::Database;
+
+/// The SQL migrations which make up the database schema, in the order they must be applied.
+///
+/// The schema version of a database is the number of migrations which have been applied to it, so
+/// migrations must never be removed or reordered once they have been released. Any change to the
+/// schema should be made by adding a new migration to the end of this list.
+pub const MIGRATIONS: &[&str] = &[
+    include_str!("../../database/migrations/0001_initial.pgsql"),
+    include_str!("../../database/migrations/0002_users.pgsql"),
+    include_str!("../../database/migrations/0003_saved_views.pgsql"),
+    include_str!("../../database/migrations/0004_timestamptz.pgsql"),
+    include_str!("../../database/migrations/0005_change_events.pgsql"),
+    include_str!("../../database/migrations/0006_ticket_assignments.pgsql"),
+    include_str!("../../database/migrations/0007_labor_entries.pgsql"),
+    include_str!("../../database/migrations/0008_appointments.pgsql"),
+    include_str!("../../database/migrations/0009_warranties.pgsql"),
+    include_str!("../../database/migrations/0010_ticket_portal.pgsql"),
+    include_str!("../../database/migrations/0011_quotes.pgsql"),
+    include_str!("../../database/migrations/0012_invoice_price_locking.pgsql"),
+    include_str!("../../database/migrations/0013_cash_drawers.pgsql"),
+    include_str!("../../database/migrations/0014_locations.pgsql"),
+    include_str!("../../database/migrations/0015_device_conditions.pgsql"),
+    include_str!("../../database/migrations/0016_sessions.pgsql"),
+    include_str!("../../database/migrations/0017_low_stock_events.pgsql"),
+    include_str!("../../database/migrations/0018_warranties_on_insert.pgsql"),
+    include_str!("../../database/migrations/0019_migration_timestamptz.pgsql"),
+];
+
+impl Database {
+    /// Get the version of the schema which is currently applied to the database.
+    ///
+    /// Databases configured before migrations were tracked have a `main` schema but no
+    /// `main.schema_migrations` table, so they are assumed to be at the initial version.
+    pub async fn schema_version(&self) -> u32 {
+        let row = sqlx::query(
+            "SELECT to_regclass('main.schema_migrations') IS NOT NULL AS tracked, \
+             EXISTS (SELECT 1 FROM information_schema.schemata WHERE schema_name = 'main') AS configured",
+        )
+        .fetch_one(&self.connection)
+        .await
+        .unwrap();
+
+        match (row.get("tracked"), row.get("configured")) {
+            (true, _) => {
+                let version: i32 = sqlx::query_scalar(
+                    "SELECT COALESCE(MAX(version), 0) FROM main.schema_migrations",
+                )
+                .fetch_one(&self.connection)
+                .await
+                .unwrap();
+                version as u32
+            }
+            (false, true) => 1,
+            (false, false) => 0,
+        }
+    }
+
+    /// Apply all migrations which have not yet been applied to the database.
+    ///
+    /// Each migration is applied in its own transaction along with the record of it being applied,
+    /// so a failed migration leaves the database at the previous version. Returns the versions of
+    /// the migrations which were applied.
+    pub async fn migrate(&self) -> Vec<u32> {
+        let current_version = self.schema_version().await;
+        let mut applied = Vec::new();
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(current_version as usize) {
+            let version = index as u32 + 1;
+            let mut transaction = self.connection.begin().await.unwrap();
+            raw_sql(migration).execute(&mut *transaction).await.unwrap();
+            record_migration(&mut transaction, version).await.unwrap();
+            transaction.commit().await.unwrap();
+
+            applied.push(version);
+        }
+
+        // * A database configured before migrations were tracked needs its initial version recorded
+        // * even if there is nothing left to apply.
+        if applied.is_empty() && current_version > 0 {
+            let mut transaction = self.connection.begin().await.unwrap();
+            record_migration(&mut transaction, current_version)
+                .await
+                .unwrap();
+            transaction.commit().await.unwrap();
+        }
+
+        applied
+    }
+}
+
+/// Apply every migration to a database without a schema over `connection`, without committing
+/// anything, so that the new schema can be filled in within the same transaction.
+pub(super) async fn apply_all(connection: &mut PgConnection) -> Result<(), sqlx::Error> {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        raw_sql(migration).execute(&mut *connection).await?;
+        record_migration(connection, index as u32 + 1).await?;
+    }
+
+    Ok(())
+}
+
+async fn record_migration(connection: &mut PgConnection, version: u32) -> Result<(), sqlx::Error> {
+    raw_sql(
+        "CREATE TABLE IF NOT EXISTS main.schema_migrations (
+            version integer PRIMARY KEY,
+            applied_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    sqlx::query("INSERT INTO main.schema_migrations (version) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(version as i32)
+        .execute(&mut *connection)
+        .await?;
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
-use serde::Serialize;
+use proc_macros::ApiSchema;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
 #[serde(rename_all = "snake_case")]
 #[sqlx(type_name = "ticket_status", rename_all = "snake_case")]
 pub enum TicketStatus {
@@ -12,7 +13,7 @@ pub enum TicketStatus {
     Closed,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, sqlx::Type)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
 #[serde(rename_all = "snake_case")]
 #[sqlx(type_name = "payment_type", rename_all = "snake_case")]
 pub enum PaymentType {
@@ -20,10 +21,62 @@ pub enum PaymentType {
     Cash,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, sqlx::Type)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
 #[serde(rename_all = "snake_case")]
 #[sqlx(type_name = "item_type", rename_all = "snake_case")]
 pub enum ItemType {
     Product,
     Service,
 }
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, clap::ValueEnum, ApiSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "user_role", rename_all = "snake_case")]
+pub enum UserRole {
+    Admin,
+    FrontDesk,
+    Technician,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "appointment_status", rename_all = "snake_case")]
+pub enum AppointmentStatus {
+    Booked,
+    /// The customer dropped their device off and a ticket was created for it.
+    CheckedIn,
+    Cancelled,
+    NoShow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "quote_status", rename_all = "snake_case")]
+pub enum QuoteStatus {
+    Pending,
+    Approved,
+    Declined,
+    /// The quote was turned into a ticket and an invoice.
+    Converted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "cash_movement_type", rename_all = "snake_case")]
+pub enum CashMovementType {
+    PaidIn,
+    PaidOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "component_condition", rename_all = "snake_case")]
+pub enum ComponentCondition {
+    Working,
+    /// The component works, but is cracked, scratched or otherwise visibly damaged.
+    Damaged,
+    NotWorking,
+    NotChecked,
+}
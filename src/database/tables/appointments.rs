@@ -0,0 +1,312 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::shared_models::AppointmentStatus;
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "appointments", primary_key = "id")]
+pub struct AppointmentsTable {
+    records: Vec<AppointmentsTableRecord>,
+}
+
+/// A time booked for a customer to drop off a device for repair.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct AppointmentsTableRecord {
+    pub id: i32,
+    pub customer: i32,
+    pub device_model: Option<i32>,
+    pub starts_at: DateTime<Utc>,
+    /// When the appointment ends, which is determined by the durations of its service types.
+    pub ends_at: DateTime<Utc>,
+    #[defaultable]
+    #[api_schema(value_type = AppointmentStatus)]
+    pub status: Option<AppointmentStatus>,
+    pub notes: Option<String>,
+    /// The ticket created when the customer checked in, if they have.
+    pub ticket: Option<i32>,
+    #[defaultable]
+    #[api_schema(value_type = DateTime<Utc>)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "appointment_services",
+    primary_key = "(appointment, service_type)",
+    serial_primary_key = false
+)]
+pub struct AppointmentServicesJunctionTable {
+    records: Vec<AppointmentServicesJunctionTableRecord>,
+}
+
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize)]
+pub struct AppointmentServicesJunctionTableRecord {
+    pub appointment: i32,
+    pub service_type: i32,
+}
+
+/// An appointment along with the types of service requested for it.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct AppointmentDetails {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub appointment: AppointmentsTableRecord,
+    pub service_types: Vec<i32>,
+}
+
+/// An appointment with the names of everything it refers to, for calendar feeds.
+#[derive(sqlx::FromRow, Clone)]
+pub struct AppointmentCalendarEntry {
+    pub id: i32,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub status: AppointmentStatus,
+    pub customer: String,
+    pub device_model: Option<String>,
+    pub services: Vec<String>,
+    pub notes: Option<String>,
+}
+
+/// Why an appointment could not be booked.
+pub enum BookingError {
+    CustomerNotFound,
+    /// The time overlaps another appointment which is still expected to happen.
+    SlotTaken,
+}
+
+/// The statuses of appointments which still occupy their time slot.
+const ACTIVE_STATUSES: &str = "('booked', 'checked_in')";
+
+const SELECT_DETAILS: &str = "SELECT appointment.*, ARRAY( \
+         SELECT service_type FROM main.appointment_services \
+         WHERE appointment = appointment.id ORDER BY service_type \
+     ) AS service_types \
+     FROM main.appointments appointment";
+
+impl AppointmentsTableRecord {
+    /// Get the appointments still occupying their time slot that overlap a span of time.
+    pub async fn query_active_between(
+        database: &Database,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<Self> {
+        sqlx::query_as(&format!(
+            "SELECT * FROM main.appointments WHERE starts_at < $2 AND ends_at > $1 \
+             AND status IN {ACTIVE_STATUSES} ORDER BY starts_at"
+        ))
+        .bind(from)
+        .bind(to)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl AppointmentDetails {
+    /// Get an appointment by its ID, or [`None`] if there is no such appointment.
+    pub async fn query_by_id(database: &Database, id: i32) -> Option<Self> {
+        sqlx::query_as(&format!("{SELECT_DETAILS} WHERE appointment.id = $1"))
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Get every appointment overlapping a span of time, in order of when they start.
+    pub async fn query_between(
+        database: &Database,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<Self> {
+        sqlx::query_as(&format!(
+            "{SELECT_DETAILS} WHERE appointment.starts_at < $2 AND appointment.ends_at > $1 \
+             ORDER BY appointment.starts_at, appointment.id"
+        ))
+        .bind(from)
+        .bind(to)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Book an appointment for a customer, as long as the time does not overlap any other
+    /// appointment still occupying its slot.
+    pub async fn book(
+        database: &Database,
+        customer: i32,
+        device_model: Option<i32>,
+        service_types: &[i32],
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        notes: Option<&str>,
+    ) -> Result<Self, BookingError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+
+        // * Bookings are serialized so that two customers cannot take the same slot at once.
+        sqlx::query("LOCK TABLE main.appointments IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+
+        let customer_exists: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.customers WHERE id = $1)")
+                .bind(customer)
+                .fetch_one(&mut *transaction)
+                .await
+                .unwrap();
+        if !customer_exists {
+            return Err(BookingError::CustomerNotFound);
+        }
+
+        let slot_taken: bool = sqlx::query_scalar(&format!(
+            "SELECT EXISTS (SELECT 1 FROM main.appointments WHERE starts_at < $2 AND ends_at > $1 \
+             AND status IN {ACTIVE_STATUSES})"
+        ))
+        .bind(starts_at)
+        .bind(ends_at)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+        if slot_taken {
+            return Err(BookingError::SlotTaken);
+        }
+
+        let appointment: AppointmentsTableRecord = sqlx::query_as(
+            "INSERT INTO main.appointments (customer, device_model, starts_at, ends_at, notes) \
+             VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        )
+        .bind(customer)
+        .bind(device_model)
+        .bind(starts_at)
+        .bind(ends_at)
+        .bind(notes)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            "INSERT INTO main.appointment_services (appointment, service_type) \
+             SELECT $1, UNNEST($2::integer[])",
+        )
+        .bind(appointment.id)
+        .bind(service_types)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+
+        let mut service_types = service_types.to_vec();
+        service_types.sort();
+        Ok(Self {
+            appointment,
+            service_types,
+        })
+    }
+
+    /// Change the status of an appointment which is still booked, returning [`None`] if there is
+    /// no such appointment or it is no longer booked.
+    pub async fn set_status(
+        database: &Database,
+        id: i32,
+        status: AppointmentStatus,
+    ) -> Option<Self> {
+        let updated = sqlx::query(
+            "UPDATE main.appointments SET status = $2 WHERE id = $1 AND status = 'booked'",
+        )
+        .bind(id)
+        .bind(status)
+        .execute(&database.connection)
+        .await
+        .unwrap()
+        .rows_affected();
+
+        match updated {
+            0 => None,
+            _ => Self::query_by_id(database, id).await,
+        }
+    }
+
+    /// Check in a booked appointment by creating a ticket for the customer at a store, with the
+    /// requested services as its description and the appointment's notes as its first note.
+    ///
+    /// Returns [`None`] if there is no such appointment or it is no longer booked.
+    pub async fn check_in(database: &Database, id: i32, location: i32) -> Option<Self> {
+        let mut transaction = database.connection.begin().await.unwrap();
+
+        let ticket: Option<i32> = sqlx::query_scalar(
+            "INSERT INTO main.tickets (customer, description, notes, location) \
+             SELECT appointment.customer, \
+                 ( \
+                     SELECT string_agg(service_type.display_name, ', ' ORDER BY service_type.id) \
+                     FROM main.appointment_services appointment_service \
+                         JOIN main.service_types service_type \
+                             ON appointment_service.service_type = service_type.id \
+                     WHERE appointment_service.appointment = appointment.id \
+                 ), \
+                 CASE WHEN appointment.notes IS NULL THEN '{}' ELSE ARRAY[appointment.notes] END, \
+                 $2 \
+             FROM main.appointments appointment \
+             WHERE appointment.id = $1 AND appointment.status = 'booked' \
+             RETURNING id",
+        )
+        .bind(id)
+        .bind(location)
+        .fetch_optional(&mut *transaction)
+        .await
+        .unwrap();
+        let ticket = ticket?;
+
+        sqlx::query(
+            "UPDATE main.appointments SET status = 'checked_in', ticket = $2 WHERE id = $1",
+        )
+        .bind(id)
+        .bind(ticket)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Self::query_by_id(database, id).await
+    }
+}
+
+impl AppointmentCalendarEntry {
+    /// Get every appointment still occupying its slot which ends after a point in time, in order of
+    /// when they start.
+    pub async fn query_since(database: &Database, since: DateTime<Utc>) -> Vec<Self> {
+        sqlx::query_as(&format!(
+            "SELECT \
+                 appointment.id, appointment.starts_at, appointment.ends_at, \
+                 appointment.created_at, appointment.status, \
+                 customer.name AS customer, device_model.display_name AS device_model, \
+                 ARRAY( \
+                     SELECT service_type.display_name \
+                     FROM main.appointment_services appointment_service \
+                         JOIN main.service_types service_type \
+                             ON appointment_service.service_type = service_type.id \
+                     WHERE appointment_service.appointment = appointment.id \
+                     ORDER BY service_type.id \
+                 ) AS services, \
+                 appointment.notes \
+             FROM main.appointments appointment \
+                 JOIN main.customers customer ON appointment.customer = customer.id \
+                 LEFT JOIN main.device_models device_model \
+                     ON appointment.device_model = device_model.id \
+             WHERE appointment.ends_at > $1 AND appointment.status IN {ACTIVE_STATUSES} \
+             ORDER BY appointment.starts_at, appointment.id"
+        ))
+        .bind(since)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
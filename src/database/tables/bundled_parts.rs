@@ -1,22 +1,26 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
 
+use proc_macros::{BackupTable, BulkInsert, GenerateTable, Relation, SingleInsert, Table};
+
+use super::generators::GenerationRng;
 use super::parts::PartsTable;
 use super::ticket_devices::TicketDevicesJunctionTable;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(
     relation_name = "bundled_parts",
-    primary_key = "(ticket, device, part)"
+    primary_key = "(ticket, device, part)",
+    serial_primary_key = false
 )]
 pub struct BundledPartsJunctionTable {
     records: Vec<BundledPartsJunctionTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize)]
 pub struct BundledPartsJunctionTableRecord {
     pub ticket: i32,
     pub device: i32,
@@ -31,15 +35,16 @@ impl GenerateRecord for BundledPartsJunctionTableRecord {
         _existing_records: &[Self],
         existing_pairs: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
         let mut ticket = 0;
         let mut device = 0;
         let mut part = 0;
         let mut first_roll = true;
         while first_roll || existing_pairs.get(&(ticket, device, part)).is_some() {
-            let ticket_device = dependencies.0.pick_random();
+            let ticket_device = dependencies.0.pick_random(rng);
             (ticket, device) = (ticket_device.ticket, ticket_device.device);
-            part = dependencies.1.pick_random().id();
+            part = dependencies.1.pick_random(rng).id();
             first_roll = false;
         }
 
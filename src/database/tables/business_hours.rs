@@ -0,0 +1,57 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{ApiSchema, BackupTable, BulkInsert, Relation, SingleInsert, Table};
+
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "business_hours",
+    primary_key = "weekday",
+    serial_primary_key = false
+)]
+pub struct BusinessHoursTable {
+    records: Vec<BusinessHoursTableRecord>,
+}
+
+/// The opening hours of the shop on a day of the week, in the shop's local time.
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize, ApiSchema)]
+pub struct BusinessHoursTableRecord {
+    /// The ISO weekday, from 1 for Monday to 7 for Sunday.
+    #[api_schema(schema = serde_json::json!({ "type": "integer", "minimum": 1, "maximum": 7 }))]
+    pub weekday: i32,
+    pub opens_at: NaiveTime,
+    pub closes_at: NaiveTime,
+}
+
+impl BusinessHoursTableRecord {
+    /// Get the opening hours of every day the shop is open, from Monday to Sunday.
+    pub async fn query_week(database: &Database) -> Vec<Self> {
+        sqlx::query_as("SELECT * FROM main.business_hours ORDER BY weekday")
+            .fetch_all(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Replace the opening hours of the whole week. The shop is closed on days which are left out.
+    pub async fn replace_week(database: &Database, week: &[Self]) {
+        let mut transaction = database.connection.begin().await.unwrap();
+        sqlx::query("DELETE FROM main.business_hours")
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+        for day in week {
+            sqlx::query(
+                "INSERT INTO main.business_hours (weekday, opens_at, closes_at) VALUES ($1, $2, $3)",
+            )
+            .bind(day.weekday)
+            .bind(day.opens_at)
+            .bind(day.closes_at)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+        }
+        transaction.commit().await.unwrap();
+    }
+}
@@ -1,19 +1,27 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, Relation, SingleInsert, Table};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{BackupTable, BulkInsert, GenerateTable, Relation, SingleInsert, Table};
 
 use super::device_models::DeviceModelsTable;
+use super::generators::{GenerationRng, GroupedIds};
 use super::parts::PartsTable;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
-#[relation(relation_name = "compatible_parts", primary_key = "(device, part)")]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
+#[relation(
+    relation_name = "compatible_parts",
+    primary_key = "(device, part)",
+    serial_primary_key = false
+)]
 pub struct CompatiblePartsJunctionTable {
     records: Vec<CompatiblePartsJunctionTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize)]
 pub struct CompatiblePartsJunctionTableRecord {
     pub device: i32,
     pub part: i32,
@@ -21,18 +29,39 @@ pub struct CompatiblePartsJunctionTableRecord {
 
 impl GenerateRecord for CompatiblePartsJunctionTableRecord {
     type Identifier = (i32, i32);
-    type Dependencies<'a> = (&'a DeviceModelsTable, &'a PartsTable);
+    type Dependencies<'a> = (
+        &'a DeviceModelsTable,
+        &'a PartsTable,
+        &'a GroupedIds<String>,
+    );
     fn generate(
         _existing_records: &[Self],
         existing_pairs: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
+        let (device_models, parts, models_by_name) = dependencies;
+
         let mut device = 0;
         let mut part = 0;
         let mut first_roll = true;
         while first_roll || existing_pairs.get(&(device, part)).is_some() {
-            device = dependencies.0.pick_random().id();
-            part = dependencies.1.pick_random().id();
+            let random_part = parts.pick_random(rng);
+            part = random_part.id();
+
+            // * Parts are named after the model they were made for, so prefer pairing a part with
+            // * that model (or another model with the same name) before falling back to any model.
+            let named_models: Vec<i32> = random_part
+                .display_name
+                .match_indices(' ')
+                .flat_map(|(index, _)| models_by_name.get(&random_part.display_name[..index]))
+                .copied()
+                .filter(|model| existing_pairs.get(&(*model, part)).is_none())
+                .collect();
+            device = match named_models.is_empty() {
+                true => device_models.pick_random(rng).id(),
+                false => named_models[rng.gen_range(0..named_models.len())],
+            };
             first_roll = false;
         }
 
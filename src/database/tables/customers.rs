@@ -1,17 +1,21 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::generators::*;
 use crate::database::GenerateRecord;
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "customers", primary_key = "id")]
 pub struct CustomersTable {
     records: Vec<CustomersTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, Clone, IdentifiableRecord)]
+#[derive(SingleInsert, sqlx::FromRow, Clone, IdentifiableRecord, Serialize, Deserialize)]
 pub struct CustomersTableRecord {
     pub id: i32,
     pub name: String,
@@ -27,13 +31,14 @@ impl GenerateRecord for CustomersTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         _dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            name: generate_name(),
-            email_address: generate_option(generate_email_address(), 0.9),
-            phone_number: generate_option(generate_phone_number(), 0.9),
-            street_address: generate_option(generate_street_address(), 0.9),
+            id: generate_sequential_i32(existing_ids),
+            name: generate_name(rng),
+            email_address: generate_option(generate_email_address(rng), 0.9, rng),
+            phone_number: generate_option(generate_phone_number(rng), 0.9, rng),
+            street_address: generate_option(generate_street_address(rng), 0.9, rng),
         }
     }
 }
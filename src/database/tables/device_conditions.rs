@@ -0,0 +1,213 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::shared_models::ComponentCondition;
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "device_conditions",
+    primary_key = "(ticket, device)",
+    serial_primary_key = false
+)]
+pub struct DeviceConditionsJunctionTable {
+    records: Vec<DeviceConditionsJunctionTableRecord>,
+}
+
+/// The checklist filled in for a device when it was checked in on a ticket.
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize, ApiSchema)]
+pub struct DeviceConditionsJunctionTableRecord {
+    pub ticket: i32,
+    pub device: i32,
+    pub screen: ComponentCondition,
+    pub buttons: ComponentCondition,
+    pub cameras: ComponentCondition,
+    /// Whether the device's liquid contact indicators show water damage, or [`None`] if they were
+    /// not checked.
+    pub water_indicators_tripped: Option<bool>,
+    pub passcode_provided: bool,
+    pub accessories: Vec<String>,
+    pub notes: Option<String>,
+    pub recorded_by: Option<i32>,
+    #[defaultable]
+    #[api_schema(value_type = DateTime<Utc>)]
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "device_photos", primary_key = "id")]
+pub struct DevicePhotosTable {
+    records: Vec<DevicePhotosTableRecord>,
+}
+
+/// A photo of a device taken when it was checked in on a ticket.
+///
+/// Only the key of the image is kept in the database; the image and its thumbnail are kept in the
+/// shop's photo store.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct DevicePhotosTableRecord {
+    pub id: i32,
+    pub ticket: i32,
+    pub device: i32,
+    pub storage_key: String,
+    pub content_type: String,
+    pub caption: Option<String>,
+    pub uploaded_by: Option<i32>,
+    #[defaultable]
+    #[api_schema(value_type = DateTime<Utc>)]
+    pub uploaded_at: Option<DateTime<Utc>>,
+}
+
+/// A device on a ticket along with what it is being repaired for, as shown in its condition report.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct ConditionReportDevice {
+    pub device: i32,
+    pub model: String,
+    pub service: Option<String>,
+    pub diagnostic: Option<String>,
+}
+
+impl DeviceConditionsJunctionTableRecord {
+    /// Get the checklists of every device on a ticket which has one.
+    pub async fn query_for_ticket(database: &Database, ticket: i32) -> Vec<Self> {
+        sqlx::query_as("SELECT * FROM main.device_conditions WHERE ticket = $1 ORDER BY device")
+            .bind(ticket)
+            .fetch_all(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Record the checklist of a device on a ticket, replacing any that was recorded before.
+    ///
+    /// Returns [`None`] if the ticket does not have the device.
+    pub async fn record(self, database: &Database) -> Option<Self> {
+        sqlx::query_as(
+            "INSERT INTO main.device_conditions ( \
+                 ticket, device, screen, buttons, cameras, water_indicators_tripped, \
+                 passcode_provided, accessories, notes, recorded_by \
+             ) \
+             SELECT ticket, device, $3, $4, $5, $6, $7, $8, $9, $10 \
+             FROM main.ticket_devices WHERE ticket = $1 AND device = $2 \
+             ON CONFLICT (ticket, device) DO UPDATE SET \
+                 screen = EXCLUDED.screen, buttons = EXCLUDED.buttons, \
+                 cameras = EXCLUDED.cameras, \
+                 water_indicators_tripped = EXCLUDED.water_indicators_tripped, \
+                 passcode_provided = EXCLUDED.passcode_provided, \
+                 accessories = EXCLUDED.accessories, notes = EXCLUDED.notes, \
+                 recorded_by = EXCLUDED.recorded_by, recorded_at = CURRENT_TIMESTAMP \
+             RETURNING *",
+        )
+        .bind(self.ticket)
+        .bind(self.device)
+        .bind(self.screen)
+        .bind(self.buttons)
+        .bind(self.cameras)
+        .bind(self.water_indicators_tripped)
+        .bind(self.passcode_provided)
+        .bind(&self.accessories)
+        .bind(&self.notes)
+        .bind(self.recorded_by)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl DevicePhotosTableRecord {
+    pub async fn query_by_id(database: &Database, id: i32) -> Option<Self> {
+        sqlx::query_as("SELECT * FROM main.device_photos WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Get the photos of every device on a ticket, in the order they were taken.
+    pub async fn query_for_ticket(database: &Database, ticket: i32) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.device_photos WHERE ticket = $1 ORDER BY device, uploaded_at, id",
+        )
+        .bind(ticket)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Add a photo of a device on a ticket whose image has been stored under `storage_key`.
+    ///
+    /// Returns [`None`] if the ticket does not have the device.
+    pub async fn create(
+        database: &Database,
+        ticket: i32,
+        device: i32,
+        storage_key: &str,
+        content_type: &str,
+        caption: Option<&str>,
+        user: i32,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "INSERT INTO main.device_photos \
+                 (ticket, device, storage_key, content_type, caption, uploaded_by) \
+             SELECT ticket, device, $3, $4, $5, $6 \
+             FROM main.ticket_devices WHERE ticket = $1 AND device = $2 \
+             RETURNING *",
+        )
+        .bind(ticket)
+        .bind(device)
+        .bind(storage_key)
+        .bind(content_type)
+        .bind(caption)
+        .bind(user)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Delete a photo, returning it so its image can be removed from the photo store.
+    pub async fn delete(database: &Database, id: i32) -> Option<Self> {
+        sqlx::query_as("DELETE FROM main.device_photos WHERE id = $1 RETURNING *")
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+}
+
+impl ConditionReportDevice {
+    /// Get every device on a ticket, in the order of their IDs.
+    pub async fn query_for_ticket(database: &Database, ticket: i32) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT ticket_device.device, device_model.display_name AS model, \
+                 service_type.display_name AS service, ticket_device.diagnostic \
+             FROM main.ticket_devices ticket_device \
+                 JOIN main.devices device ON ticket_device.device = device.id \
+                 JOIN main.device_models device_model ON device.model = device_model.id \
+                 LEFT JOIN main.services service ON ticket_device.service = service.id \
+                 LEFT JOIN main.service_types service_type ON service.type = service_type.id \
+             WHERE ticket_device.ticket = $1 \
+             ORDER BY ticket_device.device",
+        )
+        .bind(ticket)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Check whether a ticket has a device.
+    pub async fn exists(database: &Database, ticket: i32, device: i32) -> bool {
+        sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM main.ticket_devices WHERE ticket = $1 AND device = $2)",
+        )
+        .bind(ticket)
+        .bind(device)
+        .fetch_one(&database.connection)
+        .await
+        .unwrap()
+    }
+}
@@ -1,6 +1,11 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::device_categories::DeviceCategoriesTable;
 use super::device_manufacturers::DeviceManufacturersTable;
@@ -8,13 +13,13 @@ use super::generators::*;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "device_models", primary_key = "id")]
 pub struct DeviceModelsTable {
     records: Vec<DeviceModelsTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct DeviceModelsTableRecord {
     pub id: i32,
     pub display_name: String,
@@ -32,15 +37,58 @@ impl GenerateRecord for DeviceModelsTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
+        let (manufacturers, categories) = dependencies;
+
+        // * Most models are made by well-known brands, like in a real shop. The well-known brands are
+        // * always the first device manufacturers generated.
+        let known_manufacturers =
+            &manufacturers.records()[..DEVICE_BRANDS.len().min(manufacturers.records().len())];
+        let manufacturer = match known_manufacturers.is_empty() || rng.gen_bool(0.2) {
+            true => manufacturers.pick_random(rng),
+            false => known_manufacturers[rng.gen_range(0..known_manufacturers.len())].clone(),
+        };
+
+        let (category, product_line) = match find_device_brand(&manufacturer.display_name) {
+            Some(brand) => {
+                let (category_name, product_line) =
+                    brand.product_lines[rng.gen_range(0..brand.product_lines.len())];
+                let category = categories
+                    .records()
+                    .iter()
+                    .find(|c| c.display_name == category_name)
+                    .cloned()
+                    .unwrap_or_else(|| categories.pick_random(rng));
+                (category, product_line.to_owned())
+            }
+            None => {
+                let category = categories.pick_random(rng);
+                let company = manufacturer
+                    .display_name
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_end_matches(',');
+                let product_line = format!("{} {}", company, category.display_name);
+                (category, product_line)
+            }
+        };
+
+        let primary_model_identifiers = (0..rng.gen_range(1..=3))
+            .map(|_| generate_model_identifier(&manufacturer.display_name, rng))
+            .collect();
+        let secondary_model_identifiers = (0..rng.gen_range(0..=2))
+            .map(|_| generate_model_identifier(&manufacturer.display_name, rng))
+            .collect();
+
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            display_name: generate_device_name(),
-            // TODO: Add model identifiers generator
-            primary_model_identifiers: Vec::new(),
-            secondary_model_identifiers: Vec::new(),
-            manufacturer: dependencies.0.pick_random().id(),
-            category: dependencies.1.pick_random().id(),
+            id: generate_sequential_i32(existing_ids),
+            display_name: generate_device_model_name(&product_line, rng),
+            primary_model_identifiers,
+            secondary_model_identifiers,
+            manufacturer: manufacturer.id(),
+            category: category.id(),
         }
     }
 }
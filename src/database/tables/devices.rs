@@ -1,6 +1,10 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::customers::CustomersTable;
 use super::device_models::DeviceModelsTable;
@@ -8,17 +12,20 @@ use super::generators::*;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "devices", primary_key = "id")]
 pub struct DevicesTable {
     records: Vec<DevicesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct DevicesTableRecord {
     pub id: i32,
     pub model: i32,
     pub owner: Option<i32>,
+    /// The store holding the device, which is [`None`] while its owner has it or it is being
+    /// transferred between stores.
+    pub location: Option<i32>,
 }
 
 impl GenerateRecord for DevicesTableRecord {
@@ -28,11 +35,14 @@ impl GenerateRecord for DevicesTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            model: dependencies.0.pick_random().id(),
-            owner: generate_option(dependencies.1.pick_random().id(), 0.9),
+            id: generate_sequential_i32(existing_ids),
+            model: dependencies.0.pick_random(rng).id(),
+            owner: generate_option(dependencies.1.pick_random(rng).id(), 0.9, rng),
+            // * Devices are placed in stores as they are checked in on tickets.
+            location: None,
         }
     }
 }
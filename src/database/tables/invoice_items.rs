@@ -1,43 +1,166 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert,
+    Table,
+};
+
+use super::generators::GenerationRng;
 use super::invoices::InvoicesTable;
-use super::items::ItemsTable;
+use super::items::{ItemPrices, ItemsTable};
 use super::IdentifiableRecord;
-use crate::database::{GenerateRecord, Relation};
+use crate::database::{Database, GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
-#[relation(relation_name = "invoice_items", primary_key = "(invoice, item)")]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
+#[relation(
+    relation_name = "invoice_items",
+    primary_key = "(invoice, item)",
+    serial_primary_key = false
+)]
 pub struct InvoiceItemsTable {
     records: Vec<InvoiceItemsTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
 pub struct InvoiceItemsTableRecord {
     pub invoice: i32,
     pub item: i32,
+    /// Whether the item is free because it is covered by a warranty claim.
+    pub covered: bool,
+    /// The price of the item when it was added to the invoice, or the price it was quoted at.
+    pub unit_price: Decimal,
+    /// A price set by hand, which is charged instead of the unit price.
+    pub override_price: Option<Decimal>,
+    pub override_reason: Option<String>,
+    /// The user who set the price by hand.
+    pub overridden_by: Option<i32>,
+}
+
+/// A price set by hand for an invoice item, replacing the price it was added at.
+pub struct PriceOverride<'a> {
+    pub price: Decimal,
+    pub reason: &'a str,
+    pub user: i32,
+}
+
+/// Why an item could not be added to an invoice.
+pub enum InvoiceItemError {
+    InvoiceNotFound,
+    ItemNotFound,
+    AlreadyAdded,
+}
+
+impl InvoiceItemsTableRecord {
+    /// Add an item to an invoice, capturing its current price so that later price changes do not
+    /// affect the invoice, or charging a price set by hand instead.
+    pub async fn add(
+        database: &Database,
+        invoice: i32,
+        item: i32,
+        price_override: Option<PriceOverride<'_>>,
+    ) -> Result<Self, InvoiceItemError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+
+        let invoice_exists: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.invoices WHERE id = $1)")
+                .bind(invoice)
+                .fetch_one(&mut *transaction)
+                .await
+                .unwrap();
+        if !invoice_exists {
+            return Err(InvoiceItemError::InvoiceNotFound);
+        }
+
+        let item_exists: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.items WHERE id = $1)")
+                .bind(item)
+                .fetch_one(&mut *transaction)
+                .await
+                .unwrap();
+        if !item_exists {
+            return Err(InvoiceItemError::ItemNotFound);
+        }
+
+        // * The unit price is left out so that it is captured by the database as of now.
+        let record = sqlx::query_as(
+            "INSERT INTO main.invoice_items \
+                 (invoice, item, override_price, override_reason, overridden_by) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (invoice, item) DO NOTHING \
+             RETURNING *",
+        )
+        .bind(invoice)
+        .bind(item)
+        .bind(price_override.as_ref().map(|o| o.price))
+        .bind(price_override.as_ref().map(|o| o.reason))
+        .bind(price_override.as_ref().map(|o| o.user))
+        .fetch_optional(&mut *transaction)
+        .await
+        .unwrap()
+        .ok_or(InvoiceItemError::AlreadyAdded)?;
+
+        transaction.commit().await.unwrap();
+        Ok(record)
+    }
+
+    /// Set the price of an invoice item by hand, or clear it to charge the price the item was
+    /// added at again. Returns [`None`] if the item is not on the invoice.
+    pub async fn set_override(
+        database: &Database,
+        invoice: i32,
+        item: i32,
+        price_override: Option<PriceOverride<'_>>,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "UPDATE main.invoice_items \
+             SET override_price = $3, override_reason = $4, overridden_by = $5 \
+             WHERE invoice = $1 AND item = $2 \
+             RETURNING *",
+        )
+        .bind(invoice)
+        .bind(item)
+        .bind(price_override.as_ref().map(|o| o.price))
+        .bind(price_override.as_ref().map(|o| o.reason))
+        .bind(price_override.as_ref().map(|o| o.user))
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
 }
 
 impl GenerateRecord for InvoiceItemsTableRecord {
     type Identifier = (i32, i32);
-    type Dependencies<'a> = (&'a InvoicesTable, &'a ItemsTable);
+    type Dependencies<'a> = (&'a InvoicesTable, &'a ItemsTable, &'a ItemPrices);
     fn generate(
         _existing_records: &[Self],
         existing_pairs: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let mut invoice = 0;
-        let mut item = 0;
-        let mut first_roll = true;
-        while first_roll || existing_pairs.get(&(invoice, item)).is_some() {
-            invoice = dependencies.0.pick_random().id();
-            item = dependencies.1.pick_random().id();
-            first_roll = false;
+        let mut invoice = dependencies.0.pick_random(rng);
+        let mut item = dependencies.1.pick_random(rng).id();
+        while existing_pairs.get(&(invoice.id, item)).is_some() {
+            invoice = dependencies.0.pick_random(rng);
+            item = dependencies.1.pick_random(rng).id();
         }
 
-        existing_pairs.insert((invoice, item));
+        existing_pairs.insert((invoice.id, item));
 
-        Self { invoice, item }
+        Self {
+            invoice: invoice.id,
+            item,
+            covered: false,
+            unit_price: dependencies
+                .2
+                .get_price_at_time(item, invoice.created_at.unwrap()),
+            override_price: None,
+            override_reason: None,
+            overridden_by: None,
+        }
     }
 }
@@ -1,28 +1,27 @@
 use std::collections::HashSet;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
+use rand::Rng;
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::generators::*;
-use super::invoice_items::InvoiceItemsTable;
-use super::invoices::InvoicesTable;
-use super::items::ItemsTable;
-use super::product_prices::ProductPricesTable;
-use super::service_prices::ServicePricesTable;
-use super::IdentifiableRecord;
+use super::invoices::InvoiceLedger;
 use crate::database::shared_models::PaymentType;
-use crate::database::{GenerateRecord, Relation};
+use crate::database::GenerateRecord;
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "invoice_payments", primary_key = "id")]
 pub struct InvoicePaymentsTable {
     records: Vec<InvoicePaymentsTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct InvoicePaymentsTableRecord {
     pub id: i32,
     pub invoice: i32,
@@ -30,63 +29,45 @@ pub struct InvoicePaymentsTableRecord {
     #[sqlx(rename = "type")]
     pub r#type: PaymentType,
     #[defaultable]
-    pub timestamp: Option<NaiveDateTime>,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 impl GenerateRecord for InvoicePaymentsTableRecord {
     type Identifier = i32;
-    type Dependencies<'a> = (
-        &'a InvoicesTable,
-        &'a InvoiceItemsTable,
-        &'a ItemsTable,
-        &'a ProductPricesTable,
-        &'a ServicePricesTable,
-    );
+    type Dependencies<'a> = &'a InvoiceLedger;
+
+    /// Payments can only be generated while an invoice is left to pay, so a profile asking for more
+    /// payments than the invoices can take gets fewer.
+    fn can_generate(dependencies: Self::Dependencies<'_>) -> bool {
+        dependencies.has_unpaid()
+    }
 
     fn generate(
-        existing_records: &[Self],
+        _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let (invoice, amount) = loop {
-            let random_invoice = dependencies.0.pick_random();
-            let invoice_total: Decimal = dependencies
-                .1
-                .records()
-                .iter()
-                .filter(|i| i.invoice == random_invoice.id())
-                .map(|i| {
-                    dependencies.2.get_item_price_by_id(
-                        i.item,
-                        dependencies.3,
-                        dependencies.4,
-                        random_invoice.created_at.unwrap(),
-                    )
-                })
-                .sum();
-            let current_payment_total: Decimal = existing_records
-                .iter()
-                .filter(|r| r.invoice == random_invoice.id())
-                .map(|r| r.amount)
-                .sum();
+        let (invoice, balance) = dependencies
+            .pick_unpaid(rng)
+            .expect("payments are only generated while there is an unpaid invoice");
 
-            let maximum_payment_amount = invoice_total - current_payment_total;
-            if maximum_payment_amount < Decimal::new(1, 2) {
-                continue;
+        // * Most customers pay off the whole balance at once rather than in installments.
+        let amount = match rng.gen_bool(0.7) {
+            true => balance.remaining(),
+            false => {
+                generate_dollar_value(Some(0.01), Some(balance.remaining().to_f32().unwrap()), rng)
             }
-
-            break (
-                random_invoice,
-                generate_dollar_value(Some(0.01), Some(maximum_payment_amount.to_f32().unwrap())),
-            );
         };
+        let timestamp = generate_date_within(balance.created_at, 30, rng);
+        dependencies.record_payment(invoice, amount, timestamp);
 
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            invoice: invoice.id(),
+            id: generate_sequential_i32(existing_ids),
+            invoice,
             amount,
-            r#type: generate_payment_type(),
-            timestamp: Some(generate_date(invoice.created_at)),
+            r#type: generate_payment_type(rng),
+            timestamp: Some(timestamp),
         }
     }
 }
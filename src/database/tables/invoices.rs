@@ -1,42 +1,382 @@
-use std::collections::HashSet;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert,
+    Table,
+};
 
 use super::generators::*;
-use crate::database::GenerateRecord;
+use super::invoice_items::InvoiceItemsTable;
+use super::locations::LocationsTable;
+use super::IdentifiableRecord;
+use crate::database::{Database, GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "invoices", primary_key = "id")]
 pub struct InvoicesTable {
     records: Vec<InvoicesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct InvoicesTableRecord {
     pub id: i32,
     #[defaultable]
-    pub created_at: Option<NaiveDateTime>,
+    pub created_at: Option<DateTime<Utc>>,
     #[defaultable]
-    pub updated_at: Option<NaiveDateTime>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// The store the invoice was opened at.
+    #[defaultable]
+    pub location: Option<i32>,
+}
+
+/// The running balance of an invoice while synthetic payments are being generated.
+#[derive(Clone, Copy)]
+pub struct InvoiceBalance {
+    pub created_at: DateTime<Utc>,
+    pub total: Decimal,
+    pub paid: Decimal,
+    pub last_payment: Option<DateTime<Utc>>,
+}
+
+impl InvoiceBalance {
+    pub fn remaining(&self) -> Decimal {
+        self.total - self.paid
+    }
+
+    /// Whether at least one cent remains to be paid.
+    pub fn is_unpaid(&self) -> bool {
+        self.remaining() >= Decimal::new(1, 2)
+    }
+}
+
+/// The balances of every invoice, indexed by invoice ID.
+///
+/// This is built once from the invoice items so that generating payments and tickets doesn't
+/// need to rescan every invoice item for each record. Payments are recorded as they are
+/// generated, which is why the ledger uses interior mutability despite being passed as a shared
+/// generation dependency.
+pub struct InvoiceLedger {
+    balances: RefCell<HashMap<i32, InvoiceBalance>>,
+    /// Invoices which may still have a remaining balance. Paid invoices are removed lazily when
+    /// they are picked.
+    unpaid: RefCell<Vec<i32>>,
+    /// The number of invoices which actually have a remaining balance.
+    unpaid_count: Cell<usize>,
+}
+
+impl InvoiceLedger {
+    pub fn new(invoices: &InvoicesTable, invoice_items: &InvoiceItemsTable) -> Self {
+        let mut balances: HashMap<i32, InvoiceBalance> = invoices
+            .records()
+            .iter()
+            .map(|invoice| {
+                let balance = InvoiceBalance {
+                    created_at: invoice.created_at.unwrap(),
+                    total: Decimal::new(0, 2),
+                    paid: Decimal::new(0, 2),
+                    last_payment: None,
+                };
+                (invoice.id, balance)
+            })
+            .collect();
+        for invoice_item in invoice_items.records() {
+            let balance = balances.get_mut(&invoice_item.invoice).unwrap();
+            balance.total += invoice_item.unit_price;
+        }
+
+        let unpaid_count = balances.values().filter(|b| b.is_unpaid()).count();
+        Self {
+            balances: RefCell::new(balances),
+            unpaid: RefCell::new(invoices.records().iter().map(|i| i.id).collect()),
+            unpaid_count: Cell::new(unpaid_count),
+        }
+    }
+
+    pub fn get_balance(&self, invoice: i32) -> InvoiceBalance {
+        self.balances.borrow()[&invoice]
+    }
+
+    /// Pick a random invoice with at least one cent remaining to be paid, or `None` if every
+    /// invoice has been paid in full.
+    pub fn pick_unpaid(&self, rng: &mut GenerationRng) -> Option<(i32, InvoiceBalance)> {
+        let mut unpaid = self.unpaid.borrow_mut();
+        while !unpaid.is_empty() {
+            let index = rng.gen_range(0..unpaid.len());
+            let balance = self.get_balance(unpaid[index]);
+            if balance.is_unpaid() {
+                return Some((unpaid[index], balance));
+            }
+
+            unpaid.swap_remove(index);
+        }
+
+        None
+    }
+
+    /// Whether any invoice has at least one cent remaining to be paid.
+    pub fn has_unpaid(&self) -> bool {
+        self.unpaid_count.get() > 0
+    }
+
+    pub fn record_payment(&self, invoice: i32, amount: Decimal, timestamp: DateTime<Utc>) {
+        let mut balances = self.balances.borrow_mut();
+        let balance = balances.get_mut(&invoice).unwrap();
+        let was_unpaid = balance.is_unpaid();
+        balance.paid += amount;
+        balance.last_payment = balance.last_payment.max(Some(timestamp));
+        if was_unpaid && !balance.is_unpaid() {
+            self.unpaid_count.set(self.unpaid_count.get() - 1);
+        }
+    }
 }
 
 impl GenerateRecord for InvoicesTableRecord {
     type Identifier = i32;
-    type Dependencies<'a> = ();
+    type Dependencies<'a> = &'a LocationsTable;
     fn generate(
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
-        _dependencies: Self::Dependencies<'_>,
+        dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let created_at = generate_date(None);
-        let updated_at = generate_date(Some(created_at));
+        let created_at = generate_date(None, rng);
+        let updated_at = generate_date(Some(created_at), rng);
 
         Self {
-            id: generate_unique_i32(0, existing_ids),
+            id: generate_sequential_i32(existing_ids),
             created_at: Some(created_at),
             updated_at: Some(updated_at),
+            location: Some(dependencies.pick_random(rng).id()),
+        }
+    }
+}
+
+/// What the rows of the profit report are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfitGrouping {
+    Period,
+    ServiceType,
+    DeviceManufacturer,
+    Technician,
+    PaymentType,
+}
+
+/// The length of the periods of the profit report when it is grouped by period.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ApiSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+/// The revenue, cost and labor of the invoices created in a span of time, for one group.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct ProfitReportRow {
+    /// The ID of the service type, device manufacturer or technician of the group.
+    pub group_id: Option<i32>,
+    /// The first day of the period, or the name of the group. Products have no service type or
+    /// device manufacturer, and the unpaid part of an invoice has no payment type.
+    pub group_name: Option<String>,
+    pub invoices: i32,
+    /// What was charged, leaving out items covered by warranties.
+    pub revenue: Decimal,
+    /// The cost of the products sold and of the parts used in the repairs.
+    pub cost_of_goods: Decimal,
+    pub gross_margin: Decimal,
+    /// The gross margin as a percentage of the revenue, or [`None`] if there was no revenue.
+    pub margin_percent: Option<Decimal>,
+    pub labor_minutes: i32,
+}
+
+/// Every invoice item of the invoices created between `$1` and `$2`, with what it was charged at,
+/// what it cost the shop and the labor that went into it.
+///
+/// A service item costs the parts bundled with, and the labor logged on, the devices which were
+/// repaired with it on the invoice's tickets. Parts are costed at their current cost, since their
+/// costs have no history. Items covered by warranties bring in nothing but still cost the shop.
+///
+/// When grouping by payment type, each invoice is split between the types it was paid with in
+/// proportion to the amounts paid, with any unpaid part of it having no type.
+const PROFIT_LINES: &str = "WITH work AS ( \
+         SELECT \
+             ticket.invoice, ticket_device.service, \
+             SUM(( \
+                 SELECT SUM(part.cost) \
+                 FROM main.bundled_parts bundled_part \
+                     JOIN main.parts part ON bundled_part.part = part.id \
+                 WHERE bundled_part.ticket = ticket_device.ticket \
+                     AND bundled_part.device = ticket_device.device \
+             )) AS parts_cost, \
+             SUM(( \
+                 SELECT SUM(EXTRACT(EPOCH FROM entry.ended_at - entry.started_at)) / 60 \
+                 FROM main.labor_entries entry \
+                 WHERE entry.ticket = ticket_device.ticket \
+                     AND entry.device = ticket_device.device \
+                     AND entry.ended_at IS NOT NULL \
+             )) AS labor_minutes, \
+             (ARRAY_AGG( \
+                 COALESCE(ticket_device.assignee, ticket.assignee) ORDER BY ticket.id \
+             ))[1] AS technician \
+         FROM main.tickets ticket \
+             JOIN main.ticket_devices ticket_device ON ticket.id = ticket_device.ticket \
+         WHERE ticket.invoice IS NOT NULL \
+         GROUP BY ticket.invoice, ticket_device.service \
+     ), ticket_assignee AS ( \
+         SELECT DISTINCT ON (invoice) invoice, assignee \
+         FROM main.tickets \
+         WHERE invoice IS NOT NULL AND assignee IS NOT NULL \
+         ORDER BY invoice, id \
+     ), line AS ( \
+         SELECT \
+             invoice.id AS invoice, invoice.created_at, \
+             CASE \
+                 WHEN invoice_item.covered THEN 0 \
+                 ELSE COALESCE(invoice_item.override_price, invoice_item.unit_price) \
+             END AS revenue, \
+             CASE item.type \
+                 WHEN 'product' THEN ( \
+                     SELECT cost \
+                     FROM main.get_product_price_at_time(item.product_or_service, invoice.created_at) \
+                 ) \
+                 ELSE COALESCE(work.parts_cost, 0) \
+             END AS cost, \
+             COALESCE(work.labor_minutes, 0) AS labor_minutes, \
+             service.type AS service_type, \
+             model.manufacturer AS device_manufacturer, \
+             COALESCE(work.technician, ticket_assignee.assignee) AS technician \
+         FROM main.invoice_items invoice_item \
+             JOIN main.invoices invoice ON invoice_item.invoice = invoice.id \
+             JOIN main.items item ON invoice_item.item = item.id \
+             LEFT JOIN main.services service \
+                 ON item.type = 'service' AND item.product_or_service = service.id \
+             LEFT JOIN main.device_models model ON service.device = model.id \
+             LEFT JOIN work ON invoice.id = work.invoice AND service.id = work.service \
+             LEFT JOIN ticket_assignee ON invoice.id = ticket_assignee.invoice \
+         WHERE invoice.created_at >= $1 AND invoice.created_at < $2 \
+     ), invoice_total AS ( \
+         SELECT \
+             line.invoice, SUM(line.revenue) AS revenue, \
+             COALESCE(( \
+                 SELECT SUM(amount) FROM main.invoice_payments WHERE invoice = line.invoice \
+             ), 0) AS paid \
+         FROM line \
+         GROUP BY line.invoice \
+     ), share AS ( \
+         SELECT \
+             payment.invoice, payment.type AS payment_type, \
+             SUM(payment.amount) / GREATEST(invoice_total.revenue, invoice_total.paid) AS fraction \
+         FROM main.invoice_payments payment \
+             JOIN invoice_total ON payment.invoice = invoice_total.invoice \
+         WHERE invoice_total.paid > 0 \
+         GROUP BY payment.invoice, payment.type, invoice_total.revenue, invoice_total.paid \
+         UNION ALL \
+         SELECT \
+             invoice, NULL, \
+             COALESCE((revenue - paid) / NULLIF(revenue, 0), 1) AS fraction \
+         FROM invoice_total \
+         WHERE revenue > paid OR paid <= 0 \
+     )";
+
+impl ProfitGrouping {
+    /// The ID and the name of the group of each line.
+    fn columns(&self) -> &'static str {
+        match self {
+            Self::Period => {
+                "NULL::integer AS group_id, \
+                 to_char(date_trunc($3, line.created_at AT TIME ZONE $4), 'YYYY-MM-DD') \
+                     AS group_name"
+            }
+            Self::ServiceType => {
+                "line.service_type AS group_id, \
+                 (SELECT display_name FROM main.service_types WHERE id = line.service_type) \
+                     AS group_name"
+            }
+            Self::DeviceManufacturer => {
+                "line.device_manufacturer AS group_id, \
+                 ( \
+                     SELECT display_name FROM main.device_manufacturers \
+                     WHERE id = line.device_manufacturer \
+                 ) AS group_name"
+            }
+            Self::Technician => {
+                "line.technician AS group_id, \
+                 (SELECT display_name FROM main.users WHERE id = line.technician) AS group_name"
+            }
+            Self::PaymentType => {
+                "NULL::integer AS group_id, share.payment_type::text AS group_name"
+            }
+        }
+    }
+}
+
+impl ReportPeriod {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
         }
     }
 }
+
+impl ProfitReportRow {
+    /// Report the profit of the invoices created between `from` and `to`, grouped by `grouping`.
+    ///
+    /// Periods start at midnight in `timezone` and are listed in order, while other groups are
+    /// listed with the most revenue first.
+    pub async fn query(
+        database: &Database,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        grouping: ProfitGrouping,
+        period: ReportPeriod,
+        timezone: &str,
+    ) -> Vec<Self> {
+        let (join, weight) = match grouping {
+            ProfitGrouping::PaymentType => (
+                "JOIN share ON line.invoice = share.invoice",
+                "share.fraction",
+            ),
+            _ => ("", "1"),
+        };
+        let order = match grouping {
+            ProfitGrouping::Period => "group_name",
+            _ => "revenue DESC, group_name",
+        };
+
+        sqlx::query_as(&format!(
+            "{PROFIT_LINES} \
+             SELECT \
+                 {columns}, \
+                 COUNT(DISTINCT line.invoice)::integer AS invoices, \
+                 ROUND(SUM(line.revenue * {weight}), 2) AS revenue, \
+                 ROUND(SUM(line.cost * {weight}), 2) AS cost_of_goods, \
+                 ROUND(SUM((line.revenue - line.cost) * {weight}), 2) AS gross_margin, \
+                 ROUND( \
+                     SUM((line.revenue - line.cost) * {weight}) * 100 \
+                         / NULLIF(SUM(line.revenue * {weight}), 0), \
+                     1 \
+                 ) AS margin_percent, \
+                 ROUND(SUM(line.labor_minutes * {weight}))::integer AS labor_minutes \
+             FROM line {join} \
+             GROUP BY 1, 2 \
+             ORDER BY {order} NULLS LAST",
+            columns = grouping.columns(),
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(period.name())
+        .bind(timezone)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
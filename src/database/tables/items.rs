@@ -1,37 +1,25 @@
-use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::product_prices::ProductPricesTable;
 use super::service_prices::ServicePricesTable;
 use crate::database::shared_models::ItemType;
-use crate::database::Relation;
+use crate::database::{Database, Relation};
 
-#[derive(Relation, Table, BulkInsert, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
 #[relation(relation_name = "items", primary_key = "id")]
 pub struct ItemsTable {
     records: Vec<ItemsTableRecord>,
 }
 
-impl ItemsTable {
-    pub fn get_item_price_by_id(
-        &self,
-        item_id: i32,
-        product_prices: &ProductPricesTable,
-        service_prices: &ServicePricesTable,
-        timestamp: NaiveDateTime,
-    ) -> Decimal {
-        self.records
-            .iter()
-            .find(|r| r.id == item_id)
-            // TODO: Remove this unwrap (probably)
-            .unwrap()
-            .get_item_price_at_time(product_prices, service_prices, timestamp)
-    }
-}
-
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct ItemsTableRecord {
     pub id: i32,
     pub product_or_service: i32,
@@ -39,43 +27,504 @@ pub struct ItemsTableRecord {
     pub r#type: ItemType,
 }
 
-impl ItemsTableRecord {
-    pub fn get_item_price_at_time(
-        &self,
+/// The price history of every item, indexed so that the price of an item at a given time can be
+/// found without scanning the price tables.
+///
+/// This is used when generating synthetic data, where the price of every invoice item is needed.
+pub struct ItemPrices {
+    prices: HashMap<i32, Vec<(DateTime<Utc>, Decimal)>>,
+}
+
+impl ItemPrices {
+    pub fn new(
+        items: &ItemsTable,
         product_prices: &ProductPricesTable,
         service_prices: &ServicePricesTable,
-        timestamp: NaiveDateTime,
-    ) -> Decimal {
-        let product_or_service_id = self.product_or_service;
-        match self.r#type {
-            ItemType::Product => {
-                let most_recent_price = product_prices
-                    .records()
-                    .iter()
-                    .filter(|p| {
-                        p.product == product_or_service_id && p.time_set.unwrap() <= timestamp
-                    })
-                    .max_by_key(|p| p.time_set);
-
-                match most_recent_price {
-                    Some(p) => p.price.unwrap(),
-                    None => Decimal::new(0, 2),
-                }
+    ) -> Self {
+        let mut product_history: HashMap<i32, Vec<(DateTime<Utc>, Decimal)>> = HashMap::new();
+        for price in product_prices.records() {
+            product_history
+                .entry(price.product)
+                .or_default()
+                .push((price.time_set.unwrap(), price.price.unwrap()));
+        }
+        let mut service_history: HashMap<i32, Vec<(DateTime<Utc>, Decimal)>> = HashMap::new();
+        for price in service_prices.records() {
+            service_history.entry(price.service).or_default().push((
+                price.time_set.unwrap(),
+                price.base_fee.unwrap() + price.labor_fee.unwrap(),
+            ));
+        }
+
+        let prices = items
+            .records()
+            .iter()
+            .map(|item| {
+                let history = match item.r#type {
+                    ItemType::Product => product_history.get(&item.product_or_service),
+                    ItemType::Service => service_history.get(&item.product_or_service),
+                };
+                let mut history = history.cloned().unwrap_or_default();
+                history.sort_by_key(|(time_set, _)| *time_set);
+                (item.id, history)
+            })
+            .collect();
+
+        Self { prices }
+    }
+
+    /// Get the most recent price of an item as of `timestamp`, or zero if the item had no price
+    /// at the time.
+    pub fn get_price_at_time(&self, item: i32, timestamp: DateTime<Utc>) -> Decimal {
+        let history = &self.prices[&item];
+        match history.partition_point(|(time_set, _)| *time_set <= timestamp) {
+            0 => Decimal::new(0, 2),
+            index => history[index - 1].1,
+        }
+    }
+}
+
+/// A price an item had, has, or is scheduled to have, from `product_prices` or `service_prices`.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct PriceHistoryEntry {
+    /// The ID of the entry in the price table of the item's type.
+    pub id: i32,
+    pub time_set: DateTime<Utc>,
+    /// What the shop pays for a product. Services have no cost.
+    pub cost: Option<Decimal>,
+    pub base_fee: Option<Decimal>,
+    pub labor_fee: Option<Decimal>,
+    /// What the customer is charged, which is the sum of the fees for services.
+    pub price: Decimal,
+    /// The price minus the cost, for products.
+    pub margin: Option<Decimal>,
+    /// The margin as a percentage of the price.
+    pub margin_percent: Option<Decimal>,
+    /// Whether this is the price the item is charged at now.
+    pub current: bool,
+    /// Whether the price has yet to take effect.
+    pub scheduled: bool,
+}
+
+/// Every price of an item, newest first.
+#[derive(Clone, Serialize, ApiSchema)]
+pub struct PriceHistory {
+    pub item: i32,
+    pub item_type: ItemType,
+    pub description: String,
+    pub entries: Vec<PriceHistoryEntry>,
+}
+
+/// A new price for an item. Anything left out keeps its value as of when the price takes effect.
+pub struct NewPrice {
+    pub cost: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub base_fee: Option<Decimal>,
+    pub labor_fee: Option<Decimal>,
+}
+
+/// Why the price of an item could not be changed.
+pub enum PriceError {
+    ItemNotFound,
+    /// The new price has fields which only belong to the other type of item.
+    WrongFields(ItemType),
+    EntryNotFound,
+    /// The price has already taken effect, so it is part of the item's history.
+    AlreadyInEffect,
+}
+
+/// Changes every matching item's price by a percentage. Filters by service type, device category
+/// or device manufacturer only match services, since products have none of them.
+pub struct PriceAdjustment {
+    pub percent: Decimal,
+    pub effective_at: DateTime<Utc>,
+    pub item_type: Option<ItemType>,
+    pub service_type: Option<i32>,
+    pub device_category: Option<i32>,
+    pub device_manufacturer: Option<i32>,
+}
+
+/// How a price adjustment changes the price of an item.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct PriceChange {
+    pub item: i32,
+    pub item_type: ItemType,
+    pub description: String,
+    pub old_price: Decimal,
+    pub new_price: Decimal,
+}
+
+/// An open quote or invoice with items whose prices would change.
+///
+/// Quotes and invoices keep the prices their items were added at, so their totals stay the same.
+/// The repriced total is what they would come to if their items were added again at the new
+/// prices.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct PriceImpact {
+    pub id: i32,
+    pub customer: Option<i32>,
+    pub current_total: Decimal,
+    pub repriced_total: Decimal,
+    pub changed_items: Vec<i32>,
+}
+
+/// What a price adjustment would change, before it is made.
+#[derive(Clone, Serialize, ApiSchema)]
+pub struct AdjustmentPreview {
+    pub effective_at: DateTime<Utc>,
+    pub changes: Vec<PriceChange>,
+    /// Pending quotes which have not expired, and approved quotes.
+    pub open_quotes: Vec<PriceImpact>,
+    /// The invoices of tickets which are not closed.
+    pub open_invoices: Vec<PriceImpact>,
+}
+
+const SELECT_PRODUCT_HISTORY: &str = "SELECT \
+         price.id, price.time_set, price.cost, \
+         NULL::numeric AS base_fee, NULL::numeric AS labor_fee, price.price, \
+         price.price - price.cost AS margin, \
+         ROUND((price.price - price.cost) * 100 / NULLIF(price.price, 0), 1) AS margin_percent, \
+         price.id IS NOT DISTINCT FROM ( \
+             SELECT id FROM main.product_prices \
+             WHERE product = $1 AND time_set <= CURRENT_TIMESTAMP \
+             ORDER BY time_set DESC, id DESC LIMIT 1 \
+         ) AS current, \
+         price.time_set > CURRENT_TIMESTAMP AS scheduled \
+     FROM main.product_prices price \
+     WHERE price.product = $1";
+
+const SELECT_SERVICE_HISTORY: &str = "SELECT \
+         price.id, price.time_set, NULL::numeric AS cost, \
+         price.base_fee, price.labor_fee, price.base_fee + price.labor_fee AS price, \
+         NULL::numeric AS margin, NULL::numeric AS margin_percent, \
+         price.id IS NOT DISTINCT FROM ( \
+             SELECT id FROM main.service_prices \
+             WHERE service = $1 AND time_set <= CURRENT_TIMESTAMP \
+             ORDER BY time_set DESC, id DESC LIMIT 1 \
+         ) AS current, \
+         price.time_set > CURRENT_TIMESTAMP AS scheduled \
+     FROM main.service_prices price \
+     WHERE price.service = $1";
+
+/// The items matching a price adjustment, along with their prices as of when it takes effect.
+///
+/// The parameters are the percentage, the item type, the service type, the device category, the
+/// device manufacturer and when the adjustment takes effect. Items which had no price by then are
+/// left alone.
+const ADJUSTMENT_TARGETS: &str = "WITH target AS ( \
+         SELECT \
+             item.id AS item, item.type AS item_type, item.product_or_service, \
+             COALESCE( \
+                 product.display_name, \
+                 service_type.display_name || ' (' || model.display_name || ')' \
+             ) AS description \
+         FROM main.items item \
+             LEFT JOIN main.products product \
+                 ON item.type = 'product' AND item.product_or_service = product.sku \
+             LEFT JOIN main.services service \
+                 ON item.type = 'service' AND item.product_or_service = service.id \
+             LEFT JOIN main.service_types service_type ON service.type = service_type.id \
+             LEFT JOIN main.device_models model ON service.device = model.id \
+         WHERE ($2::item_type IS NULL OR item.type = $2) \
+             AND ($3::integer IS NULL OR service.type = $3) \
+             AND ($4::integer IS NULL OR model.category = $4) \
+             AND ($5::integer IS NULL OR model.manufacturer = $5) \
+     ), product_change AS ( \
+         SELECT \
+             target.item, target.description, target.product_or_service AS product, \
+             price.cost, price.price AS old_price, \
+             ROUND(price.price * (100 + $1) / 100, 2) AS new_price \
+         FROM target, main.get_product_price_at_time(target.product_or_service, $6) price \
+         WHERE target.item_type = 'product' AND EXISTS ( \
+             SELECT 1 FROM main.product_prices \
+             WHERE product = target.product_or_service AND time_set <= $6 \
+         ) \
+     ), service_change AS ( \
+         SELECT \
+             target.item, target.description, target.product_or_service AS service, \
+             fee.base_fee + fee.labor_fee AS old_price, \
+             ROUND(fee.base_fee * (100 + $1) / 100, 2) AS base_fee, \
+             ROUND(fee.labor_fee * (100 + $1) / 100, 2) AS labor_fee \
+         FROM target, main.get_service_price_at_time(target.product_or_service, $6) fee \
+         WHERE target.item_type = 'service' AND EXISTS ( \
+             SELECT 1 FROM main.service_prices \
+             WHERE service = target.product_or_service AND time_set <= $6 \
+         ) \
+     )";
+
+const SELECT_CHANGES: &str = "SELECT \
+         item, 'product'::item_type AS item_type, description, old_price, new_price \
+     FROM product_change \
+     UNION ALL \
+     SELECT \
+         item, 'service'::item_type AS item_type, description, old_price, \
+         base_fee + labor_fee AS new_price \
+     FROM service_change \
+     ORDER BY item";
+
+impl PriceHistory {
+    /// Get every price of an item, including any scheduled ones, or [`None`] if there is no such
+    /// item.
+    pub async fn query_for_item(database: &Database, item: i32) -> Option<Self> {
+        let (item_type, product_or_service, description): (ItemType, i32, String) = sqlx::query_as(
+            "SELECT \
+                     item.type, item.product_or_service, \
+                     COALESCE( \
+                         product.display_name, \
+                         service_type.display_name || ' (' || model.display_name || ')' \
+                     ) \
+                 FROM main.items item \
+                     LEFT JOIN main.products product \
+                         ON item.type = 'product' AND item.product_or_service = product.sku \
+                     LEFT JOIN main.services service \
+                         ON item.type = 'service' AND item.product_or_service = service.id \
+                     LEFT JOIN main.service_types service_type \
+                         ON service.type = service_type.id \
+                     LEFT JOIN main.device_models model ON service.device = model.id \
+                 WHERE item.id = $1",
+        )
+        .bind(item)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()?;
+
+        let select = match item_type {
+            ItemType::Product => SELECT_PRODUCT_HISTORY,
+            ItemType::Service => SELECT_SERVICE_HISTORY,
+        };
+        let entries = sqlx::query_as(&format!(
+            "{select} ORDER BY price.time_set DESC, price.id DESC"
+        ))
+        .bind(product_or_service)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+
+        Some(Self {
+            item,
+            item_type,
+            description,
+            entries,
+        })
+    }
+
+    /// Give an item a new price from `effective_at` on.
+    pub async fn schedule(
+        database: &Database,
+        item: i32,
+        effective_at: DateTime<Utc>,
+        new_price: &NewPrice,
+    ) -> Result<PriceHistoryEntry, PriceError> {
+        let item = find_item(database, item).await?;
+        let id: i32 = match item.r#type {
+            ItemType::Product if new_price.base_fee.is_none() && new_price.labor_fee.is_none() => {
+                sqlx::query_scalar(
+                    "INSERT INTO main.product_prices (product, cost, price, time_set) \
+                     SELECT $1, COALESCE($2, current.cost), COALESCE($3, current.price), $4 \
+                     FROM main.get_product_price_at_time($1, $4) current \
+                     RETURNING id",
+                )
+                .bind(item.product_or_service)
+                .bind(new_price.cost)
+                .bind(new_price.price)
+                .bind(effective_at)
+                .fetch_one(&database.connection)
+                .await
+                .unwrap()
             }
-            ItemType::Service => {
-                let most_recent_price = service_prices
-                    .records()
-                    .iter()
-                    .filter(|p| {
-                        p.service == product_or_service_id && p.time_set.unwrap() <= timestamp
-                    })
-                    .max_by_key(|p| p.time_set);
-
-                match most_recent_price {
-                    Some(p) => p.base_fee.unwrap() + p.labor_fee.unwrap(),
-                    None => Decimal::new(0, 2),
-                }
+            ItemType::Service if new_price.cost.is_none() && new_price.price.is_none() => {
+                sqlx::query_scalar(
+                    "INSERT INTO main.service_prices (service, base_fee, labor_fee, time_set) \
+                     SELECT \
+                         $1, COALESCE($2, current.base_fee), COALESCE($3, current.labor_fee), $4 \
+                     FROM main.get_service_price_at_time($1, $4) current \
+                     RETURNING id",
+                )
+                .bind(item.product_or_service)
+                .bind(new_price.base_fee)
+                .bind(new_price.labor_fee)
+                .bind(effective_at)
+                .fetch_one(&database.connection)
+                .await
+                .unwrap()
             }
+            item_type => return Err(PriceError::WrongFields(item_type)),
+        };
+
+        let select = match item.r#type {
+            ItemType::Product => SELECT_PRODUCT_HISTORY,
+            ItemType::Service => SELECT_SERVICE_HISTORY,
+        };
+        Ok(sqlx::query_as(&format!("{select} AND price.id = $2"))
+            .bind(item.product_or_service)
+            .bind(id)
+            .fetch_one(&database.connection)
+            .await
+            .unwrap())
+    }
+
+    /// Cancel a price of an item which has yet to take effect.
+    pub async fn cancel(database: &Database, item: i32, id: i32) -> Result<(), PriceError> {
+        let item = find_item(database, item).await?;
+        let table = match item.r#type {
+            ItemType::Product => "main.product_prices WHERE product = $1",
+            ItemType::Service => "main.service_prices WHERE service = $1",
+        };
+
+        let deleted: Option<i32> = sqlx::query_scalar(&format!(
+            "DELETE FROM {table} AND id = $2 AND time_set > CURRENT_TIMESTAMP RETURNING id"
+        ))
+        .bind(item.product_or_service)
+        .bind(id)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap();
+        if deleted.is_none() {
+            let exists: bool = sqlx::query_scalar(&format!(
+                "SELECT EXISTS (SELECT 1 FROM {table} AND id = $2)"
+            ))
+            .bind(item.product_or_service)
+            .bind(id)
+            .fetch_one(&database.connection)
+            .await
+            .unwrap();
+            return Err(match exists {
+                true => PriceError::AlreadyInEffect,
+                false => PriceError::EntryNotFound,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl PriceAdjustment {
+    /// Get what the adjustment would change, including the open quotes and invoices with items
+    /// whose prices would change.
+    pub async fn preview(&self, database: &Database) -> AdjustmentPreview {
+        let changes: Vec<PriceChange> = self
+            .bind(&format!("{ADJUSTMENT_TARGETS} {SELECT_CHANGES}"))
+            .fetch_all(&database.connection)
+            .await
+            .unwrap();
+
+        let (items, prices): (Vec<i32>, Vec<Decimal>) = changes
+            .iter()
+            .map(|change| (change.item, change.new_price))
+            .unzip();
+        let open_quotes = sqlx::query_as(
+            "WITH change AS ( \
+                 SELECT * FROM UNNEST($1::integer[], $2::numeric[]) AS change (item, new_price) \
+             ) \
+             SELECT \
+                 quote.id, quote.customer, \
+                 SUM(quote_item.unit_price) AS current_total, \
+                 SUM(COALESCE(change.new_price, quote_item.unit_price)) AS repriced_total, \
+                 ARRAY_AGG(change.item ORDER BY change.item) \
+                     FILTER (WHERE change.item IS NOT NULL) AS changed_items \
+             FROM main.quotes quote \
+                 JOIN main.quote_items quote_item ON quote.id = quote_item.quote \
+                 LEFT JOIN change ON quote_item.item = change.item \
+             WHERE quote.status = 'approved' \
+                 OR (quote.status = 'pending' AND quote.expires_at > CURRENT_TIMESTAMP) \
+             GROUP BY quote.id \
+             HAVING COUNT(change.item) > 0 \
+             ORDER BY quote.id",
+        )
+        .bind(&items)
+        .bind(&prices)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+
+        // * Covered items and items priced by hand are charged the same whatever their price is.
+        let open_invoices = sqlx::query_as(
+            "WITH change AS ( \
+                 SELECT * FROM UNNEST($1::integer[], $2::numeric[]) AS change (item, new_price) \
+             ), line AS ( \
+                 SELECT \
+                     invoice_item.*, \
+                     CASE \
+                         WHEN invoice_item.covered OR invoice_item.override_price IS NOT NULL \
+                             THEN NULL \
+                         ELSE change.new_price \
+                     END AS new_price \
+                 FROM main.invoice_items invoice_item \
+                     LEFT JOIN change ON invoice_item.item = change.item \
+             ) \
+             SELECT \
+                 invoice.id, \
+                 ( \
+                     SELECT customer FROM main.tickets \
+                     WHERE invoice = invoice.id ORDER BY created_at DESC LIMIT 1 \
+                 ) AS customer, \
+                 main.get_invoice_total(invoice.id) AS current_total, \
+                 COALESCE( \
+                     SUM(COALESCE(line.new_price, line.override_price, line.unit_price)) \
+                         FILTER (WHERE NOT line.covered), \
+                     0 \
+                 ) AS repriced_total, \
+                 ARRAY_AGG(line.item ORDER BY line.item) \
+                     FILTER (WHERE line.new_price IS NOT NULL) AS changed_items \
+             FROM main.invoices invoice JOIN line ON invoice.id = line.invoice \
+             WHERE EXISTS ( \
+                 SELECT 1 FROM main.tickets \
+                 WHERE invoice = invoice.id AND status <> 'closed' \
+             ) \
+             GROUP BY invoice.id \
+             HAVING COUNT(line.new_price) > 0 \
+             ORDER BY invoice.id",
+        )
+        .bind(&items)
+        .bind(&prices)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+
+        AdjustmentPreview {
+            effective_at: self.effective_at,
+            changes,
+            open_quotes,
+            open_invoices,
         }
     }
+
+    /// Give every matching item its adjusted price from when the adjustment takes effect.
+    pub async fn apply(&self, database: &Database) -> Vec<PriceChange> {
+        self.bind(&format!(
+            "{ADJUSTMENT_TARGETS}, \
+             new_product_price AS ( \
+                 INSERT INTO main.product_prices (product, cost, price, time_set) \
+                 SELECT product, cost, new_price, $6 FROM product_change \
+             ), new_service_price AS ( \
+                 INSERT INTO main.service_prices (service, base_fee, labor_fee, time_set) \
+                 SELECT service, base_fee, labor_fee, $6 FROM service_change \
+             ) \
+             {SELECT_CHANGES}"
+        ))
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    fn bind<'q>(
+        &self,
+        sql: &'q str,
+    ) -> sqlx::query::QueryAs<'q, sqlx::Postgres, PriceChange, sqlx::postgres::PgArguments> {
+        sqlx::query_as(sql)
+            .bind(self.percent)
+            .bind(self.item_type.clone())
+            .bind(self.service_type)
+            .bind(self.device_category)
+            .bind(self.device_manufacturer)
+            .bind(self.effective_at)
+    }
+}
+
+async fn find_item(database: &Database, item: i32) -> Result<ItemsTableRecord, PriceError> {
+    sqlx::query_as("SELECT * FROM main.items WHERE id = $1")
+        .bind(item)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+        .ok_or(PriceError::ItemNotFound)
 }
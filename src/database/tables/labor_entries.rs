@@ -0,0 +1,210 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "labor_entries", primary_key = "id")]
+pub struct LaborEntriesTable {
+    records: Vec<LaborEntriesTableRecord>,
+}
+
+/// Why a labor timer could not be started.
+pub enum TimerError {
+    DeviceNotFound,
+    /// The technician already has a timer running.
+    AlreadyRunning,
+}
+
+/// A span of time a technician spent repairing a device of a ticket.
+///
+/// Entries started with a timer have no `ended_at` until the timer is stopped, and are left out of
+/// labor totals until then.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct LaborEntriesTableRecord {
+    pub id: i32,
+    pub ticket: i32,
+    pub device: i32,
+    pub technician: Option<i32>,
+    #[defaultable]
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+/// How logged labor time compares to the labor fee of a service, across every repair it was logged
+/// against.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct LaborReportRow {
+    pub service: i32,
+    pub type_name: Option<String>,
+    pub device_name: Option<String>,
+    /// The current labor fee of the service.
+    pub labor_fee: Decimal,
+    /// The number of devices with labor logged against them for this service.
+    pub repairs: i32,
+    pub average_minutes: Decimal,
+    /// The labor fee divided by the average hours logged per repair, or [`None`] if no time has
+    /// been logged.
+    pub effective_hourly_rate: Option<Decimal>,
+}
+
+impl LaborEntriesTableRecord {
+    /// The length of the entry in whole minutes, or [`None`] if its timer is still running.
+    pub fn minutes(&self) -> Option<i32> {
+        self.ended_at
+            .map(|ended_at| (ended_at - self.started_at.unwrap()).num_minutes() as i32)
+    }
+
+    /// Get the timer a technician currently has running, if any.
+    pub async fn query_running(database: &Database, technician: i32) -> Option<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.labor_entries WHERE technician = $1 AND ended_at IS NULL",
+        )
+        .bind(technician)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Start a timer for a technician working on a device of a ticket.
+    pub async fn start(
+        database: &Database,
+        ticket: i32,
+        device: i32,
+        technician: i32,
+        notes: Option<&str>,
+    ) -> Result<Self, TimerError> {
+        let entry: Option<Self> = sqlx::query_as(
+            "INSERT INTO main.labor_entries (ticket, device, technician, notes) \
+             SELECT ticket, device, $3, $4 FROM main.ticket_devices WHERE ticket = $1 AND device = $2 \
+             ON CONFLICT (technician) WHERE ended_at IS NULL DO NOTHING \
+             RETURNING *",
+        )
+        .bind(ticket)
+        .bind(device)
+        .bind(technician)
+        .bind(notes)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap();
+        if let Some(entry) = entry {
+            return Ok(entry);
+        }
+
+        // * Nothing is inserted either when the ticket does not have the device or when another
+        // * request started a timer for the technician first, so the device is checked again.
+        let device_exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM main.ticket_devices WHERE ticket = $1 AND device = $2)",
+        )
+        .bind(ticket)
+        .bind(device)
+        .fetch_one(&database.connection)
+        .await
+        .unwrap();
+        Err(match device_exists {
+            true => TimerError::AlreadyRunning,
+            false => TimerError::DeviceNotFound,
+        })
+    }
+
+    /// Stop the timer a technician has running, returning [`None`] if there is none.
+    pub async fn stop(database: &Database, technician: i32) -> Option<Self> {
+        sqlx::query_as(
+            "UPDATE main.labor_entries SET ended_at = CURRENT_TIMESTAMP \
+             WHERE technician = $1 AND ended_at IS NULL RETURNING *",
+        )
+        .bind(technician)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Record a span of time a technician spent on a device of a ticket after the fact.
+    ///
+    /// Returns [`None`] if the ticket does not have the device.
+    pub async fn create(
+        database: &Database,
+        ticket: i32,
+        device: i32,
+        technician: i32,
+        started_at: DateTime<Utc>,
+        ended_at: DateTime<Utc>,
+        notes: Option<&str>,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "INSERT INTO main.labor_entries (ticket, device, technician, started_at, ended_at, notes) \
+             SELECT ticket, device, $3, $4, $5, $6 FROM main.ticket_devices WHERE ticket = $1 AND device = $2 \
+             RETURNING *",
+        )
+        .bind(ticket)
+        .bind(device)
+        .bind(technician)
+        .bind(started_at)
+        .bind(ended_at)
+        .bind(notes)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Delete an entry logged by a technician, returning whether it existed.
+    pub async fn delete_owned(database: &Database, id: i32, technician: i32) -> bool {
+        sqlx::query("DELETE FROM main.labor_entries WHERE id = $1 AND technician = $2")
+            .bind(id)
+            .bind(technician)
+            .execute(&database.connection)
+            .await
+            .unwrap()
+            .rows_affected()
+            > 0
+    }
+
+    /// Get every entry logged against a ticket, ordered by when the work started.
+    pub async fn query_for_ticket(database: &Database, ticket: i32) -> Vec<Self> {
+        sqlx::query_as("SELECT * FROM main.labor_entries WHERE ticket = $1 ORDER BY started_at, id")
+            .bind(ticket)
+            .fetch_all(&database.connection)
+            .await
+            .unwrap()
+    }
+}
+
+impl LaborReportRow {
+    /// Compare the labor logged against every service to its labor fee, with the services earning
+    /// the least per hour of labor first.
+    ///
+    /// Labor on warranty claims is left out, since it is not charged for, and is reported with the
+    /// warranties instead.
+    pub async fn query_all(database: &Database) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT \
+                 service.id AS service, service.type_name, service.device_name, \
+                 COALESCE(service.labor_fee, 0) AS labor_fee, \
+                 COUNT(DISTINCT (entry.ticket, entry.device))::integer AS repairs, \
+                 ROUND(SUM(EXTRACT(EPOCH FROM entry.ended_at - entry.started_at)) / 60 \
+                     / COUNT(DISTINCT (entry.ticket, entry.device)), 1) AS average_minutes, \
+                 ROUND(COALESCE(service.labor_fee, 0) \
+                     / NULLIF(SUM(EXTRACT(EPOCH FROM entry.ended_at - entry.started_at)) / 3600 \
+                         / COUNT(DISTINCT (entry.ticket, entry.device)), 0), 2) AS effective_hourly_rate \
+             FROM main.labor_entries entry \
+                 JOIN main.ticket_devices ticket_device \
+                     ON entry.ticket = ticket_device.ticket AND entry.device = ticket_device.device \
+                 JOIN main.services_view service ON ticket_device.service = service.id \
+             WHERE entry.ended_at IS NOT NULL \
+                 AND NOT EXISTS (SELECT 1 FROM main.warranty_claims WHERE ticket = entry.ticket) \
+             GROUP BY service.id, service.type_name, service.device_name, service.labor_fee \
+             ORDER BY effective_hourly_rate ASC NULLS LAST, service.id",
+        )
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
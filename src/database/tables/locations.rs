@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::{Database, GenerateStaticRecord, GenerateStaticTable};
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "locations", primary_key = "id")]
+pub struct LocationsTable {
+    records: Vec<LocationsTableRecord>,
+}
+
+/// A store of the shop.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct LocationsTableRecord {
+    pub id: i32,
+    pub display_name: String,
+    pub address: Option<String>,
+}
+
+impl GenerateStaticTable for LocationsTable {
+    const ITEMS: &[&str] = &["Downtown", "Eastside"];
+}
+
+impl GenerateStaticRecord for LocationsTableRecord {
+    fn new(id: i32, display_name: impl Into<String>) -> Self {
+        Self {
+            id,
+            display_name: display_name.into(),
+            address: None,
+        }
+    }
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "part_stock",
+    primary_key = "(location, part)",
+    serial_primary_key = false
+)]
+pub struct PartStockJunctionTable {
+    records: Vec<PartStockJunctionTableRecord>,
+}
+
+/// How many of a part a store has on hand.
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize, ApiSchema)]
+pub struct PartStockJunctionTableRecord {
+    pub location: i32,
+    pub part: i32,
+    pub quantity: i32,
+    /// How few of the part the store can have before it is warned that it is running low.
+    pub low_stock_threshold: i32,
+}
+
+/// The stock of a part at a store, along with what is on its way there.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct PartStockLevel {
+    pub location: i32,
+    pub location_name: String,
+    pub part: i32,
+    pub part_name: String,
+    pub quantity: i32,
+    /// How many of the part have been sent to the store but not yet received.
+    pub incoming: i32,
+}
+
+impl LocationsTableRecord {
+    /// Get every store, in the order they were added.
+    pub async fn query_all(database: &Database) -> Vec<Self> {
+        sqlx::query_as("SELECT * FROM main.locations ORDER BY id")
+            .fetch_all(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Get a store by its ID, or the first store if no ID is given. Returns [`None`] if there is
+    /// no such store.
+    pub async fn query_or_default(database: &Database, id: Option<i32>) -> Option<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.locations \
+             WHERE id = COALESCE($1, main.get_default_location())",
+        )
+        .bind(id)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Add a store, returning [`None`] if there is already a store with the same name.
+    pub async fn create(
+        database: &Database,
+        display_name: &str,
+        address: Option<&str>,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "INSERT INTO main.locations (display_name, address) VALUES ($1, $2) \
+             ON CONFLICT (display_name) DO NOTHING \
+             RETURNING *",
+        )
+        .bind(display_name)
+        .bind(address)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl PartStockJunctionTableRecord {
+    /// Set how many of a part a store has on hand, such as after counting it, and optionally how
+    /// few it can have before it is running low. Returns [`None`] if there is no such store or part.
+    pub async fn set(
+        database: &Database,
+        location: i32,
+        part: i32,
+        quantity: i32,
+        low_stock_threshold: Option<i32>,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "INSERT INTO main.part_stock (location, part, quantity, low_stock_threshold) \
+             SELECT location.id, part.id, $3, COALESCE($4, 1) \
+             FROM main.locations location, main.parts part \
+             WHERE location.id = $1 AND part.id = $2 \
+             ON CONFLICT (location, part) DO UPDATE SET \
+                 quantity = EXCLUDED.quantity, \
+                 low_stock_threshold = COALESCE($4, part_stock.low_stock_threshold) \
+             RETURNING *",
+        )
+        .bind(location)
+        .bind(part)
+        .bind(quantity)
+        .bind(low_stock_threshold)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl PartStockLevel {
+    /// Get the stock of every part at every store, optionally only at one store or of one part.
+    ///
+    /// Parts a store has none of and none on the way are left out.
+    pub async fn query(database: &Database, location: Option<i32>, part: Option<i32>) -> Vec<Self> {
+        sqlx::query_as(
+            "WITH incoming AS ( \
+                 SELECT to_location AS location, part, SUM(quantity)::integer AS quantity \
+                 FROM main.transfers \
+                 WHERE part IS NOT NULL AND received_at IS NULL \
+                 GROUP BY to_location, part \
+             ) \
+             SELECT \
+                 location.id AS location, location.display_name AS location_name, \
+                 part.id AS part, part.display_name AS part_name, \
+                 COALESCE(stock.quantity, 0) AS quantity, \
+                 COALESCE(incoming.quantity, 0) AS incoming \
+             FROM main.part_stock stock \
+                 FULL JOIN incoming \
+                     ON stock.location = incoming.location AND stock.part = incoming.part \
+                 JOIN main.locations location \
+                     ON location.id = COALESCE(stock.location, incoming.location) \
+                 JOIN main.parts part ON part.id = COALESCE(stock.part, incoming.part) \
+             WHERE ($1::integer IS NULL OR location.id = $1) \
+                 AND ($2::integer IS NULL OR part.id = $2) \
+                 AND (COALESCE(stock.quantity, 0) > 0 OR COALESCE(incoming.quantity, 0) > 0) \
+             ORDER BY location.id, part.display_name, part.id",
+        )
+        .bind(location)
+        .bind(part)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
@@ -1,7 +1,10 @@
+pub mod appointments;
 pub mod bundled_parts;
+pub mod business_hours;
 pub mod compatible_parts;
 pub mod customers;
 pub mod device_categories;
+pub mod device_conditions;
 pub mod device_manufacturers;
 pub mod device_models;
 pub mod devices;
@@ -9,139 +12,387 @@ pub mod invoice_items;
 pub mod invoice_payments;
 pub mod invoices;
 pub mod items;
+pub mod labor_entries;
+pub mod locations;
 pub mod part_categories;
 pub mod part_manufacturers;
 pub mod parts;
 pub mod product_prices;
 pub mod products;
+pub mod quotes;
+pub mod register_sessions;
+pub mod saved_views;
 pub mod service_prices;
 pub mod service_types;
 pub mod services;
+pub mod sessions;
+pub mod ticket_assignments;
 pub mod ticket_devices;
 pub mod tickets;
+pub mod transfers;
 pub mod type_allocation_codes;
+pub mod users;
 pub mod vendors;
+pub mod warranties;
 
 pub trait IdentifiableRecord {
     fn id(&self) -> i32;
 }
 
 pub mod generators {
-    use std::collections::HashSet;
+    use std::borrow::Borrow;
+    use std::collections::{HashMap, HashSet};
+    use std::hash::Hash;
 
-    use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+    use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
     use fake::faker::address::en::{CityName, StateAbbr, StreetName, StreetSuffix};
     use fake::faker::company::en::CompanyName;
     use fake::faker::internet::en::FreeEmail;
     use fake::faker::name::en::Name;
     use fake::faker::phone_number::en::PhoneNumber;
     use fake::Fake;
-    use rand::{thread_rng, Rng};
+    use rand::Rng;
+    use rand_chacha::ChaCha8Rng;
     use rust_decimal::Decimal;
 
     use crate::database::shared_models::{PaymentType, TicketStatus};
 
-    pub fn generate_option<T>(maybe_value: T, some_chance: f64) -> Option<T> {
-        match thread_rng().gen_bool(some_chance) {
-            true => Some(maybe_value),
-            false => None,
+    /// The random number generator used for all synthetic data generation.
+    ///
+    /// A specific algorithm is used rather than [`rand::rngs::StdRng`] because the output of
+    /// `StdRng` is not guaranteed to stay the same between versions of `rand`, and the same seed
+    /// must always produce the same dataset.
+    pub type GenerationRng = ChaCha8Rng;
+
+    /// Record IDs grouped by some key, such as a name or a foreign key column.
+    ///
+    /// This is built once before generating a table whose records must be related to a specific
+    /// subset of a dependency table, so that the subset can be found without scanning the whole
+    /// dependency table for each generated record.
+    pub struct GroupedIds<K> {
+        groups: HashMap<K, Vec<i32>>,
+    }
+
+    impl<K: Eq + Hash> GroupedIds<K> {
+        pub fn new(entries: impl IntoIterator<Item = (K, i32)>) -> Self {
+            let mut groups: HashMap<K, Vec<i32>> = HashMap::new();
+            for (key, id) in entries {
+                groups.entry(key).or_default().push(id);
+            }
+
+            Self { groups }
+        }
+
+        pub fn get<Q>(&self, key: &Q) -> &[i32]
+        where
+            K: Borrow<Q>,
+            Q: Eq + Hash + ?Sized,
+        {
+            self.groups.get(key).map(Vec::as_slice).unwrap_or_default()
         }
     }
 
-    pub fn generate_unique_i32(min: i32, existing: &mut HashSet<i32>) -> i32 {
-        let mut val = 0;
-        let mut first_roll = true;
-        while first_roll || existing.get(&val).is_some() {
-            val = thread_rng().gen_range(min..=i32::MAX);
-            first_roll = false;
+    pub fn generate_option<T>(
+        maybe_value: T,
+        some_chance: f64,
+        rng: &mut GenerationRng,
+    ) -> Option<T> {
+        match rng.gen_bool(some_chance) {
+            true => Some(maybe_value),
+            false => None,
         }
+    }
 
-        existing.insert(val);
+    /// Hand out the next ID of a table whose IDs are numbered from 1 in the order they are
+    /// generated.
+    pub fn generate_sequential_i32(existing: &mut HashSet<i32>) -> i32 {
+        let id = existing.len() as i32 + 1;
+        existing.insert(id);
 
-        val
+        id
     }
 
-    pub fn generate_dollar_value(min: Option<f32>, max: Option<f32>) -> Decimal {
+    pub fn generate_dollar_value(
+        min: Option<f32>,
+        max: Option<f32>,
+        rng: &mut GenerationRng,
+    ) -> Decimal {
         let adjusted_min = (min.unwrap_or_default() * 100.0) as i64;
         let adjusted_max = (max.unwrap_or_default() * 100.0) as i64;
-        Decimal::new(thread_rng().gen_range(adjusted_min..=adjusted_max), 2)
+        Decimal::new(rng.gen_range(adjusted_min..=adjusted_max), 2)
     }
 
-    pub fn generate_name() -> String {
-        Name().fake()
+    pub fn generate_name(rng: &mut GenerationRng) -> String {
+        Name().fake_with_rng(rng)
     }
 
-    pub fn generate_email_address() -> String {
-        FreeEmail().fake()
+    pub fn generate_email_address(rng: &mut GenerationRng) -> String {
+        FreeEmail().fake_with_rng(rng)
     }
 
-    pub fn generate_phone_number() -> String {
-        PhoneNumber().fake()
+    pub fn generate_phone_number(rng: &mut GenerationRng) -> String {
+        PhoneNumber().fake_with_rng(rng)
     }
 
-    pub fn generate_street_address() -> String {
+    pub fn generate_street_address(rng: &mut GenerationRng) -> String {
         format!(
             "{} {} {}, {}, {} {}",
-            thread_rng().gen_range(1..=9999),
-            StreetName().fake::<String>(),
-            StreetSuffix().fake::<String>(),
-            CityName().fake::<String>(),
-            StateAbbr().fake::<String>(),
-            thread_rng().gen_range(10000..=99999)
+            rng.gen_range(1..=9999),
+            StreetName().fake_with_rng::<String, _>(rng),
+            StreetSuffix().fake_with_rng::<String, _>(rng),
+            CityName().fake_with_rng::<String, _>(rng),
+            StateAbbr().fake_with_rng::<String, _>(rng),
+            rng.gen_range(10000..=99999)
         )
     }
 
-    pub fn generate_company_name() -> String {
-        CompanyName().fake()
+    pub fn generate_company_name(rng: &mut GenerationRng) -> String {
+        CompanyName().fake_with_rng(rng)
     }
 
-    pub fn generate_date(start: Option<NaiveDateTime>) -> NaiveDateTime {
-        let start = start.unwrap_or_else(|| {
-            NaiveDate::from_ymd_opt(2020, 1, 1)
-                .unwrap()
-                .and_hms_opt(0, 0, 0)
-                .unwrap()
-        });
+    pub fn generate_date(start: Option<DateTime<Utc>>, rng: &mut GenerationRng) -> DateTime<Utc> {
+        let start = start.map_or_else(
+            || {
+                NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+            },
+            |start| start.naive_utc(),
+        );
         let end = NaiveDate::from_ymd_opt(2025, 1, 1)
             .unwrap()
             .and_hms_opt(0, 0, 0)
             .unwrap();
         let days_in_range = (end - start).num_days();
-        let random_days = thread_rng().gen_range(0..=days_in_range);
+        let random_days = rng.gen_range(0..=days_in_range);
 
-        let hours = thread_rng().gen_range(0..24);
-        let minutes = thread_rng().gen_range(0..60);
-        let seconds = thread_rng().gen_range(0..60);
+        let hours = rng.gen_range(0..24);
+        let minutes = rng.gen_range(0..60);
+        let seconds = rng.gen_range(0..60);
 
         NaiveDateTime::new(
             start.date() + Duration::days(random_days),
             NaiveTime::from_hms_opt(hours, minutes, seconds).unwrap(),
         )
+        .and_utc()
     }
 
-    // TODO: Use company name to generate device model name
-    pub fn generate_device_name() -> String {
-        const PHONE_LINES: [&str; 8] = [
-            "iPhone",
-            "Samsung Galaxy",
-            "Google Pixel",
-            "Motorola G",
-            "LG",
-            "Nokia",
-            "Sony Xperia",
-            "OnePlus",
-        ];
+    /// Generate a date and time at most `max_days` days after `start`.
+    pub fn generate_date_within(
+        start: DateTime<Utc>,
+        max_days: i64,
+        rng: &mut GenerationRng,
+    ) -> DateTime<Utc> {
+        start + Duration::minutes(rng.gen_range(0..=max_days * 24 * 60))
+    }
+
+    /// A real device manufacturer along with the product lines it makes for each device category.
+    pub struct DeviceBrand {
+        pub manufacturer: &'static str,
+        /// Pairs of device category names and product line names.
+        pub product_lines: &'static [(&'static str, &'static str)],
+    }
+
+    /// Well-known device manufacturers, which make up the first device manufacturers generated so
+    /// that most device models look like ones a repair shop would actually see.
+    pub const DEVICE_BRANDS: &[DeviceBrand] = &[
+        DeviceBrand {
+            manufacturer: "Apple",
+            product_lines: &[
+                ("Phone", "iPhone"),
+                ("Tablet", "iPad"),
+                ("Laptop", "MacBook"),
+                ("Desktop", "iMac"),
+            ],
+        },
+        DeviceBrand {
+            manufacturer: "Samsung",
+            product_lines: &[
+                ("Phone", "Galaxy S"),
+                ("Phone", "Galaxy A"),
+                ("Tablet", "Galaxy Tab S"),
+                ("Laptop", "Galaxy Book"),
+            ],
+        },
+        DeviceBrand {
+            manufacturer: "Google",
+            product_lines: &[("Phone", "Pixel"), ("Tablet", "Pixel Tablet")],
+        },
+        DeviceBrand {
+            manufacturer: "Motorola",
+            product_lines: &[("Phone", "Moto G"), ("Phone", "Edge")],
+        },
+        DeviceBrand {
+            manufacturer: "OnePlus",
+            product_lines: &[("Phone", "OnePlus"), ("Tablet", "OnePlus Pad")],
+        },
+        DeviceBrand {
+            manufacturer: "Sony",
+            product_lines: &[
+                ("Phone", "Xperia"),
+                ("Game Console", "PlayStation"),
+                ("Camera", "Alpha"),
+            ],
+        },
+        DeviceBrand {
+            manufacturer: "Microsoft",
+            product_lines: &[
+                ("Tablet", "Surface Pro"),
+                ("Laptop", "Surface Laptop"),
+                ("Game Console", "Xbox"),
+            ],
+        },
+        DeviceBrand {
+            manufacturer: "Nintendo",
+            product_lines: &[("Game Console", "Switch")],
+        },
+        DeviceBrand {
+            manufacturer: "Dell",
+            product_lines: &[("Laptop", "XPS"), ("Desktop", "OptiPlex")],
+        },
+        DeviceBrand {
+            manufacturer: "DJI",
+            product_lines: &[("Drone", "Mavic"), ("Drone", "Mini"), ("Camera", "Osmo")],
+        },
+    ];
+
+    pub fn find_device_brand(manufacturer: &str) -> Option<&'static DeviceBrand> {
+        DEVICE_BRANDS
+            .iter()
+            .find(|b| b.manufacturer == manufacturer)
+    }
+
+    /// Generate the display name of a device model in the given product line, such as
+    /// "iPhone 12 Pro" or "Galaxy S21 Ultra".
+    pub fn generate_device_model_name(product_line: &str, rng: &mut GenerationRng) -> String {
+        const MODIFIERS: [&str; 6] = ["Pro", "Pro Max", "Ultra", "Plus", "Lite", "Mini"];
+
+        let generation = rng.gen_range(1..=16);
+        // * Lines ending in a single letter (e.g. "Galaxy S") have the generation appended directly.
+        let separator = match product_line.rsplit(' ').next() {
+            Some(word) if word.len() == 1 => "",
+            _ => " ",
+        };
+
+        match generate_option(MODIFIERS[rng.gen_range(0..MODIFIERS.len())], 0.5, rng) {
+            Some(modifier) => format!("{product_line}{separator}{generation} {modifier}"),
+            None => format!("{product_line}{separator}{generation}"),
+        }
+    }
+
+    /// Generate a model identifier in the style used by the given manufacturer, such as "A2172"
+    /// for Apple or "SM-G991U" for Samsung.
+    pub fn generate_model_identifier(manufacturer: &str, rng: &mut GenerationRng) -> String {
+        const ALPHANUMERICS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ0123456789";
+
+        match manufacturer {
+            "Apple" => format!("A{}", rng.gen_range(1000..=3200)),
+            "Samsung" => format!(
+                "SM-{}{}{}",
+                ['G', 'A', 'S', 'N', 'X', 'T'][rng.gen_range(0..6)],
+                rng.gen_range(100..=999),
+                ['U', 'W', 'F', 'B'][rng.gen_range(0..4)]
+            ),
+            "Google" => (0..5)
+                .map(|_| ALPHANUMERICS[rng.gen_range(0..ALPHANUMERICS.len())] as char)
+                .collect(),
+            "Motorola" => format!("XT{}-{}", rng.gen_range(2000..=2500), rng.gen_range(1..=5)),
+            _ => {
+                let initials: String = manufacturer
+                    .split_whitespace()
+                    .filter_map(|word| word.chars().find(|c| c.is_ascii_alphabetic()))
+                    .take(3)
+                    .collect();
+                format!(
+                    "{}-{}",
+                    initials.to_ascii_uppercase(),
+                    rng.gen_range(1000..=9999)
+                )
+            }
+        }
+    }
+
+    /// Generate the display name of a part for a device model, such as
+    /// "iPhone 12 Pro Screen (OEM)".
+    pub fn generate_part_name(
+        device_model: &str,
+        category: &str,
+        manufacturer: Option<&str>,
+        rng: &mut GenerationRng,
+    ) -> String {
+        const GRADES: [&str; 4] = ["OEM", "Premium", "Aftermarket", "Refurbished"];
+
+        let source = manufacturer.unwrap_or_else(|| GRADES[rng.gen_range(0..GRADES.len())]);
+        format!("{device_model} {category} ({source})")
+    }
+
+    /// Get a realistic range of costs for a part in the given category.
+    pub fn part_cost_range(category: &str) -> (f32, f32) {
+        match category {
+            "Screen" => (20.00, 400.00),
+            "Battery" => (5.00, 80.00),
+            "Backglass" => (5.00, 60.00),
+            "Frame" => (10.00, 120.00),
+            "Front Camera" => (5.00, 60.00),
+            "Rear Camera" => (10.00, 150.00),
+            "Charge Port" => (3.00, 40.00),
+            _ => (1.00, 500.00),
+        }
+    }
 
-        const MODIFIERS: [&str; 8] = ["Pro", "Max", "Ultra", "Plus", "Lite", "Mini", "X", "Z"];
+    pub fn generate_product_name(rng: &mut GenerationRng) -> String {
+        const PRODUCTS: [(&str, &[&str], &[&str]); 8] = [
+            (
+                "Screen Protector",
+                &["Tempered Glass", "Privacy", "Matte"],
+                &["iPhone", "Galaxy", "Pixel", "iPad"],
+            ),
+            (
+                "Phone Case",
+                &["Rugged", "Slim", "Clear", "Wallet"],
+                &["Black", "Clear", "Blue", "Red"],
+            ),
+            (
+                "USB-C Cable",
+                &["Braided", "Fast Charging"],
+                &["3ft", "6ft", "10ft"],
+            ),
+            (
+                "Lightning Cable",
+                &["Braided", "MFi Certified"],
+                &["3ft", "6ft", "10ft"],
+            ),
+            (
+                "Wall Charger",
+                &["Fast", "Dual Port", "Compact"],
+                &["20W", "30W", "65W"],
+            ),
+            (
+                "Wireless Charger",
+                &["Magnetic", "Stand", "Pad"],
+                &["10W", "15W"],
+            ),
+            (
+                "Power Bank",
+                &["Slim", "Rugged"],
+                &["5000mAh", "10000mAh", "20000mAh"],
+            ),
+            (
+                "Earbuds",
+                &["Wireless", "Wired", "Noise Cancelling"],
+                &["Black", "White"],
+            ),
+        ];
 
-        let phone = PHONE_LINES[thread_rng().gen_range(0..PHONE_LINES.len())];
-        let generation = thread_rng().gen_range(1..=50);
-        let modifier = MODIFIERS[thread_rng().gen_range(0..MODIFIERS.len())];
+        let (product, qualifiers, variants) = PRODUCTS[rng.gen_range(0..PRODUCTS.len())];
+        let qualifier = qualifiers[rng.gen_range(0..qualifiers.len())];
+        let variant = variants[rng.gen_range(0..variants.len())];
 
-        format!("{} {} {}", phone, generation, modifier)
+        format!("{qualifier} {product} ({variant})")
     }
 
-    pub fn generate_diagnostic() -> String {
+    pub fn generate_diagnostic(rng: &mut GenerationRng) -> String {
         const DIAGNOSTICS: [&str; 8] = [
             "Cracked Screen",
             "Battery Replacement",
@@ -153,23 +404,76 @@ pub mod generators {
             "Camera Issue",
         ];
 
-        DIAGNOSTICS[thread_rng().gen_range(0..DIAGNOSTICS.len())].to_owned()
+        DIAGNOSTICS[rng.gen_range(0..DIAGNOSTICS.len())].to_owned()
     }
 
-    pub fn generate_ticket_status() -> TicketStatus {
-        match thread_rng().gen_range(0..=5) {
-            0 => TicketStatus::New,
-            1 => TicketStatus::WaitingForParts,
-            2 => TicketStatus::WaitingForCustomer,
-            3 => TicketStatus::InRepair,
-            4 => TicketStatus::ReadyForPickup,
-            5 => TicketStatus::Closed,
-            _ => unreachable!(),
+    /// Generate a ticket status which is consistent with how much of the ticket's invoice has been
+    /// paid.
+    ///
+    /// Tickets without an invoice haven't been worked on yet, tickets with a partially-paid
+    /// invoice are in progress, and tickets with a fully-paid invoice are (almost always) closed.
+    pub fn generate_ticket_status(
+        has_invoice: bool,
+        partially_paid: bool,
+        fully_paid: bool,
+        rng: &mut GenerationRng,
+    ) -> TicketStatus {
+        const OPEN_STATUSES: [TicketStatus; 4] = [
+            TicketStatus::New,
+            TicketStatus::WaitingForParts,
+            TicketStatus::WaitingForCustomer,
+            TicketStatus::InRepair,
+        ];
+
+        if fully_paid {
+            match rng.gen_bool(0.9) {
+                true => TicketStatus::Closed,
+                false => TicketStatus::ReadyForPickup,
+            }
+        } else if partially_paid {
+            match rng.gen_bool(0.5) {
+                true => TicketStatus::InRepair,
+                false => TicketStatus::ReadyForPickup,
+            }
+        } else if has_invoice {
+            match rng.gen_bool(0.2) {
+                true => TicketStatus::ReadyForPickup,
+                false => OPEN_STATUSES[rng.gen_range(1..OPEN_STATUSES.len())],
+            }
+        } else {
+            OPEN_STATUSES[rng.gen_range(0..OPEN_STATUSES.len())]
         }
     }
 
-    pub fn generate_payment_type() -> PaymentType {
-        match thread_rng().gen_range(0..=1) {
+    /// Generate the notes a technician might leave on a ticket with the given status.
+    pub fn generate_ticket_notes(status: TicketStatus, rng: &mut GenerationRng) -> Vec<String> {
+        const INTAKE_NOTES: [&str; 4] = [
+            "Customer declined data backup",
+            "Passcode provided by customer",
+            "Device powers on, screen unresponsive",
+            "Existing scratches on housing noted at check-in",
+        ];
+
+        let status_note = match status {
+            TicketStatus::New => None,
+            TicketStatus::WaitingForParts => Some("Ordered replacement part from vendor"),
+            TicketStatus::WaitingForCustomer => Some("Left voicemail about repair quote"),
+            TicketStatus::InRepair => Some("Disassembled, starting repair"),
+            TicketStatus::ReadyForPickup => Some("Repair complete, texted customer for pickup"),
+            TicketStatus::Closed => Some("Device picked up by customer"),
+        };
+
+        let mut notes = Vec::new();
+        if rng.gen_bool(0.5) {
+            notes.push(INTAKE_NOTES[rng.gen_range(0..INTAKE_NOTES.len())].to_owned());
+        }
+        notes.extend(status_note.map(str::to_owned));
+
+        notes
+    }
+
+    pub fn generate_payment_type(rng: &mut GenerationRng) -> PaymentType {
+        match rng.gen_range(0..=1) {
             0 => PaymentType::Card,
             1 => PaymentType::Cash,
             _ => unreachable!(),
@@ -1,14 +1,16 @@
-use proc_macros::{BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
 
 use crate::database::{GenerateStaticRecord, GenerateStaticTable};
 
-#[derive(Relation, Table, BulkInsert, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
 #[relation(relation_name = "part_categories", primary_key = "id")]
 pub struct PartCategoriesTable {
     records: Vec<PartCategoriesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct PartCategoriesTableRecord {
     pub id: i32,
     pub display_name: String,
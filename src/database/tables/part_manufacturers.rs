@@ -1,17 +1,21 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::generators::*;
 use crate::database::GenerateRecord;
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "part_manufacturers", primary_key = "id")]
 pub struct PartManufacturersTable {
     records: Vec<PartManufacturersTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct PartManufacturersTableRecord {
     pub id: i32,
     pub display_name: String,
@@ -24,10 +28,11 @@ impl GenerateRecord for PartManufacturersTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         _dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            display_name: generate_company_name(),
+            id: generate_sequential_i32(existing_ids),
+            display_name: generate_company_name(rng),
         }
     }
 }
@@ -2,9 +2,13 @@ use std::collections::HashSet;
 
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
+use super::device_models::DeviceModelsTable;
 use super::generators::*;
 use super::part_categories::PartCategoriesTable;
 use super::part_manufacturers::PartManufacturersTable;
@@ -12,13 +16,13 @@ use super::vendors::VendorsTable;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "parts", primary_key = "id")]
 pub struct PartsTable {
     records: Vec<PartsTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct PartsTableRecord {
     pub id: i32,
     pub display_name: String,
@@ -37,23 +41,42 @@ impl GenerateRecord for PartsTableRecord {
         &'a VendorsTable,
         &'a PartManufacturersTable,
         &'a PartCategoriesTable,
+        &'a DeviceModelsTable,
     );
 
     fn generate(
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let cost = generate_dollar_value(Some(1.00), Some(500.00));
-        let price = generate_dollar_value(Some(cost.to_f32().unwrap()), Some(1000.00));
+        let (vendors, manufacturers, categories, device_models) = dependencies;
+
+        let category = categories.pick_random(rng);
+        let manufacturer = generate_option(manufacturers.pick_random(rng), 0.2, rng);
+        // * The part is named after the device model it was made for, which is always recorded as
+        // * one of its compatible devices when compatible parts are generated.
+        let device_model = device_models.pick_random(rng);
+
+        let (min_cost, max_cost) = part_cost_range(&category.display_name);
+        let cost = generate_dollar_value(Some(min_cost), Some(max_cost), rng);
+        let price = generate_dollar_value(
+            Some(cost.to_f32().unwrap()),
+            Some(cost.to_f32().unwrap() * 2.5),
+            rng,
+        );
 
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            // TODO: Generate via vendor/manufacturer/category data along with compatibilities
-            display_name: "PLACEHOLDER".to_owned(),
-            vendor: dependencies.0.pick_random().id(),
-            manufacturer: generate_option(dependencies.1.pick_random().id(), 0.2),
-            category: dependencies.2.pick_random().id(),
+            id: generate_sequential_i32(existing_ids),
+            display_name: generate_part_name(
+                &device_model.display_name,
+                &category.display_name,
+                manufacturer.as_ref().map(|m| m.display_name.as_str()),
+                rng,
+            ),
+            vendor: vendors.pick_random(rng).id(),
+            manufacturer: manufacturer.map(|m| m.id()),
+            category: category.id(),
             cost: Some(cost),
             price: Some(price),
         }
@@ -1,23 +1,26 @@
 use std::collections::HashSet;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::generators::*;
 use super::products::ProductsTable;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "product_prices", primary_key = "id")]
 pub struct ProductPricesTable {
     records: Vec<ProductPricesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct ProductPricesTableRecord {
     pub id: i32,
     pub product: i32,
@@ -26,7 +29,7 @@ pub struct ProductPricesTableRecord {
     #[defaultable]
     pub price: Option<Decimal>,
     #[defaultable]
-    pub time_set: Option<NaiveDateTime>,
+    pub time_set: Option<DateTime<Utc>>,
 }
 
 impl GenerateRecord for ProductPricesTableRecord {
@@ -36,16 +39,17 @@ impl GenerateRecord for ProductPricesTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let cost = generate_dollar_value(Some(1.00), Some(500.00));
-        let price = generate_dollar_value(Some(cost.to_f32().unwrap()), Some(1000.00));
+        let cost = generate_dollar_value(Some(1.00), Some(500.00), rng);
+        let price = generate_dollar_value(Some(cost.to_f32().unwrap()), Some(1000.00), rng);
 
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            product: dependencies.pick_random().id(),
+            id: generate_sequential_i32(existing_ids),
+            product: dependencies.pick_random(rng).id(),
             cost: Some(cost),
             price: Some(price),
-            time_set: Some(generate_date(None)),
+            time_set: Some(generate_date(None, rng)),
         }
     }
 }
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::shared_models::{ItemType, QuoteStatus};
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "quotes", primary_key = "id")]
+pub struct QuotesTable {
+    records: Vec<QuotesTableRecord>,
+}
+
+/// An estimate of a repair given to a customer before they commit to it.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct QuotesTableRecord {
+    pub id: i32,
+    pub customer: i32,
+    #[defaultable]
+    #[api_schema(value_type = QuoteStatus)]
+    pub status: Option<QuoteStatus>,
+    pub notes: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    #[defaultable]
+    #[api_schema(value_type = DateTime<Utc>)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the customer approved or declined the quote.
+    pub decided_at: Option<DateTime<Utc>>,
+    /// The ticket the quote was converted into, if it has been.
+    pub ticket: Option<i32>,
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "quote_devices",
+    primary_key = "(quote, device)",
+    serial_primary_key = false
+)]
+pub struct QuoteDevicesJunctionTable {
+    records: Vec<QuoteDevicesJunctionTableRecord>,
+}
+
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize, ApiSchema)]
+pub struct QuoteDevicesJunctionTableRecord {
+    pub quote: i32,
+    pub device: i32,
+    pub service: i32,
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "quote_items",
+    primary_key = "(quote, item)",
+    serial_primary_key = false
+)]
+pub struct QuoteItemsJunctionTable {
+    records: Vec<QuoteItemsJunctionTableRecord>,
+}
+
+/// An item of a quote, at the price it was quoted at.
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize)]
+pub struct QuoteItemsJunctionTableRecord {
+    pub quote: i32,
+    pub item: i32,
+    pub unit_price: Decimal,
+}
+
+/// An item of a quote, described the way it is in `items_view`.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct QuoteLine {
+    #[serde(skip)]
+    pub quote: i32,
+    pub item: i32,
+    pub item_type: ItemType,
+    pub description: String,
+    pub unit_price: Decimal,
+}
+
+/// A quote along with its devices, its items and what they add up to.
+#[derive(Clone, Serialize, ApiSchema)]
+pub struct QuoteDetails {
+    #[serde(flatten)]
+    pub quote: QuotesTableRecord,
+    /// Whether the quote ran out before the customer decided on it.
+    pub expired: bool,
+    pub devices: Vec<QuoteDevicesJunctionTableRecord>,
+    pub items: Vec<QuoteLine>,
+    pub total: Decimal,
+}
+
+/// Why a quote could not be created or changed.
+pub enum QuoteError {
+    CustomerNotFound,
+    DeviceNotFound(i32),
+    /// The device belongs to a different customer than the quote.
+    DeviceNotOwned(i32),
+    ServiceNotFound(i32),
+    ItemNotFound(i32),
+    QuoteNotFound,
+    Expired(DateTime<Utc>),
+    /// The quote has already been decided on or converted.
+    AlreadyDecided(QuoteStatus),
+}
+
+impl QuoteDetails {
+    /// Get a quote by its ID, or [`None`] if there is no such quote.
+    pub async fn query_by_id(database: &Database, id: i32) -> Option<Self> {
+        let quote = sqlx::query_as("SELECT * FROM main.quotes WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()?;
+        Self::with_details(database, vec![quote]).await.pop()
+    }
+
+    /// Get every quote, or only those of a customer, newest first.
+    pub async fn query_all(database: &Database, customer: Option<i32>) -> Vec<Self> {
+        let quotes = sqlx::query_as(
+            "SELECT * FROM main.quotes WHERE $1::integer IS NULL OR customer = $1 \
+             ORDER BY created_at DESC, id DESC",
+        )
+        .bind(customer)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+        Self::with_details(database, quotes).await
+    }
+
+    async fn with_details(database: &Database, quotes: Vec<QuotesTableRecord>) -> Vec<Self> {
+        let ids: Vec<i32> = quotes.iter().map(|quote| quote.id).collect();
+
+        let mut devices: HashMap<i32, Vec<QuoteDevicesJunctionTableRecord>> = HashMap::new();
+        let device_records: Vec<QuoteDevicesJunctionTableRecord> = sqlx::query_as(
+            "SELECT * FROM main.quote_devices WHERE quote = ANY($1) ORDER BY quote, device",
+        )
+        .bind(&ids)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+        for device in device_records {
+            devices.entry(device.quote).or_default().push(device);
+        }
+
+        let mut lines: HashMap<i32, Vec<QuoteLine>> = HashMap::new();
+        let line_records: Vec<QuoteLine> = sqlx::query_as(
+            "SELECT \
+                 quote_item.quote, quote_item.item, item.item_type, \
+                 COALESCE( \
+                     item.product_name, \
+                     item.service_type_name || ' (' || item.service_device_name || ')' \
+                 ) AS description, \
+                 quote_item.unit_price \
+             FROM main.quote_items quote_item \
+                 JOIN main.items_view item ON quote_item.item = item.item_id \
+             WHERE quote_item.quote = ANY($1) \
+             ORDER BY quote_item.quote, item.item_type DESC, quote_item.item",
+        )
+        .bind(&ids)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+        for line in line_records {
+            lines.entry(line.quote).or_default().push(line);
+        }
+
+        let now = Utc::now();
+        quotes
+            .into_iter()
+            .map(|quote| {
+                let items = lines.remove(&quote.id).unwrap_or_default();
+                Self {
+                    expired: quote.status == Some(QuoteStatus::Pending) && quote.expires_at <= now,
+                    devices: devices.remove(&quote.id).unwrap_or_default(),
+                    total: items.iter().map(|line| line.unit_price).sum(),
+                    items,
+                    quote,
+                }
+            })
+            .collect()
+    }
+
+    /// Quote a repair for a customer, pricing its items as of now.
+    ///
+    /// The items of the quote are the given items along with the service of each device.
+    pub async fn create(
+        database: &Database,
+        customer: i32,
+        devices: &[(i32, i32)],
+        items: &[i32],
+        expires_at: DateTime<Utc>,
+        notes: Option<&str>,
+    ) -> Result<Self, QuoteError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+
+        let customer_exists: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.customers WHERE id = $1)")
+                .bind(customer)
+                .fetch_one(&mut *transaction)
+                .await
+                .unwrap();
+        if !customer_exists {
+            return Err(QuoteError::CustomerNotFound);
+        }
+
+        for &(device, service) in devices {
+            let owner: Option<Option<i32>> =
+                sqlx::query_scalar("SELECT owner FROM main.devices WHERE id = $1")
+                    .bind(device)
+                    .fetch_optional(&mut *transaction)
+                    .await
+                    .unwrap();
+            match owner {
+                None => return Err(QuoteError::DeviceNotFound(device)),
+                Some(Some(owner)) if owner != customer => {
+                    return Err(QuoteError::DeviceNotOwned(device))
+                }
+                Some(_) => {}
+            }
+
+            let service_exists: bool =
+                sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.services WHERE id = $1)")
+                    .bind(service)
+                    .fetch_one(&mut *transaction)
+                    .await
+                    .unwrap();
+            if !service_exists {
+                return Err(QuoteError::ServiceNotFound(service));
+            }
+        }
+
+        let existing_items: Vec<i32> =
+            sqlx::query_scalar("SELECT id FROM main.items WHERE id = ANY($1)")
+                .bind(items)
+                .fetch_all(&mut *transaction)
+                .await
+                .unwrap();
+        if let Some(item) = items.iter().find(|item| !existing_items.contains(item)) {
+            return Err(QuoteError::ItemNotFound(*item));
+        }
+
+        let id: i32 = sqlx::query_scalar(
+            "INSERT INTO main.quotes (customer, expires_at, notes) VALUES ($1, $2, $3) \
+             RETURNING id",
+        )
+        .bind(customer)
+        .bind(expires_at)
+        .bind(notes)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        let (device_ids, services): (Vec<i32>, Vec<i32>) = devices.iter().copied().unzip();
+        sqlx::query(
+            "INSERT INTO main.quote_devices (quote, device, service) \
+             SELECT $1, device, service FROM UNNEST($2::integer[], $3::integer[]) AS t (device, service) \
+             ON CONFLICT (quote, device) DO UPDATE SET service = EXCLUDED.service",
+        )
+        .bind(id)
+        .bind(&device_ids)
+        .bind(&services)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            "INSERT INTO main.quote_items (quote, item, unit_price) \
+             SELECT $1, item.id, main.get_item_price_at_time(item.id, CURRENT_TIMESTAMP) \
+             FROM main.items item \
+             WHERE item.id = ANY($2) \
+                 OR (item.type = 'service' AND item.product_or_service = ANY($3))",
+        )
+        .bind(id)
+        .bind(items)
+        .bind(&services)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Ok(Self::query_by_id(database, id).await.unwrap())
+    }
+
+    /// Record whether the customer approved or declined a quote which is still pending.
+    pub async fn decide(database: &Database, id: i32, approved: bool) -> Result<Self, QuoteError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+        let quote = lock_quote(&mut transaction, id).await?;
+        if quote.status != Some(QuoteStatus::Pending) {
+            return Err(QuoteError::AlreadyDecided(quote.status.unwrap()));
+        }
+        if quote.expires_at <= Utc::now() {
+            return Err(QuoteError::Expired(quote.expires_at));
+        }
+
+        sqlx::query(
+            "UPDATE main.quotes SET status = $2, decided_at = CURRENT_TIMESTAMP WHERE id = $1",
+        )
+        .bind(id)
+        .bind(match approved {
+            true => QuoteStatus::Approved,
+            false => QuoteStatus::Declined,
+        })
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Ok(Self::query_by_id(database, id).await.unwrap())
+    }
+
+    /// Turn a quote into a ticket for its devices and an invoice for its items at a store, charging
+    /// the items at the prices they were quoted at.
+    ///
+    /// Pending quotes are approved by converting them, as long as they have not expired.
+    pub async fn convert(database: &Database, id: i32, location: i32) -> Result<Self, QuoteError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+        let quote = lock_quote(&mut transaction, id).await?;
+        match quote.status.unwrap() {
+            QuoteStatus::Approved => {}
+            QuoteStatus::Pending if quote.expires_at <= Utc::now() => {
+                return Err(QuoteError::Expired(quote.expires_at))
+            }
+            QuoteStatus::Pending => {}
+            status => return Err(QuoteError::AlreadyDecided(status)),
+        }
+
+        let invoice: i32 =
+            sqlx::query_scalar("INSERT INTO main.invoices (location) VALUES ($1) RETURNING id")
+                .bind(location)
+                .fetch_one(&mut *transaction)
+                .await
+                .unwrap();
+
+        let ticket: i32 = sqlx::query_scalar(
+            "INSERT INTO main.tickets (customer, invoice, description, notes, location) \
+             SELECT quote.customer, $2, \
+                 COALESCE( \
+                     ( \
+                         SELECT string_agg(DISTINCT service_type.display_name, ', ') \
+                         FROM main.quote_devices quote_device \
+                             JOIN main.services service ON quote_device.service = service.id \
+                             JOIN main.service_types service_type \
+                                 ON service.type = service_type.id \
+                         WHERE quote_device.quote = quote.id \
+                     ), \
+                     'Quote ' || quote.id \
+                 ), \
+                 CASE WHEN quote.notes IS NULL THEN '{}' ELSE ARRAY[quote.notes] END, \
+                 $3 \
+             FROM main.quotes quote WHERE quote.id = $1 \
+             RETURNING id",
+        )
+        .bind(id)
+        .bind(invoice)
+        .bind(location)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            "INSERT INTO main.ticket_devices (ticket, device, service) \
+             SELECT $2, device, service FROM main.quote_devices WHERE quote = $1",
+        )
+        .bind(id)
+        .bind(ticket)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            "INSERT INTO main.invoice_items (invoice, item, unit_price) \
+             SELECT $2, item, unit_price FROM main.quote_items WHERE quote = $1",
+        )
+        .bind(id)
+        .bind(invoice)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            "UPDATE main.quotes SET status = 'converted', ticket = $2, \
+                 decided_at = COALESCE(decided_at, CURRENT_TIMESTAMP) \
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(ticket)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Ok(Self::query_by_id(database, id).await.unwrap())
+    }
+}
+
+/// Get a quote, locking it until the end of the transaction so its status cannot change underneath
+/// it.
+async fn lock_quote(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: i32,
+) -> Result<QuotesTableRecord, QuoteError> {
+    sqlx::query_as("SELECT * FROM main.quotes WHERE id = $1 FOR UPDATE")
+        .bind(id)
+        .fetch_optional(&mut **transaction)
+        .await
+        .unwrap()
+        .ok_or(QuoteError::QuoteNotFound)
+}
@@ -0,0 +1,259 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::shared_models::{CashMovementType, PaymentType};
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "register_sessions", primary_key = "id")]
+pub struct RegisterSessionsTable {
+    records: Vec<RegisterSessionsTableRecord>,
+}
+
+/// A shift of the cash drawer, from when it was opened with a float to when its cash was counted.
+///
+/// Sessions that are still open have no `closed_at` or `counted_amount`.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct RegisterSessionsTableRecord {
+    pub id: i32,
+    pub opened_by: Option<i32>,
+    #[defaultable]
+    pub opened_at: Option<DateTime<Utc>>,
+    pub opening_float: Decimal,
+    pub closed_by: Option<i32>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub counted_amount: Option<Decimal>,
+    pub notes: Option<String>,
+    /// The store whose drawer the session is for.
+    #[defaultable]
+    pub location: Option<i32>,
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "cash_movements", primary_key = "id")]
+pub struct CashMovementsTable {
+    records: Vec<CashMovementsTableRecord>,
+}
+
+/// Cash put into or taken out of the drawer during a session other than for invoices.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct CashMovementsTableRecord {
+    pub id: i32,
+    pub session: i32,
+    #[sqlx(rename = "type")]
+    pub r#type: CashMovementType,
+    pub amount: Decimal,
+    pub reason: String,
+    pub recorded_by: Option<i32>,
+    #[defaultable]
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+/// The payments of one type taken while a session was open.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct PaymentTotal {
+    #[sqlx(rename = "type")]
+    pub r#type: PaymentType,
+    pub count: i32,
+    pub total: Decimal,
+}
+
+/// The takings of a session and how the cash in the drawer compares to what should be there.
+///
+/// Closed sessions are reported as they were counted, while open sessions are reported as of now
+/// and have no variance.
+#[derive(Clone, Serialize, ApiSchema)]
+pub struct ZReport {
+    #[serde(flatten)]
+    pub session: RegisterSessionsTableRecord,
+    pub payments: Vec<PaymentTotal>,
+    pub cash_payments: Decimal,
+    pub paid_in: Decimal,
+    pub paid_out: Decimal,
+    pub movements: Vec<CashMovementsTableRecord>,
+    /// The opening float plus cash payments and paid-in entries, less paid-out entries.
+    pub expected_cash: Decimal,
+    /// How much more cash was counted than expected, which is negative if the drawer was short.
+    pub variance: Option<Decimal>,
+}
+
+impl RegisterSessionsTableRecord {
+    /// Get the session which is currently open at a store, if any.
+    pub async fn query_open(database: &Database, location: i32) -> Option<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.register_sessions WHERE location = $1 AND closed_at IS NULL",
+        )
+        .bind(location)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Get a session by its ID, or [`None`] if there is no such session.
+    pub async fn query_by_id(database: &Database, id: i32) -> Option<Self> {
+        sqlx::query_as("SELECT * FROM main.register_sessions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Get every session, newest first, optionally only those of a store.
+    pub async fn query_all(database: &Database, location: Option<i32>) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.register_sessions \
+             WHERE $1::integer IS NULL OR location = $1 \
+             ORDER BY opened_at DESC, id DESC",
+        )
+        .bind(location)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Open the drawer of a store with a float, returning [`None`] if it is already open.
+    pub async fn open(
+        database: &Database,
+        location: i32,
+        user: i32,
+        opening_float: Decimal,
+        notes: Option<&str>,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "INSERT INTO main.register_sessions (location, opened_by, opening_float, notes) \
+             VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (location) WHERE closed_at IS NULL DO NOTHING \
+             RETURNING *",
+        )
+        .bind(location)
+        .bind(user)
+        .bind(opening_float)
+        .bind(notes)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Close the open session of a store with the cash counted in the drawer, returning [`None`]
+    /// if no session is open. Notes given on closing are added to those given on opening.
+    pub async fn close(
+        database: &Database,
+        location: i32,
+        user: i32,
+        counted_amount: Decimal,
+        notes: Option<&str>,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "UPDATE main.register_sessions \
+             SET closed_by = $2, closed_at = CURRENT_TIMESTAMP, counted_amount = $3, \
+                 notes = NULLIF(CONCAT_WS(E'\\n', notes, $4), '') \
+             WHERE location = $1 AND closed_at IS NULL \
+             RETURNING *",
+        )
+        .bind(location)
+        .bind(user)
+        .bind(counted_amount)
+        .bind(notes)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl CashMovementsTableRecord {
+    /// Record cash put into or taken out of the drawer of a store during its open session,
+    /// returning [`None`] if no session is open.
+    pub async fn record(
+        database: &Database,
+        location: i32,
+        r#type: CashMovementType,
+        amount: Decimal,
+        reason: &str,
+        user: i32,
+    ) -> Option<Self> {
+        // * The session is locked so it cannot be closed, and its report printed, before the
+        // * movement is recorded against it.
+        sqlx::query_as(
+            "INSERT INTO main.cash_movements (session, type, amount, reason, recorded_by) \
+             SELECT id, $2, $3, $4, $5 FROM main.register_sessions \
+             WHERE location = $1 AND closed_at IS NULL FOR SHARE \
+             RETURNING *",
+        )
+        .bind(location)
+        .bind(r#type)
+        .bind(amount)
+        .bind(reason)
+        .bind(user)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl ZReport {
+    /// Report the takings of a session from the payments of invoices of its store recorded while
+    /// it was open.
+    pub async fn query(database: &Database, session: RegisterSessionsTableRecord) -> Self {
+        let payments: Vec<PaymentTotal> = sqlx::query_as(
+            "SELECT payment.type, COUNT(*)::integer AS count, SUM(payment.amount) AS total \
+             FROM main.invoice_payments payment \
+                 JOIN main.invoices invoice ON payment.invoice = invoice.id \
+             WHERE invoice.location = $1 AND payment.timestamp >= $2 \
+                 AND ($3::timestamptz IS NULL OR payment.timestamp < $3) \
+             GROUP BY payment.type \
+             ORDER BY payment.type",
+        )
+        .bind(session.location)
+        .bind(session.opened_at)
+        .bind(session.closed_at)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+
+        let movements: Vec<CashMovementsTableRecord> = sqlx::query_as(
+            "SELECT * FROM main.cash_movements WHERE session = $1 ORDER BY recorded_at, id",
+        )
+        .bind(session.id)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap();
+
+        let cash_payments = payments
+            .iter()
+            .filter(|payment| payment.r#type == PaymentType::Cash)
+            .map(|payment| payment.total)
+            .sum();
+        let total_of = |r#type| {
+            movements
+                .iter()
+                .filter(|movement| movement.r#type == r#type)
+                .map(|movement| movement.amount)
+                .sum()
+        };
+        let paid_in: Decimal = total_of(CashMovementType::PaidIn);
+        let paid_out: Decimal = total_of(CashMovementType::PaidOut);
+        let expected_cash = session.opening_float + cash_payments + paid_in - paid_out;
+
+        Self {
+            variance: session
+                .counted_amount
+                .map(|counted| counted - expected_cash),
+            payments,
+            cash_payments,
+            paid_in,
+            paid_out,
+            movements,
+            expected_cash,
+            session,
+        }
+    }
+}
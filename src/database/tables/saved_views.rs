@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::api::endpoints::FilterOperator;
+use crate::database::shared_models::UserRole;
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "saved_views", primary_key = "id")]
+pub struct SavedViewsTable {
+    records: Vec<SavedViewsTableRecord>,
+}
+
+/// A saved layout of a resource's table in the frontend.
+///
+/// A view is owned either by a single user (`user_id`) or by every user with a role (`role`), in
+/// which case it is that role's default view of the resource.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct SavedViewsTableRecord {
+    pub id: i32,
+    pub user_id: Option<i32>,
+    pub role: Option<UserRole>,
+    pub resource: String,
+    pub name: String,
+    pub layout: Json<SavedViewLayout>,
+    #[defaultable]
+    pub created_at: Option<DateTime<Utc>>,
+    #[defaultable]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// The visible columns of a view in display order, along with how its rows are sorted and filtered.
+#[derive(Clone, Serialize, Deserialize, ApiSchema)]
+pub struct SavedViewLayout {
+    pub columns: Vec<SavedViewColumn>,
+    pub sort: Option<SavedViewSort>,
+    pub filters: Vec<SavedViewFilter>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ApiSchema)]
+pub struct SavedViewColumn {
+    pub key: String,
+    /// The width of the column in pixels, or [`None`] to size it automatically.
+    pub width: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ApiSchema)]
+pub struct SavedViewSort {
+    pub column: String,
+    pub descending: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, ApiSchema)]
+pub struct SavedViewFilter {
+    pub column: String,
+    pub operator: FilterOperator,
+    /// The value to compare against, which is an array of two values for
+    /// [`FilterOperator::Between`] and an array of tag names for [`FilterOperator::OneOf`].
+    pub value: Value,
+}
+
+impl SavedViewsTableRecord {
+    /// Get every view of a resource belonging to a user, ordered by name.
+    pub async fn query_for_user(database: &Database, user_id: i32, resource: &str) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.saved_views WHERE user_id = $1 AND resource = $2 ORDER BY name, id",
+        )
+        .bind(user_id)
+        .bind(resource)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Get the default view of a resource for a role, if one has been set.
+    pub async fn query_role_default(
+        database: &Database,
+        role: UserRole,
+        resource: &str,
+    ) -> Option<Self> {
+        sqlx::query_as("SELECT * FROM main.saved_views WHERE role = $1 AND resource = $2")
+            .bind(role)
+            .bind(resource)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Create a new view belonging to a user.
+    pub async fn create(
+        database: &Database,
+        user_id: i32,
+        resource: &str,
+        name: &str,
+        layout: SavedViewLayout,
+    ) -> Self {
+        sqlx::query_as(
+            "INSERT INTO main.saved_views (user_id, resource, name, layout) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(user_id)
+        .bind(resource)
+        .bind(name)
+        .bind(Json(layout))
+        .fetch_one(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Replace the resource, name and layout of a view belonging to a user.
+    ///
+    /// Returns [`None`] if the view does not exist or belongs to someone else.
+    pub async fn update(
+        database: &Database,
+        id: i32,
+        user_id: i32,
+        resource: &str,
+        name: &str,
+        layout: SavedViewLayout,
+    ) -> Option<Self> {
+        sqlx::query_as(
+            "UPDATE main.saved_views SET resource = $3, name = $4, layout = $5, updated_at = CURRENT_TIMESTAMP \
+             WHERE id = $1 AND user_id = $2 RETURNING *",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(resource)
+        .bind(name)
+        .bind(Json(layout))
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Delete a view belonging to a user, returning whether it existed.
+    pub async fn delete_owned(database: &Database, id: i32, user_id: i32) -> bool {
+        sqlx::query("DELETE FROM main.saved_views WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&database.connection)
+            .await
+            .unwrap()
+            .rows_affected()
+            > 0
+    }
+
+    /// Set the default view of a resource for a role, replacing any existing default.
+    pub async fn set_role_default(
+        database: &Database,
+        role: UserRole,
+        resource: &str,
+        name: &str,
+        layout: SavedViewLayout,
+    ) -> Self {
+        sqlx::query_as(
+            "INSERT INTO main.saved_views (role, resource, name, layout) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (role, resource) WHERE role IS NOT NULL \
+             DO UPDATE SET name = EXCLUDED.name, layout = EXCLUDED.layout, updated_at = CURRENT_TIMESTAMP \
+             RETURNING *",
+        )
+        .bind(role)
+        .bind(resource)
+        .bind(name)
+        .bind(Json(layout))
+        .fetch_one(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Remove the default view of a resource for a role, returning whether one was set.
+    pub async fn delete_role_default(database: &Database, role: UserRole, resource: &str) -> bool {
+        sqlx::query("DELETE FROM main.saved_views WHERE role = $1 AND resource = $2")
+            .bind(role)
+            .bind(resource)
+            .execute(&database.connection)
+            .await
+            .unwrap()
+            .rows_affected()
+            > 0
+    }
+}
@@ -1,22 +1,25 @@
 use std::collections::HashSet;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::generators::*;
 use super::services::ServicesTable;
 use super::IdentifiableRecord;
 use crate::database::{GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "service_prices", primary_key = "id")]
 pub struct ServicePricesTable {
     records: Vec<ServicePricesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct ServicePricesTableRecord {
     pub id: i32,
     pub service: i32,
@@ -25,7 +28,7 @@ pub struct ServicePricesTableRecord {
     #[defaultable]
     pub labor_fee: Option<Decimal>,
     #[defaultable]
-    pub time_set: Option<NaiveDateTime>,
+    pub time_set: Option<DateTime<Utc>>,
 }
 
 impl GenerateRecord for ServicePricesTableRecord {
@@ -35,16 +38,17 @@ impl GenerateRecord for ServicePricesTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let base_fee = generate_dollar_value(Some(1.00), Some(500.00));
-        let labor_fee = generate_dollar_value(Some(1.00), Some(500.00));
+        let base_fee = generate_dollar_value(Some(1.00), Some(500.00), rng);
+        let labor_fee = generate_dollar_value(Some(1.00), Some(500.00), rng);
 
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            service: dependencies.pick_random().id(),
+            id: generate_sequential_i32(existing_ids),
+            service: dependencies.pick_random(rng).id(),
             base_fee: Some(base_fee),
             labor_fee: Some(labor_fee),
-            time_set: Some(generate_date(None)),
+            time_set: Some(generate_date(None, rng)),
         }
     }
 }
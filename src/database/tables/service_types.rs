@@ -1,17 +1,32 @@
-use proc_macros::{BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
 
-use crate::database::{GenerateStaticRecord, GenerateStaticTable};
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
-#[derive(Relation, Table, BulkInsert, Clone)]
+use crate::database::{Database, GenerateStaticRecord, GenerateStaticTable};
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
 #[relation(relation_name = "service_types", primary_key = "id")]
 pub struct ServiceTypesTable {
     records: Vec<ServiceTypesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
 pub struct ServiceTypesTableRecord {
     pub id: i32,
     pub display_name: String,
+    /// How long appointments for this type of service take.
+    #[defaultable]
+    #[api_schema(value_type = i32)]
+    pub duration_minutes: Option<i32>,
+    /// How many days repairs of this type of service are guaranteed for, unless the service
+    /// overrides it.
+    #[defaultable]
+    #[api_schema(value_type = i32)]
+    pub warranty_days: Option<i32>,
 }
 
 impl GenerateStaticTable for ServiceTypesTable {
@@ -30,6 +45,43 @@ impl GenerateStaticRecord for ServiceTypesTableRecord {
         Self {
             id,
             display_name: display_name.into(),
+            duration_minutes: None,
+            warranty_days: None,
         }
     }
 }
+
+impl ServiceTypesTableRecord {
+    /// Get the service types with the given IDs, ignoring any IDs which do not exist.
+    pub async fn query_by_ids(database: &Database, ids: &[i32]) -> Vec<Self> {
+        sqlx::query_as("SELECT * FROM main.service_types WHERE id = ANY($1) ORDER BY id")
+            .bind(ids)
+            .fetch_all(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Change how long appointments for a type of service take, returning [`None`] if there is no
+    /// such service type.
+    pub async fn set_duration(database: &Database, id: i32, minutes: i32) -> Option<Self> {
+        sqlx::query_as(
+            "UPDATE main.service_types SET duration_minutes = $2 WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .bind(minutes)
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Change how many days repairs of a type of service are guaranteed for, returning [`None`] if
+    /// there is no such service type.
+    pub async fn set_warranty(database: &Database, id: i32, days: i32) -> Option<Self> {
+        sqlx::query_as("UPDATE main.service_types SET warranty_days = $2 WHERE id = $1 RETURNING *")
+            .bind(id)
+            .bind(days)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+}
@@ -1,25 +1,35 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert,
+    Table,
+};
 
 use super::device_models::DeviceModelsTable;
 use super::generators::*;
 use super::service_types::ServiceTypesTable;
 use super::IdentifiableRecord;
-use crate::database::{GenerateRecord, Relation};
+use crate::database::{Database, GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "services", primary_key = "id")]
 pub struct ServicesTable {
     records: Vec<ServicesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
 pub struct ServicesTableRecord {
     pub id: i32,
     #[sqlx(rename = "type")]
     pub r#type: i32,
     pub device: i32,
+    /// How many days repairs with this service are guaranteed for, or [`None`] to use the warranty
+    /// period of its type.
+    pub warranty_days: Option<i32>,
 }
 
 impl GenerateRecord for ServicesTableRecord {
@@ -29,11 +39,26 @@ impl GenerateRecord for ServicesTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            r#type: dependencies.0.pick_random().id(),
-            device: dependencies.1.pick_random().id(),
+            id: generate_sequential_i32(existing_ids),
+            r#type: dependencies.0.pick_random(rng).id(),
+            device: dependencies.1.pick_random(rng).id(),
+            warranty_days: None,
         }
     }
 }
+
+impl ServicesTableRecord {
+    /// Change how many days repairs with a service are guaranteed for, or make it use the warranty
+    /// period of its type again. Returns [`None`] if there is no such service.
+    pub async fn set_warranty(database: &Database, id: i32, days: Option<i32>) -> Option<Self> {
+        sqlx::query_as("UPDATE main.services SET warranty_days = $2 WHERE id = $1 RETURNING *")
+            .bind(id)
+            .bind(days)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+}
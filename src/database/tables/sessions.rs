@@ -0,0 +1,78 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::database::tables::users::UsersTableRecord;
+use crate::database::Database;
+
+/// The number of random bytes in a session token.
+const TOKEN_LENGTH: usize = 32;
+
+/// A session a user has signed in to the API with.
+///
+/// Sessions are not included in backups, since a restored database should not let anyone in
+/// without signing in again.
+#[derive(sqlx::FromRow, Clone)]
+#[allow(dead_code)]
+pub struct SessionsTableRecord {
+    pub token_hash: String,
+    pub user_id: i32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SessionsTableRecord {
+    /// Start a session for a user which lasts for `lifetime`, returning it along with the token
+    /// that authenticates it. Sessions which have expired are removed at the same time.
+    pub async fn create(database: &Database, user_id: i32, lifetime: Duration) -> (Self, String) {
+        let mut bytes = [0; TOKEN_LENGTH];
+        thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+
+        sqlx::query("DELETE FROM main.sessions WHERE expires_at <= CURRENT_TIMESTAMP")
+            .execute(&database.connection)
+            .await
+            .unwrap();
+        let session = sqlx::query_as(
+            "INSERT INTO main.sessions (token_hash, user_id, expires_at) VALUES ($1, $2, $3) \
+             RETURNING *",
+        )
+        .bind(hash_token(&token))
+        .bind(user_id)
+        .bind(Utc::now() + lifetime)
+        .fetch_one(&database.connection)
+        .await
+        .unwrap();
+
+        (session, token)
+    }
+
+    /// Get the user a session token was issued to, or [`None`] if there is no such session or it
+    /// has expired.
+    pub async fn query_user(database: &Database, token: &str) -> Option<UsersTableRecord> {
+        sqlx::query_as(
+            "SELECT users.* FROM main.sessions sessions \
+                 JOIN main.users users ON sessions.user_id = users.id \
+             WHERE sessions.token_hash = $1 AND sessions.expires_at > CURRENT_TIMESTAMP",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// End the session a token authenticates, returning whether there was one.
+    pub async fn delete(database: &Database, token: &str) -> bool {
+        sqlx::query("DELETE FROM main.sessions WHERE token_hash = $1")
+            .bind(hash_token(token))
+            .execute(&database.connection)
+            .await
+            .unwrap()
+            .rows_affected()
+            > 0
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
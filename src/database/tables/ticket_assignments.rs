@@ -0,0 +1,143 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::shared_models::TicketStatus;
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "ticket_assignments", primary_key = "id")]
+pub struct TicketAssignmentsTable {
+    records: Vec<TicketAssignmentsTableRecord>,
+}
+
+/// A single assignment of a ticket, or of one of its devices, to a technician.
+///
+/// The current assignee is stored on the ticket or device itself, so these records are only the
+/// history of who was assigned what and by whom. An `assignee` of [`None`] records the work being
+/// unassigned.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct TicketAssignmentsTableRecord {
+    pub id: i32,
+    pub ticket: i32,
+    /// The device of the ticket that was assigned, or [`None`] if the whole ticket was.
+    pub device: Option<i32>,
+    pub assignee: Option<i32>,
+    pub assigned_by: Option<i32>,
+    #[defaultable]
+    pub assigned_at: Option<DateTime<Utc>>,
+}
+
+/// A piece of open work in a technician's queue, which is either a whole ticket or a single device
+/// of a ticket.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct QueueItem {
+    #[serde(skip)]
+    pub technician: i32,
+    pub ticket: i32,
+    pub device: Option<i32>,
+    pub status: TicketStatus,
+    pub customer: Option<String>,
+    /// The description of the ticket, or the diagnostic of the device.
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TicketAssignmentsTableRecord {
+    /// Assign a ticket (or one of its devices, if `device` is given) to a technician, or unassign it
+    /// if `assignee` is [`None`], and record the change in the assignment history.
+    ///
+    /// Returns [`None`] if the ticket does not exist or does not have the device.
+    pub async fn assign(
+        database: &Database,
+        ticket: i32,
+        device: Option<i32>,
+        assignee: Option<i32>,
+        assigned_by: i32,
+    ) -> Option<Self> {
+        let mut transaction = database.connection.begin().await.unwrap();
+
+        let updated = match device {
+            None => sqlx::query(
+                "UPDATE main.tickets SET assignee = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $1",
+            )
+            .bind(ticket)
+            .bind(assignee),
+            Some(device) => sqlx::query(
+                "UPDATE main.ticket_devices SET assignee = $3 WHERE ticket = $1 AND device = $2",
+            )
+            .bind(ticket)
+            .bind(device)
+            .bind(assignee),
+        }
+        .execute(&mut *transaction)
+        .await
+        .unwrap()
+        .rows_affected();
+
+        if updated == 0 {
+            return None;
+        }
+
+        let record = sqlx::query_as(
+            "INSERT INTO main.ticket_assignments (ticket, device, assignee, assigned_by) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+        .bind(ticket)
+        .bind(device)
+        .bind(assignee)
+        .bind(assigned_by)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Some(record)
+    }
+
+    /// Get the assignment history of a ticket and its devices, oldest first.
+    pub async fn query_for_ticket(database: &Database, ticket: i32) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.ticket_assignments WHERE ticket = $1 ORDER BY assigned_at, id",
+        )
+        .bind(ticket)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl QueueItem {
+    /// Get the open work assigned to every technician, or only to one if `technician` is given.
+    ///
+    /// Work is ordered oldest first, with tickets of the same age ordered by how far along their
+    /// status is. Closed tickets are not included.
+    pub async fn query_open(database: &Database, technician: Option<i32>) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT * FROM ( \
+                 SELECT ticket.assignee AS technician, ticket.id AS ticket, NULL::integer AS device, \
+                     ticket.status, customer.name AS customer, ticket.description, ticket.created_at \
+                 FROM main.tickets ticket \
+                     LEFT JOIN main.customers customer ON ticket.customer = customer.id \
+                 WHERE ticket.assignee IS NOT NULL \
+                 UNION ALL \
+                 SELECT ticket_device.assignee, ticket.id, ticket_device.device, \
+                     ticket.status, customer.name, ticket_device.diagnostic, ticket.created_at \
+                 FROM main.ticket_devices ticket_device \
+                     JOIN main.tickets ticket ON ticket_device.ticket = ticket.id \
+                     LEFT JOIN main.customers customer ON ticket.customer = customer.id \
+                 WHERE ticket_device.assignee IS NOT NULL \
+             ) work \
+             WHERE status <> 'closed' AND ($1::integer IS NULL OR technician = $1) \
+             ORDER BY created_at, status, ticket, device NULLS FIRST",
+        )
+        .bind(technician)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
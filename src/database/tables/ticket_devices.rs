@@ -1,53 +1,108 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, Relation, SingleInsert, Table};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, GenerateTable, Relation, SingleInsert, Table,
+};
 
 use super::devices::DevicesTable;
 use super::generators::*;
 use super::services::ServicesTable;
 use super::tickets::TicketsTable;
 use super::IdentifiableRecord;
-use crate::database::{GenerateRecord, Relation};
+use crate::database::{Database, GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
-#[relation(relation_name = "ticket_devices", primary_key = "(ticket, device)")]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
+#[relation(
+    relation_name = "ticket_devices",
+    primary_key = "(ticket, device)",
+    serial_primary_key = false
+)]
 pub struct TicketDevicesJunctionTable {
     records: Vec<TicketDevicesJunctionTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize)]
 pub struct TicketDevicesJunctionTableRecord {
     pub ticket: i32,
     pub device: i32,
     pub service: i32,
     pub diagnostic: Option<String>,
+    /// The technician repairing this device, if it is assigned separately from its ticket.
+    pub assignee: Option<i32>,
+}
+
+/// The model of a device on a ticket and the service it is getting, without anything that
+/// identifies the device or its owner.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct TicketDeviceSummary {
+    pub model: String,
+    pub service: Option<String>,
 }
 
 impl GenerateRecord for TicketDevicesJunctionTableRecord {
     type Identifier = (i32, i32);
-    type Dependencies<'a> = (&'a TicketsTable, &'a DevicesTable, &'a ServicesTable);
+    type Dependencies<'a> = (
+        &'a TicketsTable,
+        &'a DevicesTable,
+        &'a ServicesTable,
+        &'a GroupedIds<i32>,
+    );
 
     fn generate(
         _existing_records: &[Self],
         existing_pairs: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let mut ticket = 0;
-        let mut device = 0;
-        let mut first_roll = true;
-        while first_roll || existing_pairs.get(&(ticket, device)).is_some() {
-            ticket = dependencies.0.pick_random().id();
-            device = dependencies.1.pick_random().id();
-            first_roll = false;
-        }
+        let (tickets, devices, services, services_by_model) = dependencies;
+
+        let (ticket, device) = loop {
+            let ticket = tickets.pick_random(rng).id();
+            let device = devices.pick_random(rng);
+            if existing_pairs.get(&(ticket, device.id())).is_none() {
+                break (ticket, device);
+            }
+        };
+
+        // * Prefer a service offered for the device's model over an arbitrary one.
+        let model_services = services_by_model.get(&device.model);
+        let service = match model_services.is_empty() {
+            true => services.pick_random(rng).id(),
+            false => model_services[rng.gen_range(0..model_services.len())],
+        };
+        let device = device.id();
 
         existing_pairs.insert((ticket, device));
 
         Self {
             ticket,
             device,
-            service: dependencies.2.pick_random().id(),
-            diagnostic: generate_option(generate_diagnostic(), 0.6),
+            service,
+            diagnostic: generate_option(generate_diagnostic(rng), 0.6, rng),
+            assignee: None,
         }
     }
 }
+
+impl TicketDeviceSummary {
+    /// Get a summary of every device on a ticket.
+    pub async fn query_for_ticket(database: &Database, ticket: i32) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT device_model.display_name AS model, service_type.display_name AS service \
+             FROM main.ticket_devices ticket_device \
+                 JOIN main.devices device ON ticket_device.device = device.id \
+                 JOIN main.device_models device_model ON device.model = device_model.id \
+                 LEFT JOIN main.services service ON ticket_device.service = service.id \
+                 LEFT JOIN main.service_types service_type ON service.type = service_type.id \
+             WHERE ticket_device.ticket = $1 \
+             ORDER BY ticket_device.device",
+        )
+        .bind(ticket)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
@@ -1,58 +1,142 @@
 use std::collections::HashSet;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::customers::CustomersTable;
 use super::generators::*;
-use super::invoices::InvoicesTable;
+use super::invoices::{InvoiceLedger, InvoicesTable};
+use super::locations::LocationsTable;
 use super::IdentifiableRecord;
 use crate::database::shared_models::TicketStatus;
-use crate::database::{GenerateRecord, Relation};
+use crate::database::{Database, GenerateRecord, Relation};
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "tickets", primary_key = "id")]
 pub struct TicketsTable {
     records: Vec<TicketsTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct TicketsTableRecord {
     pub id: i32,
     #[defaultable]
     pub status: Option<TicketStatus>,
     pub customer: Option<i32>,
     pub invoice: Option<i32>,
+    /// The technician responsible for the ticket as a whole.
+    pub assignee: Option<i32>,
     pub description: String,
     #[defaultable]
     pub notes: Option<Vec<String>>,
+    /// When the shop expects to finish the repair, as shown to the customer.
+    pub estimated_completion: Option<DateTime<Utc>>,
+    /// The store handling the ticket.
     #[defaultable]
-    pub created_at: Option<NaiveDateTime>,
+    pub location: Option<i32>,
     #[defaultable]
-    pub updated_at: Option<NaiveDateTime>,
+    pub created_at: Option<DateTime<Utc>>,
+    #[defaultable]
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
 impl GenerateRecord for TicketsTableRecord {
     type Identifier = i32;
-    type Dependencies<'a> = (&'a CustomersTable, &'a InvoicesTable);
+    type Dependencies<'a> = (
+        &'a CustomersTable,
+        &'a InvoicesTable,
+        &'a InvoiceLedger,
+        &'a LocationsTable,
+    );
+
     fn generate(
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
-        let created_at = generate_date(None);
-        let updated_at = generate_date(Some(created_at));
+        let (customers, invoices, ledger, locations) = dependencies;
+
+        let invoice = generate_option(invoices.pick_random(rng), 0.8, rng);
+        let (status, created_at, updated_at) = match &invoice {
+            Some(invoice) => {
+                // * The ticket is checked in shortly before its invoice is opened.
+                let invoice_created_at = invoice.created_at.unwrap();
+                let created_at = invoice_created_at - Duration::minutes(rng.gen_range(0..=120));
+
+                let balance = ledger.get_balance(invoice.id());
+                let fully_paid = balance.total > Decimal::ZERO && balance.paid >= balance.total;
+                let status =
+                    generate_ticket_status(true, balance.paid > Decimal::ZERO, fully_paid, rng);
+
+                // * Closed tickets were last touched when the customer paid and picked up.
+                let updated_at = match (status, balance.last_payment) {
+                    (TicketStatus::Closed, Some(last_payment)) => last_payment,
+                    (_, Some(last_payment)) => generate_date_within(last_payment, 7, rng),
+                    (_, None) => generate_date_within(invoice_created_at, 14, rng),
+                };
+
+                (status, created_at, updated_at)
+            }
+            None => {
+                let created_at = generate_date(None, rng);
+                let status = generate_ticket_status(false, false, false, rng);
+                (
+                    status,
+                    created_at,
+                    generate_date_within(created_at, 14, rng),
+                )
+            }
+        };
+
+        // * Tickets are invoiced at the store handling them.
+        let location = match &invoice {
+            Some(invoice) => invoice.location,
+            None => Some(locations.pick_random(rng).id()),
+        };
 
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            status: Some(generate_ticket_status()),
-            customer: generate_option(dependencies.0.pick_random().id(), 0.95),
-            invoice: generate_option(dependencies.1.pick_random().id(), 0.8),
-            description: generate_diagnostic(),
-            notes: None,
+            id: generate_sequential_i32(existing_ids),
+            status: Some(status),
+            customer: generate_option(customers.pick_random(rng).id(), 0.95, rng),
+            invoice: invoice.map(|i| i.id()),
+            // * Users are not generated, so there is nobody to assign tickets to.
+            assignee: None,
+            description: generate_diagnostic(rng),
+            notes: Some(generate_ticket_notes(status, rng)),
+            // * Open repairs are promised back within a week of being checked in.
+            estimated_completion: (status != TicketStatus::Closed)
+                .then(|| created_at + Duration::days(rng.gen_range(1..=7))),
+            location,
             created_at: Some(created_at),
             updated_at: Some(updated_at),
         }
     }
 }
+
+impl TicketsTableRecord {
+    /// Change when the shop expects to finish a ticket, returning whether the ticket exists.
+    pub async fn set_estimated_completion(
+        database: &Database,
+        id: i32,
+        estimated_completion: Option<DateTime<Utc>>,
+    ) -> bool {
+        sqlx::query(
+            "UPDATE main.tickets SET estimated_completion = $2, updated_at = CURRENT_TIMESTAMP \
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(estimated_completion)
+        .execute(&database.connection)
+        .await
+        .unwrap()
+        .rows_affected()
+            > 0
+    }
+}
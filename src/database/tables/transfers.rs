@@ -0,0 +1,241 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "transfers", primary_key = "id")]
+pub struct TransfersTable {
+    records: Vec<TransfersTableRecord>,
+}
+
+/// Parts or a device sent from one store to another.
+///
+/// Transfers that are still on their way have no `received_at`.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct TransfersTableRecord {
+    pub id: i32,
+    pub from_location: i32,
+    pub to_location: i32,
+    pub part: Option<i32>,
+    pub quantity: Option<i32>,
+    pub device: Option<i32>,
+    pub notes: Option<String>,
+    pub sent_by: Option<i32>,
+    #[defaultable]
+    pub sent_at: Option<DateTime<Utc>>,
+    pub received_by: Option<i32>,
+    pub received_at: Option<DateTime<Utc>>,
+}
+
+/// What a transfer sends, which is either a number of a part or a single device.
+pub enum TransferGoods {
+    Part { part: i32, quantity: i32 },
+    Device(i32),
+}
+
+/// Why a transfer could not be sent or received.
+pub enum TransferError {
+    LocationNotFound(i32),
+    PartNotFound(i32),
+    DeviceNotFound(i32),
+    /// The sending store has fewer of the part than were to be sent.
+    InsufficientStock {
+        available: i32,
+    },
+    /// The device is held by a different store, or by none.
+    DeviceNotAtLocation(Option<i32>),
+    TransferNotFound,
+    AlreadyReceived(DateTime<Utc>),
+}
+
+impl TransfersTableRecord {
+    /// Get transfers, newest first, optionally only those from or to a store and only those still
+    /// on their way.
+    pub async fn query(database: &Database, location: Option<i32>, pending: bool) -> Vec<Self> {
+        sqlx::query_as(
+            "SELECT * FROM main.transfers \
+             WHERE ($1::integer IS NULL OR $1 IN (from_location, to_location)) \
+                 AND (NOT $2 OR received_at IS NULL) \
+             ORDER BY sent_at DESC, id DESC",
+        )
+        .bind(location)
+        .bind(pending)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+
+    /// Send parts or a device from one store to another.
+    ///
+    /// Parts leave the stock of the sending store straight away, and a device is held by no store
+    /// until the transfer is received.
+    pub async fn send(
+        database: &Database,
+        from_location: i32,
+        to_location: i32,
+        goods: TransferGoods,
+        notes: Option<&str>,
+        user: i32,
+    ) -> Result<Self, TransferError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+        for location in [from_location, to_location] {
+            let exists: bool =
+                sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.locations WHERE id = $1)")
+                    .bind(location)
+                    .fetch_one(&mut *transaction)
+                    .await
+                    .unwrap();
+            if !exists {
+                return Err(TransferError::LocationNotFound(location));
+            }
+        }
+
+        let (part, quantity, device) = match goods {
+            TransferGoods::Part { part, quantity } => {
+                take_stock(&mut transaction, from_location, part, quantity).await?;
+                (Some(part), Some(quantity), None)
+            }
+            TransferGoods::Device(device) => {
+                let location: Option<Option<i32>> = sqlx::query_scalar(
+                    "SELECT location FROM main.devices WHERE id = $1 FOR UPDATE",
+                )
+                .bind(device)
+                .fetch_optional(&mut *transaction)
+                .await
+                .unwrap();
+                match location.ok_or(TransferError::DeviceNotFound(device))? {
+                    Some(location) if location == from_location => {}
+                    location => return Err(TransferError::DeviceNotAtLocation(location)),
+                }
+
+                sqlx::query("UPDATE main.devices SET location = NULL WHERE id = $1")
+                    .bind(device)
+                    .execute(&mut *transaction)
+                    .await
+                    .unwrap();
+                (None, None, Some(device))
+            }
+        };
+
+        let transfer = sqlx::query_as(
+            "INSERT INTO main.transfers \
+                 (from_location, to_location, part, quantity, device, notes, sent_by) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) \
+             RETURNING *",
+        )
+        .bind(from_location)
+        .bind(to_location)
+        .bind(part)
+        .bind(quantity)
+        .bind(device)
+        .bind(notes)
+        .bind(user)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Ok(transfer)
+    }
+
+    /// Receive a transfer at the store it was sent to, adding its parts to the stock of the store
+    /// or having the store hold its device.
+    pub async fn receive(database: &Database, id: i32, user: i32) -> Result<Self, TransferError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+        let transfer: Self =
+            sqlx::query_as("SELECT * FROM main.transfers WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut *transaction)
+                .await
+                .unwrap()
+                .ok_or(TransferError::TransferNotFound)?;
+        if let Some(received_at) = transfer.received_at {
+            return Err(TransferError::AlreadyReceived(received_at));
+        }
+
+        if let (Some(part), Some(quantity)) = (transfer.part, transfer.quantity) {
+            sqlx::query(
+                "INSERT INTO main.part_stock (location, part, quantity) VALUES ($1, $2, $3) \
+                 ON CONFLICT (location, part) \
+                 DO UPDATE SET quantity = part_stock.quantity + EXCLUDED.quantity",
+            )
+            .bind(transfer.to_location)
+            .bind(part)
+            .bind(quantity)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+        }
+        if let Some(device) = transfer.device {
+            sqlx::query("UPDATE main.devices SET location = $2 WHERE id = $1")
+                .bind(device)
+                .bind(transfer.to_location)
+                .execute(&mut *transaction)
+                .await
+                .unwrap();
+        }
+
+        let transfer = sqlx::query_as(
+            "UPDATE main.transfers SET received_by = $2, received_at = CURRENT_TIMESTAMP \
+             WHERE id = $1 \
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(user)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Ok(transfer)
+    }
+}
+
+/// Take parts out of the stock of a store, as long as it has enough of them.
+async fn take_stock(
+    transaction: &mut Transaction<'_, Postgres>,
+    location: i32,
+    part: i32,
+    quantity: i32,
+) -> Result<(), TransferError> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM main.parts WHERE id = $1)")
+        .bind(part)
+        .fetch_one(&mut **transaction)
+        .await
+        .unwrap();
+    if !exists {
+        return Err(TransferError::PartNotFound(part));
+    }
+
+    let available: Option<i32> = sqlx::query_scalar(
+        "SELECT quantity FROM main.part_stock WHERE location = $1 AND part = $2 FOR UPDATE",
+    )
+    .bind(location)
+    .bind(part)
+    .fetch_optional(&mut **transaction)
+    .await
+    .unwrap();
+    let available = available.unwrap_or(0);
+    if available < quantity {
+        return Err(TransferError::InsufficientStock { available });
+    }
+
+    sqlx::query(
+        "UPDATE main.part_stock SET quantity = quantity - $3 WHERE location = $1 AND part = $2",
+    )
+    .bind(location)
+    .bind(part)
+    .bind(quantity)
+    .execute(&mut **transaction)
+    .await
+    .unwrap();
+    Ok(())
+}
@@ -1,6 +1,8 @@
-use proc_macros::{BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
 
-#[derive(Relation, Table, BulkInsert, Clone)]
+use proc_macros::{BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
 #[relation(
     schema_name = "persistent",
     relation_name = "type_allocation_codes",
@@ -10,7 +12,7 @@ pub struct TypeAllocationCodesTable {
     records: Vec<TypeAllocationCodesTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Debug)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Debug, Serialize, Deserialize)]
 pub struct TypeAllocationCodesTableRecord {
     pub tac: i32,
     pub manufacturer: String,
@@ -0,0 +1,96 @@
+use chrono::{DateTime, Utc};
+use pbkdf2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use pbkdf2::Pbkdf2;
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table};
+
+use crate::database::shared_models::UserRole;
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "users", primary_key = "id")]
+pub struct UsersTable {
+    records: Vec<UsersTableRecord>,
+}
+
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
+pub struct UsersTableRecord {
+    pub id: i32,
+    pub username: String,
+    pub display_name: String,
+    pub password_hash: String,
+    pub role: UserRole,
+    #[defaultable]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl UsersTableRecord {
+    /// Get a user by their ID, or [`None`] if there is no such user.
+    pub async fn query_by_id(database: &Database, id: i32) -> Option<Self> {
+        sqlx::query_as("SELECT * FROM main.users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Get the user with a username if their password is correct, or [`None`] if there is no such
+    /// user or the password is wrong.
+    pub async fn authenticate(database: &Database, username: &str, password: &str) -> Option<Self> {
+        let user: Self = sqlx::query_as("SELECT * FROM main.users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()?;
+
+        // * Hashing is deliberately slow, so it is kept off the threads serving other requests.
+        let password = password.to_owned();
+        let password_hash = user.password_hash.clone();
+        let verified = tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&password_hash)
+                .is_ok_and(|hash| Pbkdf2.verify_password(password.as_bytes(), &hash).is_ok())
+        })
+        .await
+        .unwrap();
+
+        verified.then_some(user)
+    }
+
+    /// Get every user with a role, ordered by display name.
+    pub async fn query_by_role(database: &Database, role: UserRole) -> Vec<Self> {
+        sqlx::query_as("SELECT * FROM main.users WHERE role = $1 ORDER BY display_name, id")
+            .bind(role)
+            .fetch_all(&database.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Create a new user account with a freshly-hashed password, returning the ID of the new user.
+    ///
+    /// This fails if the username is already taken.
+    pub async fn create(
+        database: &Database,
+        username: &str,
+        display_name: &str,
+        password: &str,
+        role: UserRole,
+    ) -> Result<i32, sqlx::Error> {
+        let salt = SaltString::generate(&mut thread_rng());
+        let password_hash = Pbkdf2
+            .hash_password(password.as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+
+        sqlx::query_scalar(
+            "INSERT INTO main.users (username, display_name, password_hash, role) VALUES ($1, $2, $3, $4) RETURNING id",
+        )
+        .bind(username)
+        .bind(display_name)
+        .bind(password_hash)
+        .bind(role)
+        .fetch_one(&database.connection)
+        .await
+    }
+}
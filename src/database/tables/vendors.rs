@@ -1,17 +1,21 @@
 use std::collections::HashSet;
 
-use proc_macros::{BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table};
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    BackupTable, BulkInsert, GenerateTable, IdentifiableRecord, Relation, SingleInsert, Table,
+};
 
 use super::generators::*;
 use crate::database::GenerateRecord;
 
-#[derive(Relation, Table, BulkInsert, GenerateTable, Clone)]
+#[derive(Relation, Table, BulkInsert, BackupTable, GenerateTable, Clone)]
 #[relation(relation_name = "vendors", primary_key = "id")]
 pub struct VendorsTable {
     records: Vec<VendorsTableRecord>,
 }
 
-#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone)]
+#[derive(SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize)]
 pub struct VendorsTableRecord {
     pub id: i32,
     pub display_name: String,
@@ -27,13 +31,14 @@ impl GenerateRecord for VendorsTableRecord {
         _existing_records: &[Self],
         existing_ids: &mut HashSet<Self::Identifier>,
         _dependencies: Self::Dependencies<'_>,
+        rng: &mut GenerationRng,
     ) -> Self {
         Self {
-            id: generate_unique_i32(0, existing_ids),
-            display_name: generate_company_name(),
-            email_address: generate_option(generate_email_address(), 0.7),
-            phone_number: generate_option(generate_phone_number(), 0.5),
-            street_address: generate_option(generate_street_address(), 0.2),
+            id: generate_sequential_i32(existing_ids),
+            display_name: generate_company_name(rng),
+            email_address: generate_option(generate_email_address(rng), 0.7, rng),
+            phone_number: generate_option(generate_phone_number(rng), 0.5, rng),
+            street_address: generate_option(generate_street_address(rng), 0.2, rng),
         }
     }
 }
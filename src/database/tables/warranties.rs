@@ -0,0 +1,300 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use proc_macros::{
+    ApiSchema, BackupTable, BulkInsert, IdentifiableRecord, Relation, SingleInsert, Table,
+};
+
+use super::invoice_items::InvoiceItemsTableRecord;
+use crate::database::Database;
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(relation_name = "warranties", primary_key = "id")]
+pub struct WarrantiesTable {
+    records: Vec<WarrantiesTableRecord>,
+}
+
+/// The guarantee on a device repaired with a service, which starts when its ticket is closed.
+#[derive(
+    SingleInsert, sqlx::FromRow, IdentifiableRecord, Clone, Serialize, Deserialize, ApiSchema,
+)]
+pub struct WarrantiesTableRecord {
+    pub id: i32,
+    /// The ticket the device was repaired on.
+    pub ticket: i32,
+    pub device: i32,
+    pub service: i32,
+    pub customer: Option<i32>,
+    #[defaultable]
+    #[api_schema(value_type = DateTime<Utc>)]
+    pub starts_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Relation, Table, BulkInsert, BackupTable, Clone)]
+#[relation(
+    relation_name = "warranty_claims",
+    primary_key = "ticket",
+    serial_primary_key = false
+)]
+pub struct WarrantyClaimsTable {
+    records: Vec<WarrantyClaimsTableRecord>,
+}
+
+/// A ticket opened to repair a device again under one of its warranties.
+#[derive(SingleInsert, sqlx::FromRow, Clone, Serialize, Deserialize, ApiSchema)]
+pub struct WarrantyClaimsTableRecord {
+    pub ticket: i32,
+    pub warranty: i32,
+    #[defaultable]
+    #[api_schema(value_type = DateTime<Utc>)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A warranty along with whether it is still in effect and the claims made against it.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct WarrantyDetails {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub warranty: WarrantiesTableRecord,
+    pub active: bool,
+    /// The tickets opened to claim the warranty, oldest first.
+    pub claims: Vec<i32>,
+}
+
+/// A newly opened warranty claim, with the invoice created for its ticket.
+#[derive(Clone, Serialize, ApiSchema)]
+pub struct WarrantyClaimDetails {
+    #[serde(flatten)]
+    pub claim: WarrantyClaimsTableRecord,
+    pub invoice: i32,
+    pub items: Vec<InvoiceItemsTableRecord>,
+}
+
+/// How often the repairs of a type of service are claimed under warranty, and what the claims cost
+/// the shop.
+#[derive(sqlx::FromRow, Clone, Serialize, ApiSchema)]
+pub struct WarrantyReportRow {
+    pub service_type: i32,
+    pub type_name: String,
+    /// The number of repairs which started a warranty.
+    pub warranties: i32,
+    pub claims: i32,
+    /// The fraction of warranties which have been claimed, or [`None`] if none have been started.
+    pub claim_rate: Option<Decimal>,
+    /// What the covered items of the claims would have been charged at.
+    pub covered_value: Decimal,
+    /// The labor logged on the claims, which earns nothing.
+    pub labor_minutes: i32,
+}
+
+/// Why a warranty claim could not be opened.
+pub enum ClaimError {
+    WarrantyNotFound,
+    Expired(DateTime<Utc>),
+    ItemNotFound(i32),
+}
+
+const SELECT_DETAILS: &str =
+    "SELECT warranty.*, warranty.expires_at > CURRENT_TIMESTAMP AS active, \
+         ARRAY( \
+             SELECT ticket FROM main.warranty_claims \
+             WHERE warranty = warranty.id ORDER BY created_at, ticket \
+         ) AS claims \
+     FROM main.warranties warranty";
+
+impl WarrantyDetails {
+    /// Get the warranties of a device, of a customer, or of a device owned by a customer, newest
+    /// first.
+    pub async fn query_for(
+        database: &Database,
+        device: Option<i32>,
+        customer: Option<i32>,
+    ) -> Vec<Self> {
+        sqlx::query_as(&format!(
+            "{SELECT_DETAILS} WHERE ($1::integer IS NULL OR warranty.device = $1) \
+             AND ($2::integer IS NULL OR warranty.customer = $2) \
+             ORDER BY warranty.starts_at DESC, warranty.id DESC"
+        ))
+        .bind(device)
+        .bind(customer)
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
+
+impl WarrantyClaimDetails {
+    /// Open a ticket to repair a device again under one of its warranties.
+    ///
+    /// The ticket is given the device and service of the warranty, and an invoice with the item of
+    /// the service along with any extra items. The service and any products that were sold on the
+    /// original ticket are covered by the warranty and cost nothing, while other items are charged
+    /// as usual.
+    pub async fn open(
+        database: &Database,
+        warranty: i32,
+        extra_items: &[i32],
+        description: Option<&str>,
+    ) -> Result<Self, ClaimError> {
+        let mut transaction = database.connection.begin().await.unwrap();
+
+        let warranty: WarrantiesTableRecord =
+            sqlx::query_as("SELECT * FROM main.warranties WHERE id = $1 FOR SHARE")
+                .bind(warranty)
+                .fetch_optional(&mut *transaction)
+                .await
+                .unwrap()
+                .ok_or(ClaimError::WarrantyNotFound)?;
+        if warranty.expires_at <= Utc::now() {
+            return Err(ClaimError::Expired(warranty.expires_at));
+        }
+
+        let service_item: i32 = sqlx::query_scalar(
+            "SELECT id FROM main.items WHERE type = 'service' AND product_or_service = $1",
+        )
+        .bind(warranty.service)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        let mut items = vec![service_item];
+        let existing: Vec<i32> = sqlx::query_scalar("SELECT id FROM main.items WHERE id = ANY($1)")
+            .bind(extra_items)
+            .fetch_all(&mut *transaction)
+            .await
+            .unwrap();
+        for item in extra_items {
+            if !existing.contains(item) {
+                return Err(ClaimError::ItemNotFound(*item));
+            }
+            if !items.contains(item) {
+                items.push(*item);
+            }
+        }
+
+        // * The claim is handled by the store which did the original repair.
+        let invoice: i32 = sqlx::query_scalar(
+            "INSERT INTO main.invoices (location) \
+             SELECT location FROM main.tickets WHERE id = $1 \
+             RETURNING id",
+        )
+        .bind(warranty.ticket)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        let ticket: i32 = sqlx::query_scalar(
+            "INSERT INTO main.tickets (customer, invoice, description, location) \
+             SELECT $1, $2, $3, location FROM main.invoices WHERE id = $2 \
+             RETURNING id",
+        )
+        .bind(warranty.customer)
+        .bind(invoice)
+        .bind(
+            description
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("Warranty claim for ticket {}", warranty.ticket)),
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        sqlx::query(
+            "INSERT INTO main.ticket_devices (ticket, device, service) VALUES ($1, $2, $3)",
+        )
+        .bind(ticket)
+        .bind(warranty.device)
+        .bind(warranty.service)
+        .execute(&mut *transaction)
+        .await
+        .unwrap();
+
+        // * Products sold on the original ticket are the parts of the repair, so replacing them is
+        // * covered along with the labor of the service itself.
+        let items: Vec<InvoiceItemsTableRecord> = sqlx::query_as(
+            "INSERT INTO main.invoice_items (invoice, item, covered) \
+             SELECT $1, item.id, item.id = $2 OR ( \
+                 item.type = 'product' AND item.id IN ( \
+                     SELECT invoice_item.item \
+                     FROM main.tickets original \
+                         JOIN main.invoice_items invoice_item \
+                             ON original.invoice = invoice_item.invoice \
+                     WHERE original.id = $3 \
+                 ) \
+             ) \
+             FROM main.items item WHERE item.id = ANY($4) \
+             RETURNING *",
+        )
+        .bind(invoice)
+        .bind(service_item)
+        .bind(warranty.ticket)
+        .bind(&items)
+        .fetch_all(&mut *transaction)
+        .await
+        .unwrap();
+
+        let claim: WarrantyClaimsTableRecord = sqlx::query_as(
+            "INSERT INTO main.warranty_claims (ticket, warranty) VALUES ($1, $2) RETURNING *",
+        )
+        .bind(ticket)
+        .bind(warranty.id)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap();
+
+        transaction.commit().await.unwrap();
+        Ok(Self {
+            claim,
+            invoice,
+            items,
+        })
+    }
+}
+
+impl WarrantyReportRow {
+    /// Summarize the warranties and claims of every type of service. Warranty claims are counted
+    /// here rather than alongside the paid repairs in other reports.
+    pub async fn query_all(database: &Database) -> Vec<Self> {
+        sqlx::query_as(
+            "WITH warranty AS ( \
+                 SELECT warranty.id, service.type \
+                 FROM main.warranties warranty \
+                     JOIN main.services service ON warranty.service = service.id \
+             ), claim AS ( \
+                 SELECT claim.ticket, warranty.type \
+                 FROM main.warranty_claims claim JOIN warranty ON claim.warranty = warranty.id \
+             ) \
+             SELECT \
+                 service_type.id AS service_type, service_type.display_name AS type_name, \
+                 (SELECT COUNT(*) FROM warranty WHERE type = service_type.id)::integer AS warranties, \
+                 (SELECT COUNT(*) FROM claim WHERE type = service_type.id)::integer AS claims, \
+                 ROUND( \
+                     (SELECT COUNT(*) FROM claim WHERE type = service_type.id)::numeric \
+                         / NULLIF((SELECT COUNT(*) FROM warranty WHERE type = service_type.id), 0), \
+                     3 \
+                 ) AS claim_rate, \
+                 ( \
+                     SELECT COALESCE(SUM(invoice_item.unit_price), 0) \
+                     FROM claim \
+                         JOIN main.tickets ticket ON claim.ticket = ticket.id \
+                         JOIN main.invoice_items invoice_item \
+                             ON ticket.invoice = invoice_item.invoice \
+                     WHERE claim.type = service_type.id AND invoice_item.covered \
+                 ) AS covered_value, \
+                 ( \
+                     SELECT COALESCE( \
+                         ROUND(SUM(EXTRACT(EPOCH FROM entry.ended_at - entry.started_at)) / 60), 0 \
+                     ) \
+                     FROM claim JOIN main.labor_entries entry ON claim.ticket = entry.ticket \
+                     WHERE claim.type = service_type.id AND entry.ended_at IS NOT NULL \
+                 )::integer AS labor_minutes \
+             FROM main.service_types service_type \
+             ORDER BY service_type.id",
+        )
+        .fetch_all(&database.connection)
+        .await
+        .unwrap()
+    }
+}
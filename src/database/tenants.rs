@@ -0,0 +1,170 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::raw_sql;
+
+use super::Database;
+
+/// The longest a tenant's slug can be, which keeps it well within the limits of a subdomain label
+/// and of a PostgreSQL database name.
+const MAX_SLUG_LENGTH: usize = 32;
+/// The most connections each tenant's database is given, so that a server hosting many shops does
+/// not run PostgreSQL out of connections.
+const TENANT_MAX_CONNECTIONS: u32 = 4;
+
+/// A shop hosted by a multi-tenant server.
+///
+/// Every tenant has a database of its own on the same server as the configured database, with the
+/// usual `main` schema, so no query can reach the data of another tenant.
+#[derive(sqlx::FromRow, Clone)]
+pub struct TenantRecord {
+    /// The name the shop is known by in subdomains and the `X-Tenant` header.
+    pub slug: String,
+    pub database_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum TenantError {
+    /// The slug is not a lowercase subdomain label of at most [`MAX_SLUG_LENGTH`] characters.
+    InvalidSlug(String),
+    AlreadyExists(String),
+    NotFound(String),
+    /// A database with the name the tenant's database would have already exists, such as one left
+    /// behind by a tenant which was not removed cleanly.
+    DatabaseExists(String),
+}
+
+impl Display for TenantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSlug(slug) => write!(
+                f,
+                "`{slug}` is not a valid tenant name (use up to {MAX_SLUG_LENGTH} lowercase \
+                 letters, digits and hyphens, starting with a letter)"
+            ),
+            Self::AlreadyExists(slug) => write!(f, "tenant `{slug}` already exists"),
+            Self::NotFound(slug) => write!(f, "there is no tenant `{slug}`"),
+            Self::DatabaseExists(name) => write!(f, "database `{name}` already exists"),
+        }
+    }
+}
+
+/// Check whether a slug can name a tenant, which is whenever it can be used as a subdomain.
+pub fn is_valid_slug(slug: &str) -> bool {
+    slug.len() <= MAX_SLUG_LENGTH
+        && slug.starts_with(|c: char| c.is_ascii_lowercase())
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+impl Database {
+    const TENANCY_SCRIPT: &str = include_str!("../../database/tenancy.pgsql");
+
+    /// Create the registry of tenants in this database if it does not exist yet.
+    pub async fn prepare_tenant_registry(&self) {
+        raw_sql(Self::TENANCY_SCRIPT)
+            .execute(&self.connection)
+            .await
+            .unwrap();
+    }
+
+    /// Get every tenant, in the order they were created.
+    pub async fn query_tenants(&self) -> Vec<TenantRecord> {
+        sqlx::query_as("SELECT * FROM tenancy.tenants ORDER BY created_at, slug")
+            .fetch_all(&self.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Get a tenant by its slug, or [`None`] if there is no such tenant.
+    pub async fn query_tenant(&self, slug: &str) -> Option<TenantRecord> {
+        sqlx::query_as("SELECT * FROM tenancy.tenants WHERE slug = $1")
+            .bind(slug)
+            .fetch_optional(&self.connection)
+            .await
+            .unwrap()
+    }
+
+    /// Register a tenant and create its database, which is empty until it is migrated.
+    pub async fn create_tenant(&self, slug: &str) -> Result<TenantRecord, TenantError> {
+        if !is_valid_slug(slug) {
+            return Err(TenantError::InvalidSlug(slug.to_owned()));
+        }
+
+        // * Slugs cannot contain underscores, so replacing their hyphens keeps database names
+        // * unique while sparing them from needing to be quoted by hand.
+        let database_name = format!("fixwise_tenant_{}", slug.replace('-', "_"));
+        let tenant: TenantRecord = sqlx::query_as(
+            "INSERT INTO tenancy.tenants (slug, database_name) VALUES ($1, $2) \
+             ON CONFLICT DO NOTHING \
+             RETURNING *",
+        )
+        .bind(slug)
+        .bind(&database_name)
+        .fetch_optional(&self.connection)
+        .await
+        .unwrap()
+        .ok_or_else(|| TenantError::AlreadyExists(slug.to_owned()))?;
+
+        // * Databases cannot be created inside a transaction, so the tenant is unregistered again
+        // * by hand if its database cannot be created.
+        let created = raw_sql(&format!("CREATE DATABASE \"{database_name}\""))
+            .execute(&self.connection)
+            .await;
+        if let Err(e) = created {
+            self.unregister_tenant(slug).await;
+            return match e.as_database_error().and_then(|e| e.code()).as_deref() {
+                Some("42P04") => Err(TenantError::DatabaseExists(database_name)),
+                _ => panic!("could not create database `{database_name}`: {e}"),
+            };
+        }
+
+        Ok(tenant)
+    }
+
+    /// Unregister a tenant and drop its database along with all of its data, disconnecting anyone
+    /// still connected to it.
+    pub async fn delete_tenant(&self, slug: &str) -> Result<TenantRecord, TenantError> {
+        let tenant = self
+            .unregister_tenant(slug)
+            .await
+            .ok_or_else(|| TenantError::NotFound(slug.to_owned()))?;
+
+        raw_sql(&format!(
+            "DROP DATABASE IF EXISTS \"{}\" WITH (FORCE)",
+            tenant.database_name
+        ))
+        .execute(&self.connection)
+        .await
+        .unwrap();
+
+        Ok(tenant)
+    }
+
+    async fn unregister_tenant(&self, slug: &str) -> Option<TenantRecord> {
+        sqlx::query_as("DELETE FROM tenancy.tenants WHERE slug = $1 RETURNING *")
+            .bind(slug)
+            .fetch_optional(&self.connection)
+            .await
+            .unwrap()
+    }
+}
+
+impl TenantRecord {
+    /// Connect to the tenant's database, which is on the same server and uses the same
+    /// credentials as the database at `url`.
+    pub async fn connect(&self, url: &str) -> Result<Database, sqlx::Error> {
+        let options = PgConnectOptions::from_str(url)?.database(&self.database_name);
+        Ok(Database {
+            connection: PgPoolOptions::new()
+                .max_connections(TENANT_MAX_CONNECTIONS)
+                .connect_with(options)
+                .await?,
+        })
+    }
+}
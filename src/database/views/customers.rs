@@ -3,7 +3,11 @@ use serde::Serialize;
 use proc_macros::Relation;
 
 #[derive(Relation, Serialize)]
-#[relation(relation_name = "customers", primary_key = "id")]
+#[relation(
+    relation_name = "customers",
+    primary_key = "id",
+    location_filter = "id IN (SELECT customer FROM main.tickets WHERE location = $1)"
+)]
 pub struct CustomersView {
     records: Vec<CustomersViewRecord>,
 }
@@ -3,7 +3,11 @@ use serde::Serialize;
 use proc_macros::Relation;
 
 #[derive(Relation, Serialize)]
-#[relation(relation_name = "devices_view", primary_key = "id")]
+#[relation(
+    relation_name = "devices_view",
+    primary_key = "id",
+    location_filter = "id IN (SELECT id FROM main.devices WHERE location = $1)"
+)]
 pub struct DevicesView {
     records: Vec<DevicesViewRecord>,
 }
@@ -13,4 +17,6 @@ pub struct DevicesViewRecord {
     pub id: i32,
     pub model: String,
     pub owner: Option<String>,
+    /// The store holding the device, if any.
+    pub location: Option<String>,
 }
@@ -1,11 +1,15 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::Serialize;
 
 use proc_macros::Relation;
 
 #[derive(Relation, Serialize)]
-#[relation(relation_name = "invoices_view", primary_key = "id")]
+#[relation(
+    relation_name = "invoices_view",
+    primary_key = "id",
+    location_filter = "id IN (SELECT id FROM main.invoices WHERE location = $1)"
+)]
 pub struct InvoicesView {
     records: Vec<InvoicesViewRecord>,
 }
@@ -13,8 +17,9 @@ pub struct InvoicesView {
 #[derive(sqlx::FromRow, Serialize, Clone)]
 pub struct InvoicesViewRecord {
     pub id: i32,
-    pub created_at: NaiveDateTime,
-    pub updated_at: NaiveDateTime,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub invoice_total: Decimal,
     pub payment_total: Decimal,
+    pub location: String,
 }
@@ -4,7 +4,11 @@ use serde::Serialize;
 use proc_macros::Relation;
 
 #[derive(Relation, Serialize)]
-#[relation(relation_name = "parts_view", primary_key = "id")]
+#[relation(
+    relation_name = "parts_view",
+    primary_key = "id",
+    location_filter = "id IN (SELECT part FROM main.part_stock WHERE location = $1 AND quantity > 0)"
+)]
 pub struct PartsView {
     records: Vec<PartsViewRecord>,
 }
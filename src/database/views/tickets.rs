@@ -1,13 +1,18 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::Serialize;
 
 use proc_macros::Relation;
 
 use crate::database::shared_models::TicketStatus;
+use crate::database::Database;
 
 #[derive(Relation, Serialize)]
-#[relation(relation_name = "tickets_view", primary_key = "id")]
+#[relation(
+    relation_name = "tickets_view",
+    primary_key = "id",
+    location_filter = "id IN (SELECT id FROM main.tickets WHERE location = $1)"
+)]
 pub struct TicketsView {
     records: Vec<TicketsViewRecord>,
 }
@@ -17,7 +22,21 @@ pub struct TicketsViewRecord {
     pub id: i32,
     pub status: TicketStatus,
     pub customer: Option<String>,
+    pub assignee: Option<String>,
     pub balance: Decimal,
-    pub created_at: NaiveDateTime,
-    pub updated_at: NaiveDateTime,
+    pub estimated_completion: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub location: String,
+}
+
+impl TicketsViewRecord {
+    /// Get a ticket by its ID, or [`None`] if there is no such ticket.
+    pub async fn query_by_id(database: &Database, id: i32) -> Option<Self> {
+        sqlx::query_as("SELECT * FROM main.tickets_view WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&database.connection)
+            .await
+            .unwrap()
+    }
 }
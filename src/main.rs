@@ -1,15 +1,57 @@
 mod api;
+mod cli;
+mod config;
 mod database;
+mod photos;
 
+use std::path::Path;
 use std::sync::Arc;
 
-use axum::routing::{delete, get};
-use axum::Router;
+use axum::extract::DefaultBodyLimit;
+use axum::routing::{delete, get, post, put};
+use clap::Parser;
 use http::Method;
+use itertools::Itertools;
 use tokio::net::TcpListener;
 use tokio::signal;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 use tower_http::cors::{Any, CorsLayer};
 
+use api::endpoints::appointments::{
+    self, AppointmentIdParameter, AppointmentStatusRequest, AppointmentsParameters, Availability,
+    AvailabilityParameters, BookingRequest, CalendarLink, CalendarTokenParameter,
+    CheckInParameters, ServiceDurationParameters,
+};
+use api::endpoints::assignments::{
+    self, AssignmentRequest, QueueParameters, TechnicianQueue, TicketParameter,
+};
+use api::endpoints::cash_drawer::{
+    self, CashMovementRequest, CloseSessionRequest, DrawerParameter, OpenSessionRequest,
+    SessionParameter, SessionsParameter,
+};
+use api::endpoints::conditions::{
+    self, ConditionDeviceParameters, ConditionRequest, ConditionTicketParameters,
+    DeviceConditionReport, PhotoIdParameters, PhotoParameters, PhotoUploadParameters,
+};
+use api::endpoints::invoice_items::{
+    self, InvoiceItemParameters, InvoiceItemRequest, PriceOverrideRequest,
+};
+use api::endpoints::labor::{
+    self, LaborEntryRequest, LaborReportParameters, LaborTimerRequest, OwnedEntryParameters,
+    ServiceLaborReport, TicketLabor,
+};
+use api::endpoints::locations::{
+    self, LocationRequest, ReceiveTransferParameters, StockParameters, StockRequest,
+    TransferRequest, TransfersParameters,
+};
+use api::endpoints::portal::{
+    self, EstimatedCompletionRequest, PortalLink, PortalSigner, PortalStatus, PortalTokenParameter,
+    StaffTicketParameters,
+};
+use api::endpoints::prices::{
+    self, NewPriceRequest, PriceAdjustmentRequest, PriceItemParameter, ScheduledPriceParameters,
+};
 use api::endpoints::processed::customers::CustomersResource;
 use api::endpoints::processed::device_models::DeviceModelsResource;
 use api::endpoints::processed::devices::DevicesResource;
@@ -19,84 +61,1002 @@ use api::endpoints::processed::products::ProductsResource;
 use api::endpoints::processed::services::ServicesResource;
 use api::endpoints::processed::tickets::TicketsResource;
 use api::endpoints::processed::vendors::VendorsResource;
-use api::endpoints::utils::imei_check::ImeiInfoApiUtil;
+use api::endpoints::profit::{self, ProfitReportParameters};
+use api::endpoints::quotes::{
+    self, ConvertQuoteParameters, QuoteDecisionRequest, QuoteIdParameter, QuoteRequest,
+    QuotesParameters,
+};
+use api::endpoints::saved_views::{
+    self, OwnedViewParameters, RoleDefaultDeleteParameters, RoleDefaultParameters, SavedViewList,
+    SavedViewRequest, UserViewsParameters,
+};
+use api::endpoints::sessions::{self, LoginRequest, Session, SessionUser};
+use api::endpoints::utils::imei_check::{ImeiInfoApiUtil, ImeiParameter};
+use api::endpoints::warranties::{
+    self, WarrantyClaimRequest, WarrantyIdParameter, WarrantyLookupParameters,
+    WarrantyPeriodParameters,
+};
+use api::events::{self, EventsParameters};
+use api::localization::{
+    find_currency, find_locale, supported_currencies, supported_locales, Localization,
+};
+use api::openapi::DocumentedRouter;
+use api::tenancy::Tenants;
 use api::{GenericIdParameter, ServeRecordJson, ServeResourceJson};
-use database::tables::invoices::InvoicesTable;
+use cli::{Cli, Command, TenantCommand};
+use config::Config;
+use database::events::ChangeEvent;
+use database::generation::GenerationProfile;
+use database::shared_models::UserRole;
+use database::tables::appointments::AppointmentDetails;
+use database::tables::business_hours::BusinessHoursTableRecord;
+use database::tables::device_conditions::{
+    DeviceConditionsJunctionTableRecord, DevicePhotosTableRecord,
+};
+use database::tables::invoice_items::InvoiceItemsTableRecord;
+use database::tables::invoices::{InvoicesTable, ProfitReportRow};
+use database::tables::items::{AdjustmentPreview, PriceChange, PriceHistory, PriceHistoryEntry};
+use database::tables::labor_entries::LaborEntriesTableRecord;
+use database::tables::locations::{
+    LocationsTableRecord, PartStockJunctionTableRecord, PartStockLevel,
+};
+use database::tables::quotes::QuoteDetails;
+use database::tables::register_sessions::{
+    CashMovementsTableRecord, RegisterSessionsTableRecord, ZReport,
+};
+use database::tables::saved_views::SavedViewsTableRecord;
+use database::tables::service_types::ServiceTypesTableRecord;
+use database::tables::services::ServicesTableRecord;
+use database::tables::ticket_assignments::TicketAssignmentsTableRecord;
 use database::tables::tickets::TicketsTable;
+use database::tables::transfers::TransfersTableRecord;
+use database::tables::users::UsersTableRecord;
+use database::tables::warranties::{WarrantyClaimDetails, WarrantyDetails, WarrantyReportRow};
 use database::views::invoices::InvoicesView;
 use database::views::items::ItemsView;
 use database::views::products::ProductsView;
 use database::views::services::ServicesView;
 use database::views::tickets::TicketsView;
 use database::views::vendors::VendorsView;
-use database::{Database, Relation, Table};
+use database::{Database, Relation, Table, SCHEMA_VERSION};
+use photos::PhotoStore;
+
+/// How many change events are kept for `/events` streams that fall behind before they miss some.
+const EVENT_BUFFER_SIZE: usize = 1024;
 
 #[derive(Clone)]
 struct ServerState {
     database: Database,
     imei_info_api_key: String,
+    localization: Localization,
+    portal_signer: PortalSigner,
+    /// What customers are told to do to collect their devices, if the shop has set it.
+    pickup_instructions: Option<String>,
+    /// Every change announced by the database, which each `/events` stream subscribes to.
+    events: broadcast::Sender<ChangeEvent>,
+    /// Where the photos of the shop's devices are kept.
+    photos: PhotoStore,
+}
+
+/// The settings shared by every shop a server hosts, from which the state of each is made.
+struct ShopSettings {
+    imei_info_api_key: String,
+    localization: Localization,
+    portal_secret: String,
+    pickup_instructions: Option<String>,
+    photos: PhotoStore,
+}
+
+impl ShopSettings {
+    /// Make the state of a shop served from `database`, along with the task relaying its change
+    /// events.
+    fn serve(
+        &self,
+        database: Database,
+        tenant: Option<&str>,
+    ) -> (Arc<ServerState>, JoinHandle<()>) {
+        // * Each tenant signs its portal links with a secret of its own, so a link to a ticket of
+        // * one shop cannot be used to see the ticket with the same ID at another.
+        // * Their photos are kept apart in the same way.
+        let (portal_signer, photos) = match tenant {
+            Some(tenant) => (
+                PortalSigner::new(&format!("{}/{tenant}", self.portal_secret)),
+                self.photos.for_tenant(tenant),
+            ),
+            None => (PortalSigner::new(&self.portal_secret), self.photos.clone()),
+        };
+
+        let (events, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        let relay_database = database.clone();
+        let relay_events = events.clone();
+        let relay =
+            tokio::spawn(async move { relay_database.relay_change_events(relay_events).await });
+
+        let state = Arc::new(ServerState {
+            database,
+            imei_info_api_key: self.imei_info_api_key.clone(),
+            localization: self.localization,
+            portal_signer,
+            pickup_instructions: self.pickup_instructions.clone(),
+            events,
+            photos,
+        });
+        (state, relay)
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    dotenvy::dotenv().unwrap();
+    let cli = Cli::parse();
+    let mut config = Config::from_env();
+    config.tenant = cli.tenant;
 
-    println!("Connecting to database...");
-    let server_state = Arc::new(ServerState {
-        database: Database::connect_and_configure().await,
-        imei_info_api_key: std::env::var("IMEI_INFO_API_KEY").unwrap(),
-    });
+    match cli.command {
+        Command::Serve => serve(config).await,
+        Command::Seed {
+            profile,
+            scale,
+            seed,
+        } => {
+            let profile = GenerationProfile::from(profile).scaled(scale as usize);
+            seed_database(config, profile, seed).await
+        }
+        Command::Migrate => migrate(config).await,
+        Command::CreateUser {
+            username,
+            display_name,
+            role,
+        } => create_user(config, username, display_name, role).await,
+        Command::CheckConfig => check_config(config).await,
+        Command::Backup { path } => backup(config, &path).await,
+        Command::Restore { path } => restore(config, &path).await,
+        Command::Tenant { command } => manage_tenants(config, command).await,
+    }
+}
 
-    let signal_handler_server_state = server_state.clone();
-    tokio::spawn(async move {
-        signal::ctrl_c().await.unwrap();
-        println!();
-        println!("Server shutting down...");
-        signal_handler_server_state
-            .database
-            .close_connection()
-            .await;
-        println!("Database connection closed.");
-        std::process::exit(0);
-    });
+async fn serve(config: Config) {
+    let Some(imei_info_api_key) = config.imei_info_api_key.clone() else {
+        eprintln!("IMEI_INFO_API_KEY must be set to serve the API.");
+        std::process::exit(1);
+    };
 
-    server_state.database.add_generated_items().await;
+    let portal_secret = match &config.portal_secret {
+        Some(secret) if secret.len() >= portal::MIN_SECRET_LENGTH => secret.clone(),
+        _ => {
+            eprintln!(
+                "PORTAL_SECRET must be set to at least {} characters to serve the API.",
+                portal::MIN_SECRET_LENGTH
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let Some(localization) = config.localization() else {
+        eprintln!(
+            "LOCALE, CURRENCY or TIMEZONE is not supported. Run `fixwise check-config` for details."
+        );
+        std::process::exit(1);
+    };
+
+    let photos = match config.photo_store() {
+        Ok(photos) => photos,
+        Err(e) => {
+            eprintln!("{e}.");
+            std::process::exit(1);
+        }
+    };
+    println!("Keeping photos in {photos}");
+
+    let settings = ShopSettings {
+        imei_info_api_key,
+        localization,
+        portal_secret,
+        pickup_instructions: config.pickup_instructions.clone(),
+        photos,
+    };
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(Any)
         .allow_origin(Any);
 
-    let routes = Router::new()
-        .route("/customers", get(CustomersResource::serve_all))
-        .route("/device_models", get(DeviceModelsResource::serve_all))
-        .route("/devices", get(DevicesResource::serve_all))
-        .route("/parts", get(PartsResource::serve_all))
-        .route("/tickets", get(TicketsResource::serve_all))
-        .route("/invoices", get(InvoicesResource::serve_all))
-        .route("/vendors", get(VendorsResource::serve_all))
-        .route("/products", get(ProductsResource::serve_all))
-        .route("/services", get(ServicesResource::serve_all))
-        .route("/imei_check", get(ImeiInfoApiUtil::serve_one))
-        .route("/raw/items", get(ItemsView::query_all_handler))
-        .route("/raw/tickets", get(TicketsView::query_all_handler))
-        .route("/raw/invoices", get(InvoicesView::query_all_handler))
-        .route("/raw/vendors", get(VendorsView::query_all_handler))
-        .route("/raw/products", get(ProductsView::query_all_handler))
-        .route("/raw/services", get(ServicesView::query_all_handler))
-        .route(
+    let routes = DocumentedRouter::new()
+        .resource::<CustomersResource>("/customers", get(CustomersResource::serve_all))
+        .resource::<DeviceModelsResource>("/device_models", get(DeviceModelsResource::serve_all))
+        .resource::<DevicesResource>("/devices", get(DevicesResource::serve_all))
+        .resource::<PartsResource>("/parts", get(PartsResource::serve_all))
+        .resource::<TicketsResource>("/tickets", get(TicketsResource::serve_all))
+        .resource::<InvoicesResource>("/invoices", get(InvoicesResource::serve_all))
+        .resource::<VendorsResource>("/vendors", get(VendorsResource::serve_all))
+        .resource::<ProductsResource>("/products", get(ProductsResource::serve_all))
+        .resource::<ServicesResource>("/services", get(ServicesResource::serve_all))
+        .record::<ImeiInfoApiUtil, ImeiParameter>("/imei_check", get(ImeiInfoApiUtil::serve_one))
+        .raw("/raw/items", get(ItemsView::query_all_handler))
+        .raw("/raw/tickets", get(TicketsView::query_all_handler))
+        .raw("/raw/invoices", get(InvoicesView::query_all_handler))
+        .raw("/raw/vendors", get(VendorsView::query_all_handler))
+        .raw("/raw/products", get(ProductsView::query_all_handler))
+        .raw("/raw/services", get(ServicesView::query_all_handler))
+        .delete::<GenericIdParameter>(
             "/raw/invoices/delete",
             delete(InvoicesTable::delete_one_handler::<GenericIdParameter>),
         )
-        .route(
+        .delete::<GenericIdParameter>(
             "/raw/tickets/delete",
             delete(TicketsTable::delete_one_handler::<GenericIdParameter>),
         )
-        .layer(cors)
-        .with_state(server_state);
+        .json::<UserViewsParameters, (), SavedViewList>(
+            "/views",
+            "get",
+            "Get a user's saved views of a resource and the default view for their role",
+            get(saved_views::list_views),
+        )
+        .json::<(), SavedViewRequest, SavedViewsTableRecord>(
+            "/views",
+            "post",
+            "Save a new view for a user",
+            post(saved_views::create_view),
+        )
+        .json::<OwnedViewParameters, SavedViewRequest, SavedViewsTableRecord>(
+            "/views",
+            "put",
+            "Replace one of a user's saved views",
+            put(saved_views::update_view),
+        )
+        .json::<OwnedViewParameters, (), bool>(
+            "/views",
+            "delete",
+            "Delete one of a user's saved views",
+            delete(saved_views::delete_view),
+        )
+        .json::<RoleDefaultParameters, SavedViewRequest, SavedViewsTableRecord>(
+            "/views/role_default",
+            "put",
+            "Set the default view of a resource for a role (admins only)",
+            put(saved_views::set_role_default),
+        )
+        .json::<RoleDefaultDeleteParameters, (), bool>(
+            "/views/role_default",
+            "delete",
+            "Remove the default view of a resource for a role (admins only)",
+            delete(saved_views::delete_role_default),
+        )
+        .json::<(), AssignmentRequest, TicketAssignmentsTableRecord>(
+            "/tickets/assignments",
+            "put",
+            "Assign a ticket or one of its devices to a technician",
+            put(assignments::assign),
+        )
+        .json::<TicketParameter, (), Vec<TicketAssignmentsTableRecord>>(
+            "/tickets/assignments",
+            "get",
+            "Get the assignment history of a ticket",
+            get(assignments::list_assignments),
+        )
+        .json::<QueueParameters, (), Vec<TechnicianQueue>>(
+            "/queue",
+            "get",
+            "Get the open work assigned to each technician, oldest first",
+            get(assignments::serve_queue),
+        )
+        .json::<TicketParameter, (), TicketLabor>(
+            "/labor",
+            "get",
+            "Get the labor logged against a ticket, with totals for each device",
+            get(labor::ticket_labor),
+        )
+        .json::<(), LaborEntryRequest, LaborEntriesTableRecord>(
+            "/labor",
+            "post",
+            "Record time a technician spent on a device of a ticket",
+            post(labor::create_entry),
+        )
+        .json::<OwnedEntryParameters, (), bool>(
+            "/labor",
+            "delete",
+            "Delete one of a technician's labor entries",
+            delete(labor::delete_entry),
+        )
+        .json::<(), LaborTimerRequest, LaborEntriesTableRecord>(
+            "/labor/timer",
+            "post",
+            "Start timing a technician's work on a device of a ticket",
+            post(labor::start_timer),
+        )
+        .json::<(), (), LaborEntriesTableRecord>(
+            "/labor/timer/stop",
+            "post",
+            "Stop the timer a technician has running",
+            post(labor::stop_timer),
+        )
+        .json::<LaborReportParameters, (), Vec<ServiceLaborReport>>(
+            "/reports/labor",
+            "get",
+            "Compare the labor logged against each service to its labor fee",
+            get(labor::labor_report),
+        )
+        .json::<AppointmentsParameters, (), Vec<AppointmentDetails>>(
+            "/appointments",
+            "get",
+            "List the appointments overlapping a span of time",
+            get(appointments::list_appointments),
+        )
+        .json::<(), BookingRequest, AppointmentDetails>(
+            "/appointments",
+            "post",
+            "Book an appointment for a customer",
+            post(appointments::book_appointment),
+        )
+        .json::<AppointmentIdParameter, AppointmentStatusRequest, AppointmentDetails>(
+            "/appointments/status",
+            "put",
+            "Cancel a booked appointment or mark it as a no-show",
+            put(appointments::set_status),
+        )
+        .json::<CheckInParameters, (), AppointmentDetails>(
+            "/appointments/check_in",
+            "post",
+            "Check in the customer of an appointment, creating a ticket",
+            post(appointments::check_in),
+        )
+        .json::<AvailabilityParameters, (), Availability>(
+            "/appointments/availability",
+            "get",
+            "Get the times an appointment for a set of service types could start on a day",
+            get(appointments::availability),
+        )
+        .json::<(), (), CalendarLink>(
+            "/appointments/calendar_link",
+            "get",
+            "Get the link of the signed-in user's appointment calendar feed",
+            get(appointments::calendar_link),
+        )
+        .json::<(), (), Vec<BusinessHoursTableRecord>>(
+            "/business_hours",
+            "get",
+            "Get the shop's opening hours for each day of the week",
+            get(appointments::business_hours),
+        )
+        .json::<(), Vec<BusinessHoursTableRecord>, Vec<BusinessHoursTableRecord>>(
+            "/business_hours",
+            "put",
+            "Replace the shop's opening hours (admins only)",
+            put(appointments::set_business_hours),
+        )
+        .json::<ServiceDurationParameters, (), ServiceTypesTableRecord>(
+            "/service_types/duration",
+            "put",
+            "Change how long appointments for a type of service take (admins only)",
+            put(appointments::set_service_duration),
+        )
+        .json::<WarrantyLookupParameters, (), Vec<WarrantyDetails>>(
+            "/warranties",
+            "get",
+            "Look up the warranties of a device or a customer",
+            get(warranties::lookup),
+        )
+        .json::<WarrantyIdParameter, WarrantyClaimRequest, WarrantyClaimDetails>(
+            "/warranties/claim",
+            "post",
+            "Open a ticket claiming a warranty, with the covered items of its invoice free",
+            post(warranties::open_claim),
+        )
+        .json::<(), (), Vec<WarrantyReportRow>>(
+            "/reports/warranties",
+            "get",
+            "Summarize warranties and warranty claims by type of service",
+            get(warranties::warranty_report),
+        )
+        .json::<WarrantyPeriodParameters, (), ServiceTypesTableRecord>(
+            "/service_types/warranty",
+            "put",
+            "Change how many days repairs of a type of service are guaranteed for (admins only)",
+            put(warranties::set_service_type_warranty),
+        )
+        .json::<WarrantyPeriodParameters, (), ServicesTableRecord>(
+            "/services/warranty",
+            "put",
+            "Override the warranty period of a service, or clear it to use its type's (admins only)",
+            put(warranties::set_service_warranty),
+        )
+        .json::<(), InvoiceItemRequest, InvoiceItemsTableRecord>(
+            "/invoices/items",
+            "post",
+            "Add an item to an invoice at its current price, or at a price set by hand",
+            post(invoice_items::add_item),
+        )
+        .json::<InvoiceItemParameters, PriceOverrideRequest, InvoiceItemsTableRecord>(
+            "/invoices/items/price",
+            "put",
+            "Set the price of an invoice item by hand, or clear it (admins and front desk only)",
+            put(invoice_items::set_price_override),
+        )
+        .json::<PriceItemParameter, (), PriceHistory>(
+            "/prices",
+            "get",
+            "Get the price history of an item, including scheduled prices and product margins",
+            get(prices::price_history),
+        )
+        .json::<(), NewPriceRequest, PriceHistoryEntry>(
+            "/prices",
+            "post",
+            "Change the price of an item now or in the future (admins only)",
+            post(prices::schedule_price),
+        )
+        .json::<ScheduledPriceParameters, (), bool>(
+            "/prices",
+            "delete",
+            "Cancel a price change which has yet to take effect (admins only)",
+            delete(prices::cancel_price),
+        )
+        .json::<(), PriceAdjustmentRequest, AdjustmentPreview>(
+            "/prices/adjust/preview",
+            "post",
+            "Preview changing prices by a percentage and its effect on open quotes and invoices",
+            post(prices::preview_adjustment),
+        )
+        .json::<(), PriceAdjustmentRequest, Vec<PriceChange>>(
+            "/prices/adjust",
+            "post",
+            "Change the prices of matching items by a percentage (admins only)",
+            post(prices::adjust_prices),
+        )
+        .json::<ProfitReportParameters, (), Vec<ProfitReportRow>>(
+            "/reports/profit",
+            "get",
+            "Report revenue, cost of goods, gross margin and labor by period or another grouping",
+            get(profit::profit_report),
+        )
+        .document::<ProfitReportParameters>(
+            "/reports/profit.csv",
+            "text/csv",
+            "Export the profit report as CSV",
+            get(profit::profit_report_csv),
+        )
+        .json::<SessionsParameter, (), Vec<RegisterSessionsTableRecord>>(
+            "/drawer/sessions",
+            "get",
+            "List every session of the cash drawers, newest first, optionally only one store's",
+            get(cash_drawer::list_sessions),
+        )
+        .json::<DrawerParameter, OpenSessionRequest, RegisterSessionsTableRecord>(
+            "/drawer/sessions",
+            "post",
+            "Open the cash drawer with a float",
+            post(cash_drawer::open_session),
+        )
+        .json::<DrawerParameter, CloseSessionRequest, ZReport>(
+            "/drawer/sessions/close",
+            "post",
+            "Close the cash drawer with the cash counted in it, getting its Z-report",
+            post(cash_drawer::close_session),
+        )
+        .json::<DrawerParameter, CashMovementRequest, CashMovementsTableRecord>(
+            "/drawer/movements",
+            "post",
+            "Record cash paid into or out of the open drawer",
+            post(cash_drawer::record_movement),
+        )
+        .json::<SessionParameter, (), ZReport>(
+            "/drawer/report",
+            "get",
+            "Get the Z-report of a session, or the takings so far of the open session",
+            get(cash_drawer::session_report),
+        )
+        .json::<(), (), Vec<LocationsTableRecord>>(
+            "/locations",
+            "get",
+            "List every store",
+            get(locations::list_locations),
+        )
+        .json::<(), LocationRequest, LocationsTableRecord>(
+            "/locations",
+            "post",
+            "Add a store (admins only)",
+            post(locations::create_location),
+        )
+        .json::<StockParameters, (), Vec<PartStockLevel>>(
+            "/stock",
+            "get",
+            "Get the stock of parts at each store, along with what is on its way",
+            get(locations::list_stock),
+        )
+        .json::<(), StockRequest, PartStockJunctionTableRecord>(
+            "/stock",
+            "put",
+            "Set how many of a part a store has on hand",
+            put(locations::set_stock),
+        )
+        .json::<TransfersParameters, (), Vec<TransfersTableRecord>>(
+            "/transfers",
+            "get",
+            "List transfers between stores, newest first",
+            get(locations::list_transfers),
+        )
+        .json::<(), TransferRequest, TransfersTableRecord>(
+            "/transfers",
+            "post",
+            "Send parts or a device from one store to another",
+            post(locations::send_transfer),
+        )
+        .json::<ReceiveTransferParameters, (), TransfersTableRecord>(
+            "/transfers/receive",
+            "post",
+            "Receive a transfer at the store it was sent to",
+            post(locations::receive_transfer),
+        )
+        .json::<QuotesParameters, (), Vec<QuoteDetails>>(
+            "/quotes",
+            "get",
+            "List quotes, newest first, optionally only those of a customer",
+            get(quotes::list_quotes),
+        )
+        .json::<(), QuoteRequest, QuoteDetails>(
+            "/quotes",
+            "post",
+            "Quote a repair for a customer at current prices",
+            post(quotes::create_quote),
+        )
+        .json::<QuoteIdParameter, (), QuoteDetails>(
+            "/quotes/quote",
+            "get",
+            "Get a quote by its ID",
+            get(quotes::get_quote),
+        )
+        .json::<QuoteIdParameter, QuoteDecisionRequest, QuoteDetails>(
+            "/quotes/decision",
+            "put",
+            "Record whether the customer approved or declined a quote",
+            put(quotes::decide_quote),
+        )
+        .json::<ConvertQuoteParameters, (), QuoteDetails>(
+            "/quotes/convert",
+            "post",
+            "Turn a quote into a ticket and an invoice at its quoted prices",
+            post(quotes::convert_quote),
+        )
+        .json::<StaffTicketParameters, (), PortalLink>(
+            "/tickets/portal_link",
+            "get",
+            "Get the link to a ticket's status portal, to send to its customer",
+            get(portal::portal_link),
+        )
+        .json::<StaffTicketParameters, EstimatedCompletionRequest, EstimatedCompletionRequest>(
+            "/tickets/estimated_completion",
+            "put",
+            "Change when the shop expects to finish a ticket",
+            put(portal::set_estimated_completion),
+        )
+        .json::<ConditionTicketParameters, (), Vec<DeviceConditionReport>>(
+            "/tickets/condition",
+            "get",
+            "Get the condition each device of a ticket was checked in with, and its photos",
+            get(conditions::condition_report),
+        )
+        .json::<ConditionDeviceParameters, ConditionRequest, DeviceConditionsJunctionTableRecord>(
+            "/tickets/condition",
+            "put",
+            "Record the condition checklist of a device of a ticket",
+            put(conditions::record_condition),
+        )
+        .upload::<PhotoUploadParameters, DevicePhotosTableRecord>(
+            "/tickets/condition/photos",
+            conditions::PHOTO_CONTENT_TYPES,
+            "Add a photo of a device of a ticket",
+            post(conditions::upload_photo).layer(DefaultBodyLimit::max(conditions::MAX_PHOTO_SIZE)),
+        )
+        .document::<PhotoParameters>(
+            "/tickets/condition/photos",
+            "image/*",
+            "Get a photo of a device, or its thumbnail",
+            get(conditions::photo),
+        )
+        .json::<PhotoIdParameters, (), bool>(
+            "/tickets/condition/photos",
+            "delete",
+            "Delete a photo of a device",
+            delete(conditions::delete_photo),
+        )
+        .document::<ConditionTicketParameters>(
+            "/tickets/receipt",
+            "text/html",
+            "Show a printable check-in receipt with the condition and photos of a ticket's devices",
+            get(conditions::receipt),
+        )
+        .json::<(), (), SessionUser>(
+            "/session",
+            "get",
+            "Get the user signed in with the session of the request",
+            get(sessions::current_user),
+        )
+        .json::<(), (), bool>(
+            "/logout",
+            "post",
+            "Sign out, ending the session of the request",
+            post(sessions::logout),
+        )
+        .public(|router| {
+            router
+                .json::<(), LoginRequest, Session>(
+                    "/login",
+                    "post",
+                    "Sign in with a username and password, starting a session",
+                    post(sessions::login),
+                )
+                .json::<PortalTokenParameter, (), PortalStatus>(
+                    "/portal/status",
+                    "get",
+                    "Get what a customer can see about their ticket, given its portal token",
+                    get(portal::portal_status),
+                )
+                .document::<PortalTokenParameter>(
+                    "/portal",
+                    "text/html",
+                    "Show a customer the status of their ticket, given its portal token",
+                    get(portal::portal_page),
+                )
+                .document::<CalendarTokenParameter>(
+                    "/appointments/calendar.ics",
+                    "text/calendar",
+                    "Get upcoming and recent appointments as an iCalendar feed, given its token",
+                    get(appointments::calendar),
+                )
+        })
+        .event_stream::<EventsParameters, ChangeEvent>(
+            "/events",
+            "Stream changes to tickets, payments and stock levels as server-sent events",
+            get(events::serve_events),
+        )
+        .finish();
 
-    let listener = TcpListener::bind("0.0.0.0:8080").await.unwrap();
+    // * A multi-tenant server checks the registry of tenants in the configured database for each
+    // * request, while a single shop is served straight from its database.
+    let (database, routes) = match (&config.tenant_domain, &config.tenant) {
+        (Some(domain), None) => {
+            let registry = connect(&config).await;
+            registry.prepare_tenant_registry().await;
+            println!("Serving every tenant at subdomains of {domain}");
+            let tenants = Tenants::new(
+                registry.clone(),
+                config.database_url.clone(),
+                domain.clone(),
+                config.allow_tenant_header,
+                settings,
+                routes,
+            );
+            (registry, tenants.into_router())
+        }
+        _ => {
+            let database = connect(&config).await;
+            require_current_schema(&database).await;
+            let (server_state, _) = settings.serve(database.clone(), config.tenant.as_deref());
+            (database, routes.with_state(server_state))
+        }
+    };
+
+    tokio::spawn(async move {
+        signal::ctrl_c().await.unwrap();
+        println!();
+        println!("Server shutting down...");
+        database.close_connection().await;
+        println!("Database connection closed.");
+        std::process::exit(0);
+    });
+
+    let routes = routes.layer(cors);
+    let listener = TcpListener::bind(&config.listen_address).await.unwrap();
     println!("Listening on {}", listener.local_addr().unwrap());
     axum::serve(listener, routes.into_make_service())
         .await
         .unwrap();
 }
+
+async fn seed_database(config: Config, profile: GenerationProfile, seed: Option<u64>) {
+    let seed = seed.unwrap_or_else(rand::random);
+    let database = connect(&config).await;
+    println!("Resetting database...");
+    database.reset().await;
+    println!("Generating synthetic data with seed {seed}");
+    database.add_generated_items(profile, seed).await;
+    database.close_connection().await;
+}
+
+async fn migrate(config: Config) {
+    let database = connect(&config).await;
+    let applied = database.migrate().await;
+    match applied.is_empty() {
+        true => println!("Database schema is already up to date (version {SCHEMA_VERSION})."),
+        false => {
+            for version in applied {
+                println!("Applied migration {version}");
+            }
+            println!("Database schema is now at version {SCHEMA_VERSION}.");
+        }
+    }
+
+    database.close_connection().await;
+}
+
+async fn create_user(
+    config: Config,
+    username: String,
+    display_name: Option<String>,
+    role: UserRole,
+) {
+    let password = rpassword::prompt_password("Password: ").unwrap();
+    if password.is_empty() {
+        eprintln!("Password cannot be empty.");
+        std::process::exit(2);
+    }
+
+    if rpassword::prompt_password("Confirm password: ").unwrap() != password {
+        eprintln!("Passwords do not match.");
+        std::process::exit(2);
+    }
+
+    let database = connect(&config).await;
+    require_current_schema(&database).await;
+
+    let display_name = display_name.unwrap_or_else(|| username.clone());
+    match UsersTableRecord::create(&database, &username, &display_name, &password, role).await {
+        Ok(id) => println!("Created user `{username}` with ID {id}"),
+        Err(e) => {
+            eprintln!("Could not create user `{username}`: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    database.close_connection().await;
+}
+
+async fn check_config(config: Config) {
+    let mut ok = true;
+
+    println!("Database URL: {}", config.redacted_database_url());
+    println!("Listen address: {}", config.listen_address);
+    if config
+        .listen_address
+        .parse::<std::net::SocketAddr>()
+        .is_err()
+    {
+        println!("  error: not a valid socket address");
+        ok = false;
+    }
+
+    match config.imei_info_api_key {
+        Some(_) => println!("IMEI info API key: set"),
+        None => {
+            println!("IMEI info API key: not set (required to serve the API)");
+            ok = false;
+        }
+    }
+
+    match &config.portal_secret {
+        Some(secret) if secret.len() >= portal::MIN_SECRET_LENGTH => {
+            println!("Portal secret: set")
+        }
+        Some(_) => {
+            println!(
+                "Portal secret: too short (must be at least {} characters)",
+                portal::MIN_SECRET_LENGTH
+            );
+            ok = false;
+        }
+        None => {
+            println!("Portal secret: not set (required to serve the API)");
+            ok = false;
+        }
+    }
+
+    match config.photo_store() {
+        Ok(photos) => println!("Photo storage: {photos}"),
+        Err(e) => {
+            println!("Photo storage: not usable");
+            println!("  error: {e}");
+            ok = false;
+        }
+    }
+
+    match &config.tenant_domain {
+        Some(domain) => {
+            println!("Tenant domain: {domain} (serving a shop at each subdomain)");
+            if config.allow_tenant_header {
+                println!("Tenant header: accepted (for development only)");
+            }
+        }
+        None => println!("Tenant domain: not set (serving a single shop)"),
+    }
+
+    println!("Locale: {}", config.locale);
+    if find_locale(&config.locale).is_none() {
+        println!(
+            "  error: not supported (expected one of {})",
+            supported_locales().join(", ")
+        );
+        ok = false;
+    }
+
+    println!("Currency: {}", config.currency);
+    if find_currency(&config.currency).is_none() {
+        println!(
+            "  error: not supported (expected one of {})",
+            supported_currencies().join(", ")
+        );
+        ok = false;
+    }
+
+    println!("Timezone: {}", config.timezone);
+    if config.timezone.parse::<chrono_tz::Tz>().is_err() {
+        println!("  error: not an IANA timezone name, like `America/New_York`");
+        ok = false;
+    }
+
+    match Database::connect(&config.database_url).await {
+        Ok(database) => {
+            let schema_version = database.schema_version().await;
+            println!("Database: connected, schema version {schema_version} of {SCHEMA_VERSION}");
+            if schema_version != SCHEMA_VERSION {
+                println!("  error: run `fixwise migrate` to update the schema");
+                ok = false;
+            }
+            database.close_connection().await;
+        }
+        Err(e) => {
+            println!("Database: could not connect: {e}");
+            ok = false;
+        }
+    }
+
+    if !ok {
+        std::process::exit(1);
+    }
+}
+
+async fn backup(config: Config, path: &Path) {
+    let database = connect(&config).await;
+    match database.backup(path).await {
+        Ok(manifest) => println!(
+            "Backed up {} records from {} tables to {}",
+            manifest
+                .tables
+                .iter()
+                .map(|t| t.record_count)
+                .sum::<usize>(),
+            manifest.tables.len(),
+            path.display(),
+        ),
+        Err(e) => {
+            eprintln!("Backup failed: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    database.close_connection().await;
+}
+
+async fn restore(config: Config, path: &Path) {
+    let database = connect(&config).await;
+    println!("Restoring {}...", path.display());
+    match database.restore(path).await {
+        Ok(manifest) => {
+            for table in &manifest.tables {
+                println!(
+                    "Restored {} records into {}",
+                    table.record_count, table.relation_name
+                );
+            }
+            println!(
+                "Restored {} records from backup created at {}",
+                manifest
+                    .tables
+                    .iter()
+                    .map(|t| t.record_count)
+                    .sum::<usize>(),
+                manifest.created_at,
+            );
+        }
+        Err(e) => {
+            eprintln!("Restore failed: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    database.close_connection().await;
+}
+
+async fn manage_tenants(config: Config, command: TenantCommand) {
+    let registry = connect_configured(&config).await;
+    registry.prepare_tenant_registry().await;
+
+    match command {
+        TenantCommand::List => {
+            let tenants = registry.query_tenants().await;
+            if tenants.is_empty() {
+                println!("There are no tenants.");
+            }
+            for tenant in tenants {
+                println!(
+                    "{} (database `{}`, created {})",
+                    tenant.slug,
+                    tenant.database_name,
+                    tenant.created_at.format("%Y-%m-%d %H:%M UTC")
+                );
+            }
+        }
+        TenantCommand::Create { slug } => match registry.create_tenant(&slug).await {
+            Ok(tenant) => {
+                let database = tenant.connect(&config.database_url).await.unwrap();
+                database.migrate().await;
+                database.close_connection().await;
+                println!(
+                    "Created tenant `{slug}` with database `{}` at schema version \
+                     {SCHEMA_VERSION}.",
+                    tenant.database_name
+                );
+                println!("Run `fixwise --tenant {slug} create-user` to add its first user.");
+            }
+            Err(e) => {
+                eprintln!("Could not create tenant: {e}");
+                std::process::exit(1);
+            }
+        },
+        TenantCommand::Delete { slug } => match registry.delete_tenant(&slug).await {
+            Ok(tenant) => println!(
+                "Deleted tenant `{slug}` and dropped database `{}`.",
+                tenant.database_name
+            ),
+            Err(e) => {
+                eprintln!("Could not delete tenant: {e}");
+                std::process::exit(1);
+            }
+        },
+    }
+
+    registry.close_connection().await;
+}
+
+/// Connect to the database commands act on, which is that of the tenant given with `--tenant` if
+/// there is one, and the configured database otherwise.
+async fn connect(config: &Config) -> Database {
+    let database = connect_configured(config).await;
+    let Some(slug) = &config.tenant else {
+        return database;
+    };
+
+    database.prepare_tenant_registry().await;
+    let tenant = database.query_tenant(slug).await;
+    database.close_connection().await;
+    let Some(tenant) = tenant else {
+        eprintln!("There is no tenant `{slug}`. Run `fixwise tenant create {slug}` first.");
+        std::process::exit(1);
+    };
+
+    match tenant.connect(&config.database_url).await {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("Could not connect to the database of tenant `{slug}`: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn connect_configured(config: &Config) -> Database {
+    println!("Connecting to database...");
+    match Database::connect(&config.database_url).await {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("Could not connect to database: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn require_current_schema(database: &Database) {
+    let schema_version = database.schema_version().await;
+    if schema_version != SCHEMA_VERSION {
+        eprintln!(
+            "Database schema is at version {schema_version}, but version {SCHEMA_VERSION} is required. Run `fixwise migrate` first."
+        );
+        std::process::exit(1);
+    }
+}